@@ -22,6 +22,7 @@ use buck2_build_api::analysis::AnalysisResult;
 use buck2_build_api::keep_going;
 use buck2_core::configuration::compatibility::MaybeCompatible;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
+use buck2_core::soft_error;
 use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
 use buck2_data::ToProtoMessage;
 use buck2_error::Context;
@@ -65,6 +66,10 @@ enum AnalysisCalculationError {
     ProfileForwardTarget(ConfiguredTargetLabel),
 }
 
+/// How many times we retry the analysis of a target when that fails with a retryable (e.g. infra)
+/// error. Other errors are never retried.
+const MAX_ANALYSIS_RETRIES: u32 = 2;
+
 struct RuleAnalysisCalculationInstance;
 
 #[derive(
@@ -100,9 +105,44 @@ impl RuleAnalsysisCalculationImpl for RuleAnalysisCalculationInstance {
                 _cancellation: &CancellationContext,
             ) -> Self::Value {
                 let profile_mode = ctx.get_profile_mode_for_intermediate_analysis().await?;
-                Ok(get_analysis_result(ctx, &self.0, &profile_mode)
+                // Retried here rather than by callers: DICE reuses the error for everyone else
+                // requesting this key in the same transaction, so this is the only place where
+                // retrying actually runs the analysis again.
+                let mut activation_data = None;
+                let mut retries = 0;
+                let res = loop {
+                    let err = match get_analysis_result(
+                        ctx,
+                        &self.0,
+                        &profile_mode,
+                        &mut activation_data,
+                    )
                     .await
-                    .with_context(|| format!("Error running analysis for `{}`", &self.0))?)
+                    {
+                        Ok(v) => break Ok(v),
+                        Err(e) => buck2_error::Error::from(e),
+                    };
+
+                    if retries >= MAX_ANALYSIS_RETRIES || !err.is_retryable() {
+                        break Err(err.context(format!("Error running analysis for `{}`", &self.0)));
+                    }
+
+                    retries += 1;
+                    let _ignored = soft_error!(
+                        "build_analysis_retry",
+                        anyhow::Error::from(err).context(format!(
+                            "Retrying analysis of `{}` (attempt {})",
+                            &self.0, retries
+                        )),
+                        quiet: true
+                    );
+                };
+
+                // DICE only takes this once per computation, so it covers every attempt.
+                if let Some(activation_data) = activation_data {
+                    ctx.store_evaluation_data(activation_data)?;
+                }
+                res
             }
 
             fn equality(_: &Self::Value, _: &Self::Value) -> bool {
@@ -223,10 +263,13 @@ pub async fn get_rule_impl(
     Ok(get_user_defined_rule_impl(module.env().dupe(), func))
 }
 
+/// Analyze `target`. If the rule is evaluated, how long that took and its spans are added to
+/// `activation_data`, which the caller is responsible for storing.
 async fn get_analysis_result(
     ctx: &DiceComputations,
     target: &ConfiguredTargetLabel,
     profile_mode: &StarlarkProfileModeOrInstrumentation,
+    activation_data: &mut Option<AnalysisKeyActivationData>,
 ) -> anyhow::Result<MaybeCompatible<AnalysisResult>> {
     let configured_node: MaybeCompatible<ConfiguredTargetNode> =
         ctx.get_configured_target_node(target).await?;
@@ -315,10 +358,12 @@ async fn get_analysis_result(
     })
     .await;
 
-    ctx.store_evaluation_data(AnalysisKeyActivationData {
-        duration: now.elapsed(),
-        spans,
-    })?;
+    let activation_data = activation_data.get_or_insert_with(|| AnalysisKeyActivationData {
+        duration: Duration::ZERO,
+        spans: SmallVec::new(),
+    });
+    activation_data.duration += now.elapsed();
+    activation_data.spans.extend(spans);
 
     res
 }
@@ -353,15 +398,18 @@ pub async fn profile_analysis(
         None => target_node.label(),
         Some(forward) => forward.label(),
     };
-    get_analysis_result(
+    let mut activation_data = None;
+    let res = get_analysis_result(
         ctx,
         target,
         &StarlarkProfileModeOrInstrumentation::Profile(profile_mode.dupe()),
+        &mut activation_data,
     )
-    .await?
-    .require_compatible()?
-    .profile_data
-    .with_context(|| {
+    .await;
+    if let Some(activation_data) = activation_data {
+        ctx.store_evaluation_data(activation_data)?;
+    }
+    res?.require_compatible()?.profile_data.with_context(|| {
         format!(
             "profile_data not set after finished profiling analysis for `{}` (internal error)",
             target
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::sync::Arc;
 use std::time::Instant;
 
 use async_trait::async_trait;
@@ -20,11 +21,46 @@ use buck2_events::dispatch::span_async;
 use buck2_execute::artifact::artifact_dyn::ArtifactDyn;
 use buck2_execute::materialize::materializer::HasMaterializer;
 use dice::DiceComputations;
+use dice::UserComputationData;
 use dupe::Dupe;
+use dupe::OptionDupedExt;
 
 use crate::actions::artifact::get_artifact_fs::GetArtifactFs;
 use crate::build_signals::HasBuildSignals;
 
+/// Replaces the materializer when materializing the artifacts requested by a build, e.g. to record
+/// them in tests, or to write them somewhere other than buck-out.
+#[async_trait]
+pub trait MaterializerOverride: Send + Sync + 'static {
+    /// Materialize `artifact`, which resolves to `path`. If `required` is false, this is a
+    /// best-effort request, which may be ignored.
+    async fn materialize_requested_artifact(
+        &self,
+        artifact: &BuildArtifact,
+        path: ProjectRelativePathBuf,
+        required: bool,
+    ) -> anyhow::Result<()>;
+}
+
+pub trait HasMaterializerOverride {
+    fn set_materializer_override(&mut self, materializer: Arc<dyn MaterializerOverride>);
+
+    fn get_materializer_override(&self) -> Option<Arc<dyn MaterializerOverride>>;
+}
+
+impl HasMaterializerOverride for UserComputationData {
+    fn set_materializer_override(&mut self, materializer: Arc<dyn MaterializerOverride>) {
+        self.data.set(materializer);
+    }
+
+    fn get_materializer_override(&self) -> Option<Arc<dyn MaterializerOverride>> {
+        self.data
+            .get::<Arc<dyn MaterializerOverride>>()
+            .ok()
+            .duped()
+    }
+}
+
 #[async_trait]
 pub trait ArtifactMaterializer {
     async fn materialize(&self, artifact: &Artifact) -> anyhow::Result<ProjectRelativePathBuf>;
@@ -53,7 +89,7 @@ impl ArtifactMaterializer for DiceComputations {
         artifact: &BuildArtifact,
         required: bool,
     ) -> anyhow::Result<()> {
-        let materializer = self.per_transaction_data().get_materializer();
+        let materializer_override = self.per_transaction_data().get_materializer_override();
         let artifact_fs = self.get_artifact_fs().await?;
         let path = artifact_fs.resolve_build(artifact.get_path());
 
@@ -65,10 +101,17 @@ impl ArtifactMaterializer for DiceComputations {
             let now = Instant::now();
 
             let result: anyhow::Result<_> = try {
-                if required {
-                    materializer.ensure_materialized(vec![path]).await?;
+                if let Some(materializer) = materializer_override {
+                    materializer
+                        .materialize_requested_artifact(artifact, path, required)
+                        .await?;
                 } else {
-                    materializer.try_materialize_final_artifact(path).await?;
+                    let materializer = self.per_transaction_data().get_materializer();
+                    if required {
+                        materializer.ensure_materialized(vec![path]).await?;
+                    } else {
+                        materializer.try_materialize_final_artifact(path).await?;
+                    }
                 }
             };
 
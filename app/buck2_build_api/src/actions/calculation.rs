@@ -280,14 +280,18 @@ async fn build_action_no_redirect(
     let ((res, wall_time), spans) =
         async_record_root_spans(span_async(start_event, fut.boxed())).await;
 
+    let end = Instant::now();
+
     // TODO: This wall time is rather wrong. We should report a wall time on failures too.
     ctx.store_evaluation_data(BuildKeyActivationData {
         action: action.dupe(),
         duration: NodeDuration {
             user: wall_time.unwrap_or_default(),
-            total: now.elapsed(),
+            total: end - now,
         },
         spans,
+        start: now,
+        end,
     })?;
 
     res
@@ -359,6 +363,10 @@ pub struct BuildKeyActivationData {
     pub action: Arc<RegisteredAction>,
     pub duration: NodeDuration,
     pub spans: SmallVec<[SpanId; 1]>,
+    /// When the action started and finished executing (this is the interval `duration.total`
+    /// covers).
+    pub start: Instant,
+    pub end: Instant,
 }
 
 /// The cost of these calls are particularly critical. To control the cost (particularly size) of these calls
@@ -68,10 +68,12 @@ impl std::error::Error for ActionError {
             request,
             category,
             typ,
+            None, /* custom typ */
             &[],
             std::file!(),
             Some("ActionError"),
             Some(self.as_proto_event()),
+            None, /* remediation */
         );
     }
 
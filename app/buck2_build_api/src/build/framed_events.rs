@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Compact length-prefixed protobuf framing of `ConfiguredBuildEvent`s, for consumers reading
+//! build events over a pipe (where NDJSON would be needlessly verbose).
+
+use futures::Stream;
+use futures::StreamExt;
+use prost::Message;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::build::ConfiguredBuildEvent;
+use crate::build::ConfiguredBuildEventVariant;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    prost::Enumeration
+)]
+#[repr(i32)]
+pub enum FramedBuildEventStatus {
+    Prepared = 0,
+    SkippedIncompatible = 1,
+    OutputSuccess = 2,
+    OutputFailure = 3,
+    GraphSize = 4,
+    Error = 5,
+    SkippedByFilter = 6,
+    MaterializationProgress = 7,
+    ResolvedOutput = 8,
+}
+
+/// The subset of a `ConfiguredBuildEvent` which is written to the pipe.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FramedBuildEvent {
+    #[prost(string, tag = "1")]
+    pub label: String,
+    /// Only set for outputs.
+    #[prost(uint64, optional, tag = "2")]
+    pub index: Option<u64>,
+    #[prost(enumeration = "FramedBuildEventStatus", tag = "3")]
+    pub status: i32,
+}
+
+impl FramedBuildEvent {
+    fn from_event(event: &ConfiguredBuildEvent) -> Self {
+        let (status, index) = match &event.variant {
+            ConfiguredBuildEventVariant::SkippedIncompatible => {
+                (FramedBuildEventStatus::SkippedIncompatible, None)
+            }
+            ConfiguredBuildEventVariant::SkippedByFilter => {
+                (FramedBuildEventStatus::SkippedByFilter, None)
+            }
+            ConfiguredBuildEventVariant::Prepared { .. } => {
+                (FramedBuildEventStatus::Prepared, None)
+            }
+            ConfiguredBuildEventVariant::Output { output, index } => {
+                let status = match output {
+                    Ok(_) => FramedBuildEventStatus::OutputSuccess,
+                    Err(_) => FramedBuildEventStatus::OutputFailure,
+                };
+                (status, Some(*index as u64))
+            }
+            ConfiguredBuildEventVariant::ResolvedOutput { index, .. } => {
+                (FramedBuildEventStatus::ResolvedOutput, Some(*index as u64))
+            }
+            ConfiguredBuildEventVariant::MaterializationProgress { .. } => {
+                (FramedBuildEventStatus::MaterializationProgress, None)
+            }
+            ConfiguredBuildEventVariant::GraphSize { .. } => {
+                (FramedBuildEventStatus::GraphSize, None)
+            }
+            ConfiguredBuildEventVariant::Error { .. } => (FramedBuildEventStatus::Error, None),
+        };
+
+        Self {
+            label: event.label.to_string(),
+            index,
+            status: status as i32,
+        }
+    }
+}
+
+/// Write each event in `events` to `writer` as a length-delimited `FramedBuildEvent`.
+pub async fn frame_events(
+    events: impl Stream<Item = ConfiguredBuildEvent>,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> anyhow::Result<()> {
+    let mut events = std::pin::pin!(events);
+    let mut buf = Vec::new();
+    while let Some(event) = events.next().await {
+        buf.clear();
+        FramedBuildEvent::from_event(&event).encode_length_delimited(&mut buf)?;
+        writer.write_all(&buf).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Decode the output of `frame_events`.
+pub fn decode_framed_events(mut data: &[u8]) -> anyhow::Result<Vec<FramedBuildEvent>> {
+    let mut events = Vec::new();
+    while !data.is_empty() {
+        events.push(FramedBuildEvent::decode_length_delimited(&mut data)?);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::provider::label::ConfiguredProvidersLabel;
+    use buck2_core::target::label::TargetLabel;
+    use dupe::Dupe;
+
+    use super::*;
+
+    fn event(
+        label: &Arc<ConfiguredProvidersLabel>,
+        variant: ConfiguredBuildEventVariant,
+    ) -> ConfiguredBuildEvent {
+        ConfiguredBuildEvent {
+            label: label.dupe(),
+            variant,
+            request_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() -> anyhow::Result<()> {
+        let label = Arc::new(ConfiguredProvidersLabel::default_for(
+            TargetLabel::testing_parse("cell//pkg:foo").configure(ConfigurationData::testing_new()),
+        ));
+        let events = vec![
+            event(
+                &label,
+                ConfiguredBuildEventVariant::Prepared {
+                    run_args: None,
+                    target_rule_type_name: "foo_binary".to_owned(),
+                    output_count: 1,
+                    analysis_duration: Duration::from_millis(5),
+                },
+            ),
+            event(
+                &label,
+                ConfiguredBuildEventVariant::Output {
+                    output: Err(anyhow::anyhow!("failed").into()),
+                    index: 3,
+                },
+            ),
+            event(
+                &label,
+                ConfiguredBuildEventVariant::Error {
+                    err: anyhow::anyhow!("error").into(),
+                },
+            ),
+            event(&label, ConfiguredBuildEventVariant::SkippedIncompatible),
+        ];
+
+        let mut buf = Vec::new();
+        frame_events(futures::stream::iter(events), &mut buf).await?;
+
+        let label = label.to_string();
+        let framed = |index, status: FramedBuildEventStatus| FramedBuildEvent {
+            label: label.clone(),
+            index,
+            status: status as i32,
+        };
+        assert_eq!(
+            vec![
+                framed(None, FramedBuildEventStatus::Prepared),
+                framed(Some(3), FramedBuildEventStatus::OutputFailure),
+                framed(None, FramedBuildEventStatus::Error),
+                framed(None, FramedBuildEventStatus::SkippedIncompatible),
+            ],
+            decode_framed_events(&buf)?
+        );
+
+        Ok(())
+    }
+}
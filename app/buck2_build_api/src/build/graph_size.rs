@@ -65,6 +65,83 @@ impl Key for GraphSizeKey {
     }
 }
 
+/// The graph size of a target, counted up to a maximum depth.
+#[derive(Clone, Copy, Dupe, Debug, Eq, PartialEq, Allocative)]
+pub struct BoundedGraphSize {
+    pub size: u64,
+    /// Whether there were nodes beyond the maximum depth which were not counted.
+    pub truncated: bool,
+}
+
+#[derive(
+    Clone,
+    Dupe,
+    derive_more::Display,
+    Debug,
+    Eq,
+    Hash,
+    PartialEq,
+    Allocative
+)]
+#[display(fmt = "{} (max_depth = {})", label, max_depth)]
+struct BoundedGraphSizeKey {
+    label: ConfiguredTargetLabel,
+    max_depth: usize,
+}
+
+#[async_trait]
+impl Key for BoundedGraphSizeKey {
+    type Value = buck2_error::Result<MaybeCompatible<BoundedGraphSize>>;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellation: &CancellationContext,
+    ) -> Self::Value {
+        let configured_node = ctx.get_configured_target_node(&self.label).await?;
+
+        Ok(configured_node.map(|node| {
+            let mut visited = HashSet::new();
+            visited.insert(&node);
+            let mut frontier = vec![&node];
+            let mut truncated = false;
+
+            for depth in 0.. {
+                let mut next = Vec::new();
+                for item in frontier {
+                    for dep in item.deps() {
+                        if visited.contains(dep) {
+                            continue;
+                        }
+                        if depth == self.max_depth {
+                            truncated = true;
+                        } else {
+                            visited.insert(dep);
+                            next.push(dep);
+                        }
+                    }
+                }
+                if next.is_empty() {
+                    break;
+                }
+                frontier = next;
+            }
+
+            BoundedGraphSize {
+                size: visited.len() as _,
+                truncated,
+            }
+        }))
+    }
+
+    fn equality(a: &Self::Value, b: &Self::Value) -> bool {
+        match (a, b) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 /// Returns the total graph size for all dependencies of a target.
 pub async fn get_configured_graph_size(
     ctx: &DiceComputations,
@@ -72,3 +149,18 @@ pub async fn get_configured_graph_size(
 ) -> anyhow::Result<MaybeCompatible<u64>> {
     Ok(ctx.compute(&GraphSizeKey(key.dupe())).await??)
 }
+
+/// Like `get_configured_graph_size`, but only counts nodes up to `max_depth` hops from the target,
+/// which is much cheaper on huge graphs.
+pub async fn get_configured_graph_size_bounded(
+    ctx: &DiceComputations,
+    key: &ConfiguredTargetLabel,
+    max_depth: usize,
+) -> anyhow::Result<MaybeCompatible<BoundedGraphSize>> {
+    Ok(ctx
+        .compute(&BoundedGraphSizeKey {
+            label: key.dupe(),
+            max_depth,
+        })
+        .await??)
+}
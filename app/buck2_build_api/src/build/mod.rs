@@ -8,21 +8,38 @@
  */
 
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fmt::Formatter;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::mem;
 use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
 
 use allocative::Allocative;
 use anyhow::Context;
+use buck2_artifact::actions::key::ActionKey;
+use buck2_artifact::artifact::artifact_type::Artifact;
 use buck2_artifact::artifact::artifact_type::BaseArtifactKind;
 use buck2_artifact::artifact::build_artifact::BuildArtifact;
 use buck2_cli_proto::build_request::Materializations;
+use buck2_core::configuration::compatibility::IncompatiblePlatformReason;
 use buck2_core::configuration::compatibility::MaybeCompatible;
 use buck2_core::execution_types::executor_config::PathSeparatorKind;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_core::provider::label::ProvidersLabel;
+use buck2_core::provider::label::ProvidersName;
+use buck2_core::soft_error;
+use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+use buck2_error::Category;
 use buck2_events::dispatch::console_message;
+use buck2_events::errors::create_error_report;
 use buck2_execute::artifact::fs::ExecutorFs;
 use buck2_node::nodes::configured_frontend::ConfiguredTargetNodeCalculation;
 use dashmap::mapref::entry::Entry;
@@ -38,6 +55,8 @@ use futures::stream::StreamExt;
 use futures::FutureExt;
 use itertools::Itertools;
 use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
 
 use crate::actions::artifact::get_artifact_fs::GetArtifactFs;
 use crate::actions::artifact::materializer::ArtifactMaterializer;
@@ -45,6 +64,8 @@ use crate::analysis::calculation::RuleAnalysisCalculation;
 use crate::artifact_groups::calculation::ArtifactGroupCalculation;
 use crate::artifact_groups::ArtifactGroup;
 use crate::artifact_groups::ArtifactGroupValues;
+use crate::build::graph_size::BoundedGraphSize;
+use crate::build::path_filter::PathFilter;
 use crate::build_signals::HasBuildSignals;
 use crate::interpreter::rule_defs::cmd_args::AbsCommandLineContext;
 use crate::interpreter::rule_defs::cmd_args::CommandLineArgLike;
@@ -52,10 +73,19 @@ use crate::interpreter::rule_defs::cmd_args::SimpleCommandLineArtifactVisitor;
 use crate::interpreter::rule_defs::provider::builtin::run_info::FrozenRunInfo;
 use crate::interpreter::rule_defs::provider::test_provider::TestProvider;
 
-mod graph_size;
+pub mod framed_events;
+pub mod graph_size;
+pub mod path_filter;
+
+#[derive(Debug, buck2_error::Error)]
+enum BuildError {
+    #[error("No provider categories requested for `{0}`")]
+    #[buck2(user)]
+    NoProvidersRequested(ConfiguredProvidersLabel),
+}
 
 /// The types of provider to build on the configured providers label
-#[derive(Debug, Clone, Dupe, Allocative)]
+#[derive(Debug, Clone, Dupe, Allocative, Eq, PartialEq, Ord, PartialOrd)]
 pub enum BuildProviderType {
     Default,
     DefaultOther,
@@ -63,42 +93,347 @@ pub enum BuildProviderType {
     Test,
 }
 
+impl BuildProviderType {
+    fn to_proto(&self) -> buck2_data::BuildProviderType {
+        match self {
+            BuildProviderType::Default => buck2_data::BuildProviderType::BuildProviderDefault,
+            BuildProviderType::DefaultOther => {
+                buck2_data::BuildProviderType::BuildProviderDefaultOther
+            }
+            BuildProviderType::Run => buck2_data::BuildProviderType::BuildProviderRun,
+            BuildProviderType::Test => buck2_data::BuildProviderType::BuildProviderTest,
+        }
+    }
+}
+
+/// The outcome of building a target that was attempted (skipped targets have no result at all).
+#[derive(Clone, Copy, Dupe, Debug, PartialEq, Eq, Allocative)]
+pub enum TargetBuildStatus {
+    /// Everything built, and there was at least one output.
+    Built,
+    /// Nothing failed, but the target has no outputs (e.g. its rule didn't define any).
+    BuiltNoOutputs,
+    /// Nothing failed, and the target's outputs were resolved but not built (with
+    /// `BuildConfiguredLabelOptions::resolve_only`).
+    Resolved,
+    /// At least one output, or something else about the target, failed.
+    Failed,
+}
+
+impl TargetBuildStatus {
+    fn compute<T, E, R>(
+        outputs: &[Result<T, E>],
+        errors: &[buck2_error::Error],
+        resolved_outputs: &[R],
+    ) -> Self {
+        if !errors.is_empty() || outputs.iter().any(|output| output.is_err()) {
+            TargetBuildStatus::Failed
+        } else if outputs.is_empty() && !resolved_outputs.is_empty() {
+            TargetBuildStatus::Resolved
+        } else if outputs.is_empty() {
+            TargetBuildStatus::BuiltNoOutputs
+        } else {
+            TargetBuildStatus::Built
+        }
+    }
+}
+
 #[derive(Clone, Debug, Allocative)]
 pub struct ConfiguredBuildTargetResultGen<T> {
     pub outputs: Vec<T>,
     pub run_args: Option<Vec<String>>,
     pub target_rule_type_name: Option<String>,
     pub configured_graph_size: Option<buck2_error::Result<MaybeCompatible<u64>>>,
+    /// Whether `configured_graph_size` was only counted up to a maximum depth, and so is a lower
+    /// bound rather than the exact size.
+    pub configured_graph_size_truncated: bool,
+    /// The provider types which produced at least one successfully built output. A type that was
+    /// requested can be missing here, e.g. `Run` when the target has no `RunInfo`.
+    pub built_provider_types: BTreeSet<BuildProviderType>,
+    /// How long resolving the target's providers took, i.e. mostly analysis. Not set if the
+    /// target failed before that completed.
+    pub analysis_duration: Option<Duration>,
+    /// The sum of the time spent materializing each successfully built output. Zero if
+    /// materializations were skipped.
+    pub materialization_duration: Duration,
+    /// Computed from `outputs` and `errors` once all events were collected.
+    pub status: TargetBuildStatus,
     pub errors: Vec<buck2_error::Error>,
+    /// With `BuildConfiguredLabelOptions::resolve_only`, the outputs that would have been built,
+    /// in the order `outputs` would have had. Nothing was built, so `outputs` is empty.
+    pub resolved_outputs: Vec<(ArtifactGroup, BuildProviderType)>,
 }
 
 pub type ConfiguredBuildTargetResult =
     ConfiguredBuildTargetResultGen<buck2_error::Result<ProviderArtifacts>>;
 
+impl ConfiguredBuildTargetResult {
+    /// The sum of the durations of all successful outputs. Outputs without a duration are
+    /// ignored, so if only some outputs were timed this is the total of those. Returns `None` if
+    /// no durations were recorded at all.
+    pub fn total_duration(&self) -> Option<Duration> {
+        self.outputs
+            .iter()
+            .filter_map(|output| output.as_ref().ok()?.duration)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Whether any output or anything else about this target failed.
+    pub fn is_failure(&self) -> bool {
+        !self.errors.is_empty() || self.outputs.iter().any(|output| output.is_err())
+    }
+
+    /// Whether the target built successfully: there are no errors, and every output was built.
+    pub fn is_success(&self) -> bool {
+        !self.is_failure()
+    }
+
+    /// Combine `other`, a result for the same label, into this one, as if the label had been built
+    /// once with everything requested for either (e.g. different provider categories):
+    ///
+    /// * The successful outputs of `other` that this result doesn't have already (building the
+    ///   same label twice produces the same outputs) are added, and all outputs are sorted by
+    ///   provider type, which is the order a single build produces them in. Failed outputs don't
+    ///   have a known provider type, so they come last.
+    /// * `built_provider_types` and `materialization_duration` are recomputed from those outputs,
+    ///   so they are the union and sum of both sides, without counting shared outputs twice.
+    /// * `errors` are appended, and `resolved_outputs` too (without duplicates, and sorted by
+    ///   provider type as well).
+    /// * `configured_graph_size` is taken from `other` if this result doesn't have one, or only
+    ///   has a lower bound where `other` has the exact size.
+    /// * Other fields (e.g. `run_args`) are only taken from `other` if this result lacks them.
+    fn merge(&mut self, other: ConfiguredBuildTargetResult) {
+        let ConfiguredBuildTargetResultGen {
+            outputs,
+            run_args,
+            target_rule_type_name,
+            configured_graph_size,
+            configured_graph_size_truncated,
+            built_provider_types: _,
+            analysis_duration,
+            materialization_duration: _,
+            status: _,
+            errors,
+            resolved_outputs,
+        } = other;
+
+        for output in outputs {
+            let is_duplicate = output.as_ref().is_ok_and(|output| {
+                self.outputs.iter().any(|existing| {
+                    existing
+                        .as_ref()
+                        .is_ok_and(|existing| existing.is_same_output(output))
+                })
+            });
+            if !is_duplicate {
+                self.outputs.push(output);
+            }
+        }
+        // Stable, so that outputs of the same type stay in the order they were built in.
+        self.outputs.sort_by_key(|output| match output {
+            Ok(output) => (false, Some(output.provider_type.dupe())),
+            Err(_) => (true, None),
+        });
+        self.built_provider_types = self
+            .outputs
+            .iter()
+            .filter_map(|output| Some(output.as_ref().ok()?.provider_type.dupe()))
+            .collect();
+        self.materialization_duration = self
+            .outputs
+            .iter()
+            .filter_map(|output| Some(output.as_ref().ok()?.materialization_duration))
+            .sum();
+
+        self.errors.extend(errors);
+        for resolved_output in resolved_outputs {
+            if !self.resolved_outputs.contains(&resolved_output) {
+                self.resolved_outputs.push(resolved_output);
+            }
+        }
+        self.resolved_outputs
+            .sort_by_key(|(_output, provider_type)| provider_type.dupe());
+
+        let other_graph_size_is_better = match (&self.configured_graph_size, &configured_graph_size)
+        {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(_), Some(_)) => {
+                self.configured_graph_size_truncated && !configured_graph_size_truncated
+            }
+        };
+        if other_graph_size_is_better {
+            self.configured_graph_size = configured_graph_size;
+            self.configured_graph_size_truncated = configured_graph_size_truncated;
+        }
+
+        self.run_args = self.run_args.take().or(run_args);
+        self.target_rule_type_name = self.target_rule_type_name.take().or(target_rule_type_name);
+        self.analysis_duration = self.analysis_duration.or(analysis_duration);
+        self.status =
+            TargetBuildStatus::compute(&self.outputs, &self.errors, &self.resolved_outputs);
+    }
+
+    /// The first error of the target, looking at `errors` before the outputs which failed.
+    pub fn first_error(&self) -> Option<&buck2_error::Error> {
+        self.errors
+            .first()
+            .or_else(|| self.outputs.iter().find_map(|output| output.as_ref().err()))
+    }
+
+    /// The successful outputs of the default outputs of `DefaultInfo`. This excludes its other
+    /// outputs (`BuildProviderType::DefaultOther`).
+    pub fn default_outputs(&self) -> impl Iterator<Item = &ArtifactGroupValues> {
+        self.outputs_of_type(BuildProviderType::Default)
+    }
+
+    /// The successful outputs of `RunInfo`.
+    pub fn run_outputs(&self) -> impl Iterator<Item = &ArtifactGroupValues> {
+        self.outputs_of_type(BuildProviderType::Run)
+    }
+
+    /// The successful outputs of `ExternalRunnerTestInfo`.
+    pub fn test_outputs(&self) -> impl Iterator<Item = &ArtifactGroupValues> {
+        self.outputs_of_type(BuildProviderType::Test)
+    }
+
+    fn outputs_of_type(
+        &self,
+        provider_type: BuildProviderType,
+    ) -> impl Iterator<Item = &ArtifactGroupValues> {
+        self.outputs.iter().filter_map(move |output| {
+            let output = output.as_ref().ok()?;
+            (output.provider_type == provider_type).then_some(&output.values)
+        })
+    }
+}
+
+/// How many targets of a `BuildTargetResult` had each outcome.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BuildStatusCounts {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Targets skipped because they are incompatible.
+    pub skipped: usize,
+}
+
+/// An error that could not be associated with a specific configured target, along with when it
+/// was received.
+#[derive(Debug, Clone)]
+pub struct OtherError {
+    pub error: buck2_error::Error,
+    /// How long after `collect_stream` started this error arrived. This distinguishes e.g.
+    /// failures during setup from failures late in the build.
+    pub received_after: Duration,
+}
+
 pub struct BuildTargetResult {
     pub configured: BTreeMap<ConfiguredProvidersLabel, Option<ConfiguredBuildTargetResult>>,
     /// Errors that could not be associated with a specific configured target. These errors may be
     /// associated with a providers label, or might not be associated with any target at all.
-    pub other_errors: BTreeMap<Option<ProvidersLabel>, Vec<buck2_error::Error>>,
+    pub other_errors: BTreeMap<Option<ProvidersLabel>, Vec<OtherError>>,
+}
+
+/// When `BuildTargetResult::collect_stream_with_policy` stops collecting because of errors. Only
+/// errors associated with a configured target (including failed outputs) count.
+#[derive(Clone, Copy, Dupe, Debug, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Stop at the first error.
+    FailFast,
+    /// Stop once this many errors were collected, across all labels.
+    FailAfter(usize),
+    /// Collect everything.
+    Never,
+}
+
+impl FailurePolicy {
+    pub fn from_fail_fast(fail_fast: bool) -> Self {
+        if fail_fast {
+            FailurePolicy::FailFast
+        } else {
+            FailurePolicy::Never
+        }
+    }
+
+    fn should_stop(self, error_count: usize) -> bool {
+        match self {
+            FailurePolicy::FailFast => error_count > 0,
+            FailurePolicy::FailAfter(max) => error_count >= max,
+            FailurePolicy::Never => false,
+        }
+    }
 }
 
 impl BuildTargetResult {
     pub async fn collect_stream(
-        mut stream: impl Stream<Item = BuildEvent> + Unpin,
+        stream: impl Stream<Item = BuildEvent> + Unpin,
         fail_fast: bool,
+    ) -> anyhow::Result<Self> {
+        Self::collect_stream_with_policy(stream, FailurePolicy::from_fail_fast(fail_fast), false)
+            .await
+    }
+
+    /// Like `collect_stream`, with more control. If `dedupe_other_errors` is set, errors of
+    /// `other_errors` which have the same message and category as an earlier error for the same
+    /// label (e.g. the failure to load a package shared by many targets) are dropped.
+    pub async fn collect_stream_with_policy(
+        stream: impl Stream<Item = BuildEvent> + Unpin,
+        failure_policy: FailurePolicy,
+        dedupe_other_errors: bool,
+    ) -> anyhow::Result<Self> {
+        Self::collect_stream_impl(stream, failure_policy, dedupe_other_errors, |_, _| {}).await
+    }
+
+    /// Like `collect_stream_with_policy`, and invoke `on_target` once with the result of every
+    /// label that was not skipped.
+    ///
+    /// Events for different labels interleave, and the outputs of a label are only complete once
+    /// they are sorted and deduplicated, so `on_target` is invoked after the stream ended (or
+    /// collection stopped because of `failure_policy`), before this returns, in label order. The
+    /// result passed to it is exactly what is stored in `configured`.
+    pub async fn collect_stream_with_callback(
+        stream: impl Stream<Item = BuildEvent> + Unpin,
+        failure_policy: FailurePolicy,
+        on_target: impl FnMut(&ConfiguredProvidersLabel, &ConfiguredBuildTargetResult),
+    ) -> anyhow::Result<Self> {
+        Self::collect_stream_impl(stream, failure_policy, false, on_target).await
+    }
+
+    async fn collect_stream_impl(
+        mut stream: impl Stream<Item = BuildEvent> + Unpin,
+        failure_policy: FailurePolicy,
+        dedupe_other_errors: bool,
+        mut on_target: impl FnMut(&ConfiguredProvidersLabel, &ConfiguredBuildTargetResult),
     ) -> anyhow::Result<Self> {
         // Create a map of labels to outputs, but retain the expected index of each output.
         let mut res = HashMap::<
             ConfiguredProvidersLabel,
             Option<ConfiguredBuildTargetResultGen<(usize, buck2_error::Result<ProviderArtifacts>)>>,
         >::new();
+        // Likewise for outputs which were only resolved.
+        let mut resolved_outputs = HashMap::<ConfiguredProvidersLabel, Vec<_>>::new();
         let mut other_errors = BTreeMap::<_, Vec<_>>::new();
+        let mut seen_other_errors = BTreeSet::new();
+        let start = Instant::now();
+        let mut error_count = 0;
 
         while let Some(event) = stream.next().await {
-            let ConfiguredBuildEvent { variant, label } = match event {
+            let ConfiguredBuildEvent { variant, label, .. } = match event {
                 BuildEvent::Configured(variant) => variant,
                 BuildEvent::OtherError { label: target, err } => {
-                    other_errors.entry(target).or_default().push(err);
+                    if dedupe_other_errors
+                        && !seen_other_errors.insert((
+                            target.clone(),
+                            format!("{:#}", err),
+                            err.get_category(),
+                        ))
+                    {
+                        continue;
+                    }
+                    other_errors.entry(target).or_default().push(OtherError {
+                        error: err,
+                        received_after: start.elapsed(),
+                    });
                     continue;
                 }
             };
@@ -106,9 +441,14 @@ impl BuildTargetResult {
                 ConfiguredBuildEventVariant::SkippedIncompatible => {
                     res.entry((*label).clone()).or_insert(None);
                 }
+                ConfiguredBuildEventVariant::SkippedByFilter => {
+                    // The caller asked not to build this target, so don't report it at all.
+                }
                 ConfiguredBuildEventVariant::Prepared {
                     run_args,
                     target_rule_type_name,
+                    output_count: _,
+                    analysis_duration,
                 } => {
                     res.entry((*label).clone())
                         .or_insert(Some(ConfiguredBuildTargetResultGen {
@@ -116,7 +456,13 @@ impl BuildTargetResult {
                             run_args,
                             target_rule_type_name: Some(target_rule_type_name),
                             configured_graph_size: None,
+                            configured_graph_size_truncated: false,
+                            built_provider_types: BTreeSet::new(),
+                            analysis_duration: Some(analysis_duration),
+                            materialization_duration: Duration::ZERO,
+                            status: TargetBuildStatus::BuiltNoOutputs,
                             errors: Vec::new(),
+                            resolved_outputs: Vec::new(),
                         }));
                 }
                 ConfiguredBuildEventVariant::Output { index, output } => {
@@ -129,18 +475,41 @@ impl BuildTargetResult {
                         .outputs
                         .push((index, output));
 
-                    if is_err && fail_fast {
-                        break;
+                    if is_err {
+                        error_count += 1;
+                        if failure_policy.should_stop(error_count) {
+                            break;
+                        }
                     }
                 }
+                ConfiguredBuildEventVariant::ResolvedOutput {
+                    output,
+                    provider_type,
+                    index,
+                } => {
+                    res.get_mut(label.as_ref())
+                        .with_context(|| format!("BuildEventVariant::ResolvedOutput before BuildEventVariant::Prepared for {} (internal error)", label))?
+                        .as_ref()
+                        .with_context(|| format!("BuildEventVariant::ResolvedOutput for a skipped target: `{}` (internal error)", label))?;
+                    resolved_outputs.entry((*label).clone()).or_default().push((
+                        index,
+                        output,
+                        provider_type,
+                    ));
+                }
+                ConfiguredBuildEventVariant::MaterializationProgress { .. } => {
+                    // Only useful to report progress while building.
+                }
                 ConfiguredBuildEventVariant::GraphSize {
                     configured_graph_size,
+                    truncated,
                 } => {
-                    res.get_mut(label.as_ref())
+                    let result = res.get_mut(label.as_ref())
                         .with_context(|| format!("BuildEventVariant::GraphSize before BuildEventVariant::Prepared for {} (internal error)", label))?
                         .as_mut()
-                        .with_context(|| format!("BuildEventVariant::GraphSize for a skipped target: `{}` (internal error)", label))?
-                        .configured_graph_size = Some(configured_graph_size);
+                        .with_context(|| format!("BuildEventVariant::GraphSize for a skipped target: `{}` (internal error)", label))?;
+                    result.configured_graph_size = Some(configured_graph_size);
+                    result.configured_graph_size_truncated = truncated;
                 }
                 ConfiguredBuildEventVariant::Error { err } => {
                     res.entry((*label).clone())
@@ -149,13 +518,20 @@ impl BuildTargetResult {
                             run_args: None,
                             target_rule_type_name: None,
                             configured_graph_size: None,
+                            configured_graph_size_truncated: false,
+                            built_provider_types: BTreeSet::new(),
+                            analysis_duration: None,
+                            materialization_duration: Duration::ZERO,
+                            status: TargetBuildStatus::BuiltNoOutputs,
                             errors: Vec::new(),
+                            resolved_outputs: Vec::new(),
                         }))
                         .as_mut()
                         .unwrap()
                         .errors
                         .push(err);
-                    if fail_fast {
+                    error_count += 1;
+                    if failure_policy.should_stop(error_count) {
                         break;
                     }
                 }
@@ -164,7 +540,7 @@ impl BuildTargetResult {
 
         // Sort our outputs within each individual BuildTargetResult, then return those.
         // Also, turn our HashMap into a BTreeMap.
-        let res = res
+        let res: BTreeMap<_, _> = res
             .into_iter()
             .map(|(label, result)| {
                 let result = result.map(|result| {
@@ -173,7 +549,13 @@ impl BuildTargetResult {
                         run_args,
                         target_rule_type_name,
                         configured_graph_size,
+                        configured_graph_size_truncated,
+                        built_provider_types: _,
+                        analysis_duration,
+                        materialization_duration: _,
+                        status: _,
                         errors,
+                        resolved_outputs: _,
                     } = result;
 
                     // No need for a stable sort: the indices are unique (see below).
@@ -183,16 +565,38 @@ impl BuildTargetResult {
                     // request the same targets multiple times here, but since we know that
                     // ConfiguredTargetLabel -> Output is going to be deterministic, we just dedupe
                     // them using the index.
+                    let outputs = dedupe_outputs(&label, outputs);
+                    let built_provider_types = outputs
+                        .iter()
+                        .filter_map(|output| Some(output.as_ref().ok()?.provider_type.dupe()))
+                        .collect();
+                    let materialization_duration = outputs
+                        .iter()
+                        .filter_map(|output| Some(output.as_ref().ok()?.materialization_duration))
+                        .sum();
+
+                    // Like outputs, the same index might have been reported more than once.
+                    let mut resolved_outputs = resolved_outputs.remove(&label).unwrap_or_default();
+                    resolved_outputs.sort_unstable_by_key(|(index, _output, _type)| *index);
+                    resolved_outputs.dedup_by_key(|(index, _output, _type)| *index);
+                    let resolved_outputs = resolved_outputs
+                        .into_iter()
+                        .map(|(_index, output, provider_type)| (output, provider_type))
+                        .collect::<Vec<_>>();
+                    let status = TargetBuildStatus::compute(&outputs, &errors, &resolved_outputs);
+
                     ConfiguredBuildTargetResult {
-                        outputs: outputs
-                            .into_iter()
-                            .unique_by(|(index, _outputs)| *index)
-                            .map(|(_index, outputs)| outputs)
-                            .collect(),
+                        outputs,
                         run_args,
                         target_rule_type_name,
                         configured_graph_size,
+                        configured_graph_size_truncated,
+                        built_provider_types,
+                        analysis_duration,
+                        materialization_duration,
+                        status,
                         errors,
+                        resolved_outputs,
                     }
                 });
 
@@ -200,26 +604,433 @@ impl BuildTargetResult {
             })
             .collect();
 
+        for (label, result) in &res {
+            if let Some(result) = result {
+                on_target(label, result);
+            }
+        }
+
         Ok(Self {
             configured: res,
             other_errors,
         })
     }
+
+    /// Consume this result, returning the configured results sorted by label.
+    pub fn into_configured_vec(
+        self,
+    ) -> Vec<(
+        ConfiguredProvidersLabel,
+        Option<ConfiguredBuildTargetResult>,
+    )> {
+        self.configured.into_iter().collect()
+    }
+
+    /// Merge `other` into this result, e.g. to present builds of several patterns done in separate
+    /// passes as one. For labels present in both:
+    ///
+    /// * If the label was skipped (`None`) on one side but built (`Some`) on the other, the built
+    ///   result is kept.
+    /// * If the label was built on both sides, the results are combined as if the label had been
+    ///   built once, see `ConfiguredBuildTargetResult::merge`.
+    ///
+    /// `other_errors` are concatenated per label.
+    pub fn merge(&mut self, other: BuildTargetResult) {
+        for (label, other_result) in other.configured {
+            match self.configured.entry(label) {
+                std::collections::btree_map::Entry::Vacant(entry) => {
+                    entry.insert(other_result);
+                }
+                std::collections::btree_map::Entry::Occupied(mut entry) => {
+                    match (entry.get_mut(), other_result) {
+                        (_, None) => {}
+                        (result @ None, Some(other_result)) => *result = Some(other_result),
+                        (Some(result), Some(other_result)) => result.merge(other_result),
+                    }
+                }
+            }
+        }
+        for (label, errors) in other.other_errors {
+            self.other_errors.entry(label).or_default().extend(errors);
+        }
+    }
+
+    /// The successfully built outputs across all targets, without those whose artifacts were all
+    /// part of outputs already returned, e.g. generated headers shared by several targets. Outputs
+    /// that only partially overlap with earlier ones (e.g. a transitive set containing a shared
+    /// header) are still returned whole.
+    pub fn unique_outputs(&self) -> impl Iterator<Item = &ProviderArtifacts> {
+        let mut seen = HashSet::new();
+        self.configured
+            .values()
+            .flatten()
+            .flat_map(|result| result.outputs.iter())
+            .filter_map(|output| output.as_ref().ok())
+            .filter(move |output| {
+                // Not `any`, which would stop short of recording the rest of the artifacts.
+                output.values.iter().fold(false, |new, (artifact, _value)| {
+                    seen.insert(artifact) || new
+                })
+            })
+    }
+
+    /// The arguments to run `label` with, if it was built and provides a `RunInfo`. `None` for
+    /// skipped targets and for labels that are not part of this result.
+    pub fn run_args_for(&self, label: &ConfiguredProvidersLabel) -> Option<&[String]> {
+        self.configured.get(label)?.as_ref()?.run_args.as_deref()
+    }
+
+    /// The arguments to run every target that provides a `RunInfo` with, sorted by label.
+    pub fn all_run_args(&self) -> impl Iterator<Item = (&ConfiguredProvidersLabel, &[String])> {
+        self.configured
+            .iter()
+            .filter_map(|(label, result)| Some((label, result.as_ref()?.run_args.as_deref()?)))
+    }
+
+    /// Check the invariants `collect_stream` is expected to uphold, returning a description of
+    /// every violation. This is a debugging aid for aggregation bugs, not something builds should
+    /// rely on.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut issues = Vec::new();
+
+        // Compatibility is a property of the configured target, so a target can't be both skipped
+        // and built, whichever of its providers were requested.
+        let (skipped, built): (HashSet<_>, HashSet<_>) =
+            self.configured
+                .iter()
+                .partition_map(|(label, result)| match result {
+                    None => itertools::Either::Left(label.target()),
+                    Some(_) => itertools::Either::Right(label.target()),
+                });
+        for target in skipped.intersection(&built).sorted() {
+            issues.push(format!("`{}` is both skipped and built", target));
+        }
+
+        for (label, result) in &self.configured {
+            let Some(result) = result else {
+                continue;
+            };
+
+            // Everything but errors is only recorded after `Prepared`, which sets the rule type.
+            if result.target_rule_type_name.is_none() {
+                if !result.outputs.is_empty() {
+                    issues.push(format!("`{}` has outputs but no rule type", label));
+                }
+                if result.run_args.is_some() {
+                    issues.push(format!("`{}` has run args but no rule type", label));
+                }
+                if result.configured_graph_size.is_some() {
+                    issues.push(format!("`{}` has a graph size but no rule type", label));
+                }
+            }
+            if result.configured_graph_size_truncated && result.configured_graph_size.is_none() {
+                issues.push(format!(
+                    "`{}` has a truncated graph size but no graph size",
+                    label
+                ));
+            }
+        }
+
+        for (label, errors) in &self.other_errors {
+            if errors.is_empty() {
+                match label {
+                    Some(label) => issues.push(format!("`{}` has an empty list of errors", label)),
+                    None => issues.push("Empty list of unlabelled errors".to_owned()),
+                }
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    /// Count the targets in this result by outcome.
+    pub fn status_counts(&self) -> BuildStatusCounts {
+        let mut counts = BuildStatusCounts::default();
+        for result in self.configured.values() {
+            match result {
+                None => counts.skipped += 1,
+                Some(result) if result.is_failure() => counts.failed += 1,
+                Some(_) => counts.succeeded += 1,
+            }
+        }
+        counts
+    }
+
+    /// A one-line summary of this result for humans, e.g. `Built 42 targets (3 skipped), 2 failed
+    /// in 12.3s`, where 42 counts every target, including the skipped and failed ones. Targets
+    /// whose outputs were only resolved are counted as `resolved only` next to the skipped ones.
+    pub fn summary_line(&self, wall_time: Duration) -> String {
+        let BuildStatusCounts {
+            succeeded,
+            failed,
+            skipped,
+        } = self.status_counts();
+        let total = succeeded + failed + skipped;
+        let resolved = self
+            .configured
+            .values()
+            .flatten()
+            .filter(|result| result.status == TargetBuildStatus::Resolved)
+            .count();
+
+        let mut line = format!(
+            "Built {} target{}",
+            total,
+            if total == 1 { "" } else { "s" }
+        );
+        match (skipped, resolved) {
+            (0, 0) => {}
+            (skipped, 0) => write!(line, " ({} skipped)", skipped).unwrap(),
+            (0, resolved) => write!(line, " ({} resolved only)", resolved).unwrap(),
+            (skipped, resolved) => {
+                write!(line, " ({} skipped, {} resolved only)", skipped, resolved).unwrap()
+            }
+        }
+        write!(
+            line,
+            ", {} failed in {:.1}s",
+            failed,
+            wall_time.as_secs_f64()
+        )
+        .unwrap();
+        line
+    }
+
+    /// A hash of the labels, statuses and output digests of this result, which ignores anything
+    /// nondeterministic (e.g. durations or error messages). Two builds that produced the same
+    /// artifacts for the same targets have the same fingerprint.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        // Length-prefix every field, so that adjacent fields can't be confused for one another.
+        let mut update = |field: &str| {
+            hasher.update(&(field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        };
+
+        for (label, result) in &self.configured {
+            update(&label.to_string());
+            let Some(result) = result else {
+                update("skipped");
+                continue;
+            };
+            update(match result.status {
+                TargetBuildStatus::Built => "built",
+                TargetBuildStatus::BuiltNoOutputs => "built_no_outputs",
+                TargetBuildStatus::Resolved => "resolved",
+                TargetBuildStatus::Failed => "failed",
+            });
+            for output in &result.outputs {
+                let Ok(output) = output else {
+                    update("error");
+                    continue;
+                };
+                for (artifact, value) in output.values.iter() {
+                    update(&artifact.to_string());
+                    match value.digest() {
+                        Some(digest) => update(&digest.to_string()),
+                        // Symlinks have no digest, but their target identifies them.
+                        None => update(&format!("{:?}", value.entry())),
+                    }
+                }
+            }
+        }
+        for (label, errors) in &self.other_errors {
+            update(
+                &label
+                    .as_ref()
+                    .map_or_else(String::new, |label| label.to_string()),
+            );
+            update(&errors.len().to_string());
+        }
+
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Count the errors in this result (including failed outputs and errors not associated with a
+    /// configured target) by category. Errors without a category are counted under `None`.
+    pub fn error_counts_by_category(&self) -> BTreeMap<Option<Category>, usize> {
+        let target_errors = self.configured.values().flatten().flat_map(|result| {
+            result
+                .outputs
+                .iter()
+                .filter_map(|output| output.as_ref().err())
+                .chain(&result.errors)
+        });
+        let other_errors = self.other_errors.values().flatten().map(|e| &e.error);
+
+        let mut counts = BTreeMap::new();
+        for error in target_errors.chain(other_errors) {
+            *counts.entry(error.get_category()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Group `other_errors` whose message and category are identical, e.g. the failure of a
+    /// dependency shared by many targets, listing every label each unique error was reported for.
+    /// Groups are ordered by their first occurrence.
+    pub fn coalesced_other_errors(
+        &self,
+    ) -> Vec<(Vec<Option<ProvidersLabel>>, &buck2_error::Error)> {
+        let mut groups = Vec::<(Vec<Option<ProvidersLabel>>, &buck2_error::Error)>::new();
+        let mut indices = BTreeMap::new();
+        for (label, errors) in &self.other_errors {
+            for OtherError { error, .. } in errors {
+                let index = *indices
+                    .entry((format!("{:#}", error), error.get_category()))
+                    .or_insert_with(|| {
+                        groups.push((Vec::new(), error));
+                        groups.len() - 1
+                    });
+                let labels = &mut groups[index].0;
+                // The same error may be reported more than once for a label.
+                if labels.last() != Some(label) {
+                    labels.push(label.clone());
+                }
+            }
+        }
+        groups
+    }
+
+    /// The labels in this result which built at least one artifact produced by an action in
+    /// `critical_path`, e.g. the action keys of `BuildInfo`'s critical path. Failed outputs are
+    /// ignored.
+    pub fn labels_on_critical_path<'a>(
+        &self,
+        critical_path: impl IntoIterator<Item = &'a ActionKey>,
+    ) -> Vec<&ConfiguredProvidersLabel> {
+        let critical_path = critical_path.into_iter().collect::<HashSet<_>>();
+        self.configured
+            .iter()
+            .filter(|(_label, result)| {
+                result
+                    .iter()
+                    .flat_map(|result| result.outputs.iter())
+                    .filter_map(|output| output.as_ref().ok())
+                    .flat_map(|output| output.values.iter())
+                    .any(|(artifact, _value)| match artifact.as_parts().0 {
+                        BaseArtifactKind::Build(artifact) => critical_path.contains(artifact.key()),
+                        BaseArtifactKind::Source(..) => false,
+                    })
+            })
+            .map(|(label, _result)| label)
+            .collect()
+    }
+
+    /// Convert this result to a proto, so that it can be shipped to the same consumers as
+    /// `BuildGraphExecutionInfo` (e.g. IDE integrations). Output paths are resolved with `fs`.
+    /// Targets are sorted by label, and skipped targets are reported with a skipped status.
+    pub fn to_proto(&self, fs: &ArtifactFs) -> anyhow::Result<buck2_data::BuildResult> {
+        let mut targets = Vec::with_capacity(self.configured.len());
+        for (label, result) in &self.configured {
+            let Some(result) = result else {
+                targets.push(buck2_data::build_result::Target {
+                    label: label.to_string(),
+                    status: buck2_data::BuildTargetStatus::BuildTargetSkipped as i32,
+                    target_rule_type_name: None,
+                    outputs: Vec::new(),
+                    errors: Vec::new(),
+                    run_args: Vec::new(),
+                    configured_graph_size: None,
+                });
+                continue;
+            };
+
+            let mut outputs = Vec::new();
+            let mut errors = Vec::new();
+            for output in &result.outputs {
+                match output {
+                    Ok(output) => {
+                        let provider_type = output.provider_type.to_proto() as i32;
+                        for (artifact, _value) in output.values.iter() {
+                            outputs.push(buck2_data::build_result::Output {
+                                path: artifact.get_path().resolve(fs)?.to_string(),
+                                provider_type,
+                            });
+                        }
+                    }
+                    Err(e) => errors.push(create_error_report(e)),
+                }
+            }
+            errors.extend(result.errors.iter().map(create_error_report));
+
+            let status = if errors.is_empty() {
+                buck2_data::BuildTargetStatus::BuildTargetSucceeded
+            } else {
+                buck2_data::BuildTargetStatus::BuildTargetFailed
+            };
+            targets.push(buck2_data::build_result::Target {
+                label: label.to_string(),
+                status: status as i32,
+                target_rule_type_name: result.target_rule_type_name.clone(),
+                outputs,
+                errors,
+                run_args: result.run_args.clone().unwrap_or_default(),
+                configured_graph_size: match &result.configured_graph_size {
+                    Some(Ok(MaybeCompatible::Compatible(size))) => Some(*size),
+                    _ => None,
+                },
+            });
+        }
+
+        let other_errors = self
+            .other_errors
+            .iter()
+            .map(|(label, errors)| buck2_data::build_result::OtherErrors {
+                label: label.as_ref().map(|label| label.to_string()),
+                errors: errors
+                    .iter()
+                    .map(|e| create_error_report(&e.error))
+                    .collect(),
+            })
+            .collect();
+
+        Ok(buck2_data::BuildResult {
+            targets,
+            other_errors,
+        })
+    }
 }
 
 enum ConfiguredBuildEventVariant {
     SkippedIncompatible,
+    /// The target's rule type didn't match `BuildConfiguredLabelOptions::rule_type_filter`.
+    SkippedByFilter,
     Prepared {
         run_args: Option<Vec<String>>,
         target_rule_type_name: String,
+        /// How many `Output` (or `ResolvedOutput`) events will follow for this label.
+        output_count: usize,
+        /// How long it took to resolve the providers and collect the outputs to build.
+        analysis_duration: Duration,
     },
     Output {
         output: buck2_error::Result<ProviderArtifacts>,
         /// Ensure a stable ordering of outputs.
         index: usize,
     },
+    /// Emitted instead of `Output` with `BuildConfiguredLabelOptions::resolve_only`: the output
+    /// is known, but was neither built nor materialized. `index` is the same as that of the
+    /// `Output` event a regular build would emit, so outputs are keyed the same way.
+    ResolvedOutput {
+        output: ArtifactGroup,
+        provider_type: BuildProviderType,
+        index: usize,
+    },
+    /// Emitted after each `Output`, so that consumers can report per-target progress.
+    MaterializationProgress {
+        /// How many outputs of this label were built so far.
+        completed: usize,
+        /// How many outputs of this label will be built in total.
+        total: usize,
+    },
     GraphSize {
         configured_graph_size: buck2_error::Result<MaybeCompatible<u64>>,
+        /// Set when the size was only counted up to `configured_graph_size_max_depth`.
+        truncated: bool,
     },
     Error {
         /// An error that can't be associated with a single artifact.
@@ -227,10 +1038,79 @@ enum ConfiguredBuildEventVariant {
     },
 }
 
+/// Keep one output per index of `outputs`, which must be sorted by index. Outputs with the same
+/// index are expected to be identical, but should one have succeeded and another failed (which
+/// would be a bug), keep the failure so that it isn't silently dropped.
+fn dedupe_outputs(
+    label: &ConfiguredProvidersLabel,
+    outputs: Vec<(usize, buck2_error::Result<ProviderArtifacts>)>,
+) -> Vec<buck2_error::Result<ProviderArtifacts>> {
+    let mut deduped: Vec<(usize, buck2_error::Result<ProviderArtifacts>)> =
+        Vec::with_capacity(outputs.len());
+    for (index, output) in outputs {
+        match deduped.last_mut() {
+            Some((last_index, last)) if *last_index == index => {
+                if last.is_ok() != output.is_ok() {
+                    let _ignored = soft_error!(
+                        "build_output_index_collision",
+                        anyhow::anyhow!(
+                            "Output {} of `{}` both succeeded and failed",
+                            index,
+                            label
+                        )
+                    );
+                    if output.is_err() {
+                        *last = output;
+                    }
+                }
+            }
+            _ => deduped.push((index, output)),
+        }
+    }
+    deduped.into_iter().map(|(_index, output)| output).collect()
+}
+
 /// Events to be accumulated using BuildTargetResult::collect_stream.
 pub struct ConfiguredBuildEvent {
     label: Arc<ConfiguredProvidersLabel>,
     variant: ConfiguredBuildEventVariant,
+    /// The `request_id` from the `BuildConfiguredLabelOptions` this event was produced with.
+    request_id: Option<u64>,
+}
+
+impl ConfiguredBuildEvent {
+    pub fn label(&self) -> &ConfiguredProvidersLabel {
+        &self.label
+    }
+
+    pub fn request_id(&self) -> Option<u64> {
+        self.request_id
+    }
+
+    /// For the event that precedes all outputs of a label, how many outputs will be built. This
+    /// lets consumers sum totals across labels to report progress as they receive outputs.
+    pub fn output_count(&self) -> Option<usize> {
+        match &self.variant {
+            ConfiguredBuildEventVariant::Prepared { output_count, .. } => Some(*output_count),
+            _ => None,
+        }
+    }
+
+    /// Whether this event is the result of building one output.
+    pub fn is_output(&self) -> bool {
+        matches!(self.variant, ConfiguredBuildEventVariant::Output { .. })
+    }
+
+    /// For the event that follows each output, how many outputs of the label were built so far,
+    /// and how many there are in total.
+    pub fn materialization_progress(&self) -> Option<(usize, usize)> {
+        match &self.variant {
+            ConfiguredBuildEventVariant::MaterializationProgress { completed, total } => {
+                Some((*completed, *total))
+            }
+            _ => None,
+        }
+    }
 }
 
 pub enum BuildEvent {
@@ -242,10 +1122,163 @@ pub enum BuildEvent {
     },
 }
 
-#[derive(Copy, Clone, Dupe, Debug)]
+/// Start analysis of all the given labels concurrently, so that building them afterwards finds
+/// their providers already computed.
+///
+/// This is a best-effort optimization: results are discarded, and any errors will be reported
+/// again when the labels are actually built.
+pub async fn prewarm_analysis(ctx: &DiceComputations, labels: &[ConfiguredProvidersLabel]) {
+    labels
+        .iter()
+        .map(|label| ctx.get_providers(label))
+        .collect::<FuturesUnordered<_>>()
+        .for_each(|_res| future::ready(()))
+        .await;
+}
+
+/// The label for the default providers of `target`, which is what building a target without a
+/// `[subtarget]` builds. Shorthand for `ConfiguredProvidersLabel::default_for`.
+pub fn default_providers_label(target: ConfiguredTargetLabel) -> ConfiguredProvidersLabel {
+    ConfiguredProvidersLabel::default_for(target)
+}
+
+/// Where messages about targets skipped for being incompatible are reported.
+#[derive(Clone, Copy, Dupe, Debug, Default, PartialEq, Eq)]
+pub enum MessageSink {
+    /// Print a message to the console, in addition to the `SkippedIncompatible` event.
+    #[default]
+    Console,
+    /// Only emit the `SkippedIncompatible` event, for callers (e.g. JSON output) that render
+    /// skipped targets themselves.
+    EventOnly,
+    /// Don't report the skip in any message. The `SkippedIncompatible` event is still emitted,
+    /// so the target is still accounted for in the results.
+    Suppressed,
+}
+
+/// Decides for which labels to compute the configured graph size, which is expensive on large
+/// builds.
+#[derive(Clone, Dupe)]
+pub struct WantConfiguredGraphSize(Arc<dyn Fn(&ConfiguredProvidersLabel) -> bool + Send + Sync>);
+
+impl WantConfiguredGraphSize {
+    pub fn new(
+        predicate: impl Fn(&ConfiguredProvidersLabel) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(predicate))
+    }
+
+    /// Compute the configured graph size of either every label, or none.
+    pub fn for_all(want: bool) -> Self {
+        Self::new(move |_| want)
+    }
+
+    pub fn wants(&self, label: &ConfiguredProvidersLabel) -> bool {
+        (self.0)(label)
+    }
+}
+
+impl Debug for WantConfiguredGraphSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WantConfiguredGraphSize")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Receives the messages a build would otherwise print to the console, for embedders that want to
+/// route them somewhere else (e.g. into structured logs).
+#[derive(Clone, Dupe)]
+pub struct ConsoleMessageSink(Arc<dyn Fn(String) + Send + Sync>);
+
+impl ConsoleMessageSink {
+    pub fn new(sink: impl Fn(String) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+}
+
+impl Debug for ConsoleMessageSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConsoleMessageSink").finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct BuildConfiguredLabelOptions {
     pub skippable: bool,
-    pub want_configured_graph_size: bool,
+    pub want_configured_graph_size: WantConfiguredGraphSize,
+    /// When computing the configured graph size, only count nodes up to this many hops from the
+    /// target. This gives a cheap lower bound on huge graphs.
+    pub configured_graph_size_max_depth: Option<usize>,
+    /// An identifier chosen by the caller, which is attached to every event produced for this
+    /// label, so that events can be correlated with the request that caused them.
+    pub request_id: Option<u64>,
+    /// If set, only build targets whose rule type name is in this set. Other targets are skipped
+    /// without collecting (or building) any of their outputs.
+    pub rule_type_filter: Option<Arc<HashSet<String>>>,
+    /// If set, `DefaultInfo` outputs whose paths don't match this filter are neither built nor
+    /// reported.
+    pub output_path_filter: Option<Arc<PathFilter>>,
+    /// Whether to report this label to build signals as a top-level target, which critical path
+    /// attribution relies on. Builds that only exist to support another build (e.g. for internal
+    /// tooling) should set this to false.
+    pub register_as_top_level: bool,
+    /// If set, at most this many outputs of this label are built (and materialized) at once.
+    /// Targets with very many outputs otherwise materialize all of them concurrently.
+    pub max_concurrent_materializations: Option<usize>,
+    /// Where to report targets that are skipped because they are incompatible (only relevant if
+    /// `skippable` is set).
+    pub incompatible_message_sink: MessageSink,
+    /// The path separator to use in `RunInfo` arguments. Defaults to the separator of the machine
+    /// running the build, but can be overridden to generate arguments for a different host.
+    pub path_separator: Option<PathSeparatorKind>,
+    /// Only find out which outputs the label has, without building or materializing them: each
+    /// output is reported as a `ResolvedOutput` event rather than an `Output` one, and the label
+    /// is not reported to build signals.
+    pub resolve_only: bool,
+    /// If set, console messages about this label (e.g. that it is skipped or has no outputs) are
+    /// sent here instead of to the global console.
+    pub message_sink: Option<ConsoleMessageSink>,
+    /// If set, outputs stop being built (and materialized) once this is cancelled, e.g. because
+    /// the user hit ctrl-c: outputs which didn't finish by then are dropped without being
+    /// reported. Their artifacts are released from the materialization map, so later requests
+    /// for them in the same command still materialize them.
+    pub cancellation: Option<CancellationToken>,
+    /// Only analyze the label, e.g. because the user skipped every provider category. Requesting
+    /// no provider categories is then expected, and is neither an error nor reported as the label
+    /// not having any outputs.
+    pub analysis_only: bool,
+}
+
+// Every field is cheap to clone: `CancellationToken` is reference counted.
+impl Dupe for BuildConfiguredLabelOptions {}
+
+impl BuildConfiguredLabelOptions {
+    fn console_message(&self, message: String) {
+        match &self.message_sink {
+            Some(sink) => (sink.0)(message),
+            None => console_message(message),
+        }
+    }
+}
+
+/// Reports that `providers_label` was skipped for being incompatible, according to
+/// `opts.incompatible_message_sink`, and returns the event to emit for it.
+fn skipped_incompatible(
+    providers_label: &Arc<ConfiguredProvidersLabel>,
+    reason: &IncompatiblePlatformReason,
+    opts: &BuildConfiguredLabelOptions,
+) -> ConfiguredBuildEvent {
+    match opts.incompatible_message_sink {
+        MessageSink::Console => {
+            opts.console_message(reason.skipping_message(providers_label.target()));
+        }
+        MessageSink::EventOnly | MessageSink::Suppressed => {}
+    }
+    ConfiguredBuildEvent {
+        label: providers_label.dupe(),
+        variant: ConfiguredBuildEventVariant::SkippedIncompatible,
+        request_id: opts.request_id,
+    }
 }
 
 pub async fn build_configured_label<'a>(
@@ -256,6 +1289,7 @@ pub async fn build_configured_label<'a>(
     opts: BuildConfiguredLabelOptions,
 ) -> BoxStream<'a, ConfiguredBuildEvent> {
     let providers_label = Arc::new(providers_label);
+    let request_id = opts.request_id;
     build_configured_label_inner(
         ctx,
         materialization_context,
@@ -268,32 +1302,125 @@ pub async fn build_configured_label<'a>(
         futures::stream::once(futures::future::ready(ConfiguredBuildEvent {
             label: providers_label,
             variant: ConfiguredBuildEventVariant::Error { err: e.into() },
+            request_id,
         }))
         .boxed()
     })
 }
 
-async fn build_configured_label_inner<'a>(
+/// Build each of `providers_labels`, in priority order: whenever several labels have events ready,
+/// those of the earliest listed label are returned first, and its outputs are polled (and so
+/// requested for materialization) before those of later labels. Later labels still make progress
+/// whenever the earlier ones are waiting.
+///
+/// If `max_concurrent_targets` is set, at most that many labels are being built at any time, the
+/// others waiting for their turn in priority order.
+pub fn build_configured_labels<'a>(
     ctx: &'a DiceComputations,
-    materialization_context: &MaterializationContext,
-    providers_label: Arc<ConfiguredProvidersLabel>,
-    providers_to_build: &ProvidersToBuild,
+    materialization_context: &'a MaterializationContext,
+    providers_labels: Vec<ConfiguredProvidersLabel>,
+    providers_to_build: &'a ProvidersToBuild,
     opts: BuildConfiguredLabelOptions,
-) -> anyhow::Result<BoxStream<'a, ConfiguredBuildEvent>> {
-    let artifact_fs = ctx.get_artifact_fs().await?;
-
-    let (outputs, run_args, target_rule_type_name) = {
-        // A couple of these objects aren't Send and so scope them here so async transform doesn't get concerned.
-        let providers = match ctx.get_providers(providers_label.as_ref()).await? {
-            MaybeCompatible::Incompatible(reason) => {
+    max_concurrent_targets: Option<usize>,
+) -> BoxStream<'a, ConfiguredBuildEvent> {
+    let semaphore = max_concurrent_targets.map(|permits| Arc::new(Semaphore::new(permits)));
+    merge_by_priority(
+        providers_labels
+            .into_iter()
+            .map(|providers_label| {
+                with_permit(
+                    semaphore.dupe(),
+                    build_configured_label(
+                        ctx,
+                        materialization_context,
+                        providers_label,
+                        providers_to_build,
+                        opts.dupe(),
+                    ),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Only start `stream` once a permit from `semaphore` (if any) was acquired, and hold on to it
+/// until the stream is dropped.
+fn with_permit<'a, T: Send + 'a>(
+    semaphore: Option<Arc<Semaphore>>,
+    stream: impl Future<Output = BoxStream<'a, T>> + Send + 'a,
+) -> BoxStream<'a, T> {
+    async move {
+        let permit = match semaphore {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("The semaphore is never closed"),
+            ),
+            None => None,
+        };
+        stream.await.map(move |item| {
+            let _permit = &permit;
+            item
+        })
+    }
+    .flatten_stream()
+    .boxed()
+}
+
+/// Merge `streams`, always returning an item from the earliest stream which has one ready. Every
+/// stream is polled until one is ready, so later streams progress while earlier ones are pending.
+fn merge_by_priority<'a, T: 'a>(mut streams: Vec<BoxStream<'a, T>>) -> BoxStream<'a, T>
+where
+    T: Send,
+{
+    futures::stream::poll_fn(move |cx| {
+        let mut i = 0;
+        while i < streams.len() {
+            match streams[i].poll_next_unpin(cx) {
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                Poll::Ready(None) => {
+                    streams.remove(i);
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+        if streams.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    })
+    .boxed()
+}
+
+async fn build_configured_label_inner<'a>(
+    ctx: &'a DiceComputations,
+    materialization_context: &MaterializationContext,
+    providers_label: Arc<ConfiguredProvidersLabel>,
+    providers_to_build: &ProvidersToBuild,
+    opts: BuildConfiguredLabelOptions,
+) -> anyhow::Result<BoxStream<'a, ConfiguredBuildEvent>> {
+    // This would just produce no outputs, which is almost certainly not what the caller wanted
+    // (unless it only wants analysis).
+    if !opts.skippable && !opts.analysis_only && providers_to_build.is_empty() {
+        return Err(BuildError::NoProvidersRequested(providers_label.as_ref().clone()).into());
+    }
+
+    let artifact_fs = ctx.get_artifact_fs().await?;
+
+    let analysis_start = Instant::now();
+    let (outputs, run_args, target_rule_type_name, provider_errors) = {
+        // A couple of these objects aren't Send and so scope them here so async transform doesn't get concerned.
+        // Analysis retries infra errors itself, see `AnalysisKey`.
+        let providers = match ctx.get_providers(providers_label.as_ref()).await? {
+            MaybeCompatible::Incompatible(reason) => {
                 if opts.skippable {
-                    console_message(reason.skipping_message(providers_label.target()));
-                    return Ok(futures::stream::once(futures::future::ready(
-                        ConfiguredBuildEvent {
-                            label: providers_label.dupe(),
-                            variant: ConfiguredBuildEventVariant::SkippedIncompatible,
-                        },
-                    ))
+                    return Ok(futures::stream::once(future::ready(skipped_incompatible(
+                        &providers_label,
+                        &reason,
+                        &opts,
+                    )))
                     .boxed());
                 } else {
                     return Err(reason.to_err());
@@ -302,6 +1429,27 @@ async fn build_configured_label_inner<'a>(
             MaybeCompatible::Compatible(v) => v,
         };
 
+        let target_rule_type_name: String = ctx
+            .get_configured_target_node(providers_label.target())
+            .await?
+            .require_compatible()?
+            .rule_type()
+            .name()
+            .to_owned();
+
+        if let Some(rule_type_filter) = &opts.rule_type_filter {
+            if !rule_type_filter.contains(&target_rule_type_name) {
+                return Ok(
+                    futures::stream::once(futures::future::ready(ConfiguredBuildEvent {
+                        label: providers_label.dupe(),
+                        variant: ConfiguredBuildEventVariant::SkippedByFilter,
+                        request_id: opts.request_id,
+                    }))
+                    .boxed(),
+                );
+            }
+        }
+
         // Important we use an an ordered collections, so the order matches the order the rule
         // author wrote.
         let mut outputs = Vec::new();
@@ -310,8 +1458,11 @@ async fn build_configured_label_inner<'a>(
         let collection = providers.provider_collection();
 
         let mut run_args: Option<Vec<String>> = None;
+        // Failures to visit the artifacts of `RunInfo` or `TestProvider`. They are reported for
+        // the target, but don't prevent building the other outputs.
+        let mut provider_errors = Vec::new();
 
-        if providers_to_build.default {
+        if providers_to_build.wants_default() {
             collection
                 .default_info()
                 .for_each_default_output_artifact_only(&mut |o| {
@@ -319,7 +1470,7 @@ async fn build_configured_label_inner<'a>(
                     Ok(())
                 })?;
         }
-        if providers_to_build.default_other {
+        if providers_to_build.wants_default_other() {
             collection
                 .default_info()
                 .for_each_default_output_other_artifacts_only(&mut |o| {
@@ -332,51 +1483,58 @@ async fn build_configured_label_inner<'a>(
                 Ok(())
             })?;
         }
-        if providers_to_build.run {
-            if let Some(runinfo) = providers
-                .provider_collection()
-                .builtin_provider::<FrozenRunInfo>()
-            {
-                let mut artifact_visitor = SimpleCommandLineArtifactVisitor::new();
-                runinfo.visit_artifacts(&mut artifact_visitor)?;
-                for input in artifact_visitor.inputs {
-                    outputs.push((input, BuildProviderType::Run));
+        dedupe_default_outputs(&mut outputs);
+        if let Some(output_path_filter) = &opts.output_path_filter {
+            outputs = filter_default_outputs(outputs, output_path_filter, &artifact_fs)?;
+        }
+        // `RunInfo` and `TestProvider` are only looked up (and their artifacts visited) if they
+        // were asked for, so building just the default outputs doesn't pay for them.
+        if providers_to_build.wants_run() {
+            if let Some(runinfo) = collection.builtin_provider::<FrozenRunInfo>() {
+                match push_provider_outputs(&mut outputs, BuildProviderType::Run, |visitor| {
+                    runinfo.visit_artifacts(visitor)
+                }) {
+                    Ok(()) => {
+                        // Produce arguments to run on a local machine, unless asked otherwise.
+                        let path_separator = opts.path_separator.unwrap_or(if cfg!(windows) {
+                            PathSeparatorKind::Windows
+                        } else {
+                            PathSeparatorKind::Unix
+                        });
+                        let executor_fs = ExecutorFs::new(&artifact_fs, path_separator);
+                        let mut cli = Vec::<String>::new();
+                        let mut ctx = AbsCommandLineContext::new(&executor_fs);
+                        runinfo.add_to_command_line(&mut cli, &mut ctx)?;
+                        run_args = Some(cli);
+                    }
+                    Err(e) => {
+                        provider_errors.push(e.context("Error visiting artifacts of `RunInfo`"))
+                    }
                 }
-                // Produce arguments to run on a local machine.
-                let path_separator = if cfg!(windows) {
-                    PathSeparatorKind::Windows
-                } else {
-                    PathSeparatorKind::Unix
-                };
-                let executor_fs = ExecutorFs::new(&artifact_fs, path_separator);
-                let mut cli = Vec::<String>::new();
-                let mut ctx = AbsCommandLineContext::new(&executor_fs);
-                runinfo.add_to_command_line(&mut cli, &mut ctx)?;
-                run_args = Some(cli);
             }
         }
-        if providers_to_build.tests {
+        if providers_to_build.wants_tests() {
             if let Some(test_provider) = <dyn TestProvider>::from_collection(collection) {
-                let mut artifact_visitor = SimpleCommandLineArtifactVisitor::new();
-                test_provider.visit_artifacts(&mut artifact_visitor)?;
-                for input in artifact_visitor.inputs {
-                    outputs.push((input, BuildProviderType::Test));
+                if let Err(e) =
+                    push_provider_outputs(&mut outputs, BuildProviderType::Test, |visitor| {
+                        test_provider.visit_artifacts(visitor)
+                    })
+                {
+                    provider_errors
+                        .push(e.context("Error visiting artifacts of the test provider"));
                 }
             }
         }
 
-        let target_rule_type_name: String = ctx
-            .get_configured_target_node(providers_label.target())
-            .await?
-            .require_compatible()?
-            .rule_type()
-            .name()
-            .to_owned();
-
-        (outputs, run_args, target_rule_type_name)
+        (outputs, run_args, target_rule_type_name, provider_errors)
     };
+    let analysis_duration = analysis_start.elapsed();
 
-    if let Some(signals) = ctx.per_transaction_data().get_build_signals() {
+    if let Some(signals) = ctx
+        .per_transaction_data()
+        .get_build_signals()
+        .filter(|_| opts.register_as_top_level && !opts.resolve_only)
+    {
         signals.top_level_target(
             providers_label.target().dupe(),
             outputs
@@ -386,95 +1544,360 @@ async fn build_configured_label_inner<'a>(
         );
     }
 
-    if !opts.skippable && outputs.is_empty() {
+    // `Run`, `Test` and default outputs are only collected above when they were requested, so a
+    // `RunInfo` doesn't count towards the outputs of a build that only asked for default outputs.
+    if !opts.skippable && !opts.analysis_only && outputs.is_empty() {
         let docs = "https://buck2.build/docs/users/faq/common_issues/#why-does-my-target-not-have-any-outputs"; // @oss-enable
         // @oss-disable: let docs = "https://www.internalfb.com/intern/staticdocs/buck2/docs/users/faq/common_issues/#why-does-my-target-not-have-any-outputs";
-        console_message(format!(
+        opts.console_message(format!(
             "Target {} does not have any outputs. This means the rule did not define any outputs. See {} for more information",
             providers_label.target(),
             docs,
         ));
     }
 
-    let outputs = outputs
-        .into_iter()
+    let output_count = outputs.len();
+    let outputs = if opts.resolve_only {
+        // Nothing to build, so all outputs are known right away.
+        let providers_label = providers_label.dupe();
+        futures::stream::iter(outputs.into_iter().enumerate().map(
+            move |(index, (output, provider_type))| ConfiguredBuildEvent {
+                label: providers_label.dupe(),
+                variant: ConfiguredBuildEventVariant::ResolvedOutput {
+                    output,
+                    provider_type,
+                    index,
+                },
+                request_id: opts.request_id,
+            },
+        ))
+        .left_stream()
+    } else {
+        let provider_name = provider_name(&providers_label);
+        let outputs = outputs
+            .into_iter()
+            .enumerate()
+            .map({
+                |(index, (output, provider_type))| {
+                    let materialization_context = materialization_context.dupe();
+                    let provider_name = provider_name.clone();
+                    async move {
+                        let start = Instant::now();
+                        let res: anyhow::Result<_> = try {
+                            let values = ctx.ensure_artifact_group(&output).await?;
+                            let materialization_start = Instant::now();
+                            materialize_values(ctx, &values, &materialization_context).await?;
+                            let materialization_duration = match materialization_context {
+                                MaterializationContext::Skip => Duration::ZERO,
+                                MaterializationContext::Materialize { .. }
+                                | MaterializationContext::MaterializeMatching { .. } => {
+                                    materialization_start.elapsed()
+                                }
+                            };
+                            ProviderArtifacts {
+                                values,
+                                provider_type,
+                                duration: Some(start.elapsed()),
+                                materialization_duration,
+                                provider_name,
+                            }
+                        };
+
+                        (index, res.map_err(buck2_error::Error::from))
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let total = outputs.len();
+        let outputs = match opts.max_concurrent_materializations {
+            // `buffer_unordered(0)` would never make progress.
+            Some(limit) => futures::stream::iter(outputs)
+                .buffer_unordered(limit.max(1))
+                .left_stream(),
+            None => outputs
+                .into_iter()
+                .collect::<FuturesUnordered<_>>()
+                .right_stream(),
+        }
         .enumerate()
-        .map({
-            |(index, (output, provider_type))| {
-                let materialization_context = materialization_context.dupe();
-                materialize_artifact_group_owned(ctx, output, materialization_context).map(
-                    move |res| {
-                        let res =
-                            res.map_err(buck2_error::Error::from)
-                                .map(|values| ProviderArtifacts {
-                                    values,
-                                    provider_type,
-                                });
-
-                        (index, res)
-                    },
-                )
-            }
-        })
-        .collect::<FuturesUnordered<_>>()
-        .map({
+        .flat_map({
             let providers_label = providers_label.dupe();
-            move |(index, output)| ConfiguredBuildEvent {
-                label: providers_label.dupe(),
-                variant: ConfiguredBuildEventVariant::Output { index, output },
+            move |(completed, (index, output))| {
+                let event = |variant| ConfiguredBuildEvent {
+                    label: providers_label.dupe(),
+                    variant,
+                    request_id: opts.request_id,
+                };
+                futures::stream::iter([
+                    event(ConfiguredBuildEventVariant::Output { index, output }),
+                    event(ConfiguredBuildEventVariant::MaterializationProgress {
+                        completed: completed + 1,
+                        total,
+                    }),
+                ])
             }
         });
+        match &opts.cancellation {
+            Some(cancellation) => {
+                let cancellation = cancellation.clone();
+                outputs
+                    .take_until(async move { cancellation.cancelled().await })
+                    .left_stream()
+            }
+            None => outputs.right_stream(),
+        }
+        .right_stream()
+    };
 
     let stream = futures::stream::once(futures::future::ready(ConfiguredBuildEvent {
         label: providers_label.dupe(),
         variant: ConfiguredBuildEventVariant::Prepared {
             run_args,
             target_rule_type_name,
+            output_count,
+            analysis_duration,
         },
+        request_id: opts.request_id,
     }))
+    .chain(futures::stream::iter(provider_errors.into_iter().map({
+        let providers_label = providers_label.dupe();
+        move |err| ConfiguredBuildEvent {
+            label: providers_label.dupe(),
+            variant: ConfiguredBuildEventVariant::Error { err },
+            request_id: opts.request_id,
+        }
+    })))
     .chain(outputs);
 
-    if opts.want_configured_graph_size {
-        let stream = stream.chain(futures::stream::once(async move {
-            let configured_graph_size =
-                graph_size::get_configured_graph_size(ctx, providers_label.target())
+    if opts.want_configured_graph_size.wants(&providers_label) {
+        let graph_size = async move {
+            let (configured_graph_size, truncated) = match opts.configured_graph_size_max_depth {
+                Some(max_depth) => {
+                    match graph_size::get_configured_graph_size_bounded(
+                        ctx,
+                        providers_label.target(),
+                        max_depth,
+                    )
                     .await
-                    .map_err(|e| e.into());
+                    {
+                        Ok(size) => {
+                            let truncated = matches!(
+                                size,
+                                MaybeCompatible::Compatible(BoundedGraphSize {
+                                    truncated: true,
+                                    ..
+                                })
+                            );
+                            (Ok(size.map(|size| size.size)), truncated)
+                        }
+                        Err(e) => (Err(e.into()), false),
+                    }
+                }
+                None => (
+                    graph_size::get_configured_graph_size(ctx, providers_label.target())
+                        .await
+                        .map_err(|e| e.into()),
+                    false,
+                ),
+            };
 
             ConfiguredBuildEvent {
                 label: providers_label,
                 variant: ConfiguredBuildEventVariant::GraphSize {
                     configured_graph_size,
+                    truncated,
                 },
+                request_id: opts.request_id,
             }
-        }));
+        };
 
-        Ok(stream.boxed())
+        Ok(chain_graph_size(stream, graph_size))
     } else {
         Ok(stream.boxed())
     }
 }
-pub async fn materialize_artifact_group_owned(
-    ctx: &DiceComputations,
-    artifact_group: ArtifactGroup,
-    materialization_context: MaterializationContext,
-) -> anyhow::Result<ArtifactGroupValues> {
-    materialize_artifact_group(ctx, &artifact_group, &materialization_context).await
+
+/// The sub-target of `label` that outputs are built for, e.g. `[sub_target]`, or `None` for the
+/// default providers.
+fn provider_name(label: &ConfiguredProvidersLabel) -> Option<String> {
+    match label.name() {
+        ProvidersName::Default => None,
+        name @ ProvidersName::NonDefault(..) => Some(name.to_string()),
+    }
+}
+
+/// Add the artifacts `visit` visits (those of a `RunInfo` or `TestProvider`) to `outputs`, as
+/// outputs of `provider_type`. If visiting fails, nothing is added, and the outputs collected so
+/// far are left untouched.
+fn push_provider_outputs(
+    outputs: &mut Vec<(ArtifactGroup, BuildProviderType)>,
+    provider_type: BuildProviderType,
+    visit: impl FnOnce(&mut SimpleCommandLineArtifactVisitor) -> anyhow::Result<()>,
+) -> buck2_error::Result<()> {
+    let mut artifact_visitor = SimpleCommandLineArtifactVisitor::new();
+    visit(&mut artifact_visitor)?;
+    outputs.extend(
+        artifact_visitor
+            .inputs
+            .into_iter()
+            .map(|input| (input, provider_type.dupe())),
+    );
+    Ok(())
+}
+
+/// Drop `Default` and `DefaultOther` outputs whose paths don't match `filter`. Outputs which aren't
+/// a single artifact (e.g. transitive set projections) are kept.
+fn filter_default_outputs(
+    outputs: Vec<(ArtifactGroup, BuildProviderType)>,
+    filter: &PathFilter,
+    artifact_fs: &ArtifactFs,
+) -> anyhow::Result<Vec<(ArtifactGroup, BuildProviderType)>> {
+    let mut filtered = Vec::with_capacity(outputs.len());
+    for (output, provider_type) in outputs {
+        if let (
+            ArtifactGroup::Artifact(artifact),
+            BuildProviderType::Default | BuildProviderType::DefaultOther,
+        ) = (&output, &provider_type)
+        {
+            if !filter.is_match(&artifact.get_path().resolve(artifact_fs)?) {
+                continue;
+            }
+        }
+        filtered.push((output, provider_type));
+    }
+    Ok(filtered)
+}
+
+/// Append the event produced by `graph_size` to `stream`. The future is only polled once `stream`
+/// is exhausted and is owned by the returned stream, so dropping the stream (e.g. when `fail_fast`
+/// stops consuming it) cancels a graph size computation that is still running.
+fn chain_graph_size<'a>(
+    stream: impl Stream<Item = ConfiguredBuildEvent> + Send + 'a,
+    graph_size: impl Future<Output = ConfiguredBuildEvent> + Send + 'a,
+) -> BoxStream<'a, ConfiguredBuildEvent> {
+    stream.chain(futures::stream::once(graph_size)).boxed()
+}
+
+/// Drop outputs which appear more than once among the `Default` and `DefaultOther` outputs, keeping
+/// the first one. Since `Default` outputs are collected first, they take precedence.
+fn dedupe_default_outputs(outputs: &mut Vec<(ArtifactGroup, BuildProviderType)>) {
+    let mut seen = HashSet::new();
+    outputs.retain(|(output, provider_type)| match provider_type {
+        BuildProviderType::Default | BuildProviderType::DefaultOther => seen.insert(output.dupe()),
+        BuildProviderType::Run | BuildProviderType::Test => true,
+    });
 }
 
 #[derive(Clone, Allocative)]
 pub struct ProviderArtifacts {
     pub values: ArtifactGroupValues,
     pub provider_type: BuildProviderType,
+    /// How long it took to build (and materialize, if requested) these artifacts.
+    pub duration: Option<Duration>,
+    /// How much of `duration` was spent materializing these artifacts. Zero if materializations
+    /// were skipped.
+    pub materialization_duration: Duration,
+    /// The sub-target these artifacts were built for (e.g. `[sub_target]`), or `None` if they
+    /// come from the default providers of the target.
+    pub provider_name: Option<String>,
 }
 
 // what type of artifacts to build based on the provider it came from
 #[derive(Default, Clone)]
 pub struct ProvidersToBuild {
-    pub default: bool,
-    pub default_other: bool,
-    pub run: bool,
-    pub tests: bool,
+    pub(crate) default: bool,
+    pub(crate) default_other: bool,
+    pub(crate) run: bool,
+    pub(crate) tests: bool,
+}
+
+impl ProvidersToBuild {
+    /// Start from no provider category at all, and request the ones to build.
+    pub fn builder() -> ProvidersToBuildBuilder {
+        ProvidersToBuildBuilder(ProvidersToBuild::default())
+    }
+
+    /// Whether no provider category at all is requested.
+    pub fn is_empty(&self) -> bool {
+        let Self {
+            default,
+            default_other,
+            run,
+            tests,
+        } = *self;
+        !(default || default_other || run || tests)
+    }
+
+    // Not named after the fields, since `default` would shadow `Default::default`.
+
+    pub(crate) fn wants_default(&self) -> bool {
+        self.default
+    }
+
+    pub(crate) fn wants_default_other(&self) -> bool {
+        self.default_other
+    }
+
+    pub(crate) fn wants_run(&self) -> bool {
+        self.run
+    }
+
+    pub(crate) fn wants_tests(&self) -> bool {
+        self.tests
+    }
+}
+
+/// Builds a `ProvidersToBuild`, see `ProvidersToBuild::builder`.
+#[derive(Clone)]
+pub struct ProvidersToBuildBuilder(ProvidersToBuild);
+
+impl ProvidersToBuildBuilder {
+    /// Build the default outputs of `DefaultInfo`.
+    pub fn default(mut self) -> Self {
+        self.0.default = true;
+        self
+    }
+
+    /// Build the other outputs of `DefaultInfo`.
+    pub fn default_other(mut self) -> Self {
+        self.0.default_other = true;
+        self
+    }
+
+    /// Build what's needed to run the target, as per its `RunInfo`.
+    pub fn run(mut self) -> Self {
+        self.0.run = true;
+        self
+    }
+
+    /// Build what's needed to test the target, as per its test provider.
+    pub fn tests(mut self) -> Self {
+        self.0.tests = true;
+        self
+    }
+
+    /// Build every provider category.
+    pub fn all(self) -> Self {
+        self.default().default_other().run().tests()
+    }
+
+    pub fn build(self) -> ProvidersToBuild {
+        self.0
+    }
+}
+
+impl ProviderArtifacts {
+    /// Whether `other` is the same output of the same provider, e.g. because the same label was
+    /// built twice. Only the artifacts are compared, not their values or how long they took.
+    fn is_same_output(&self, other: &ProviderArtifacts) -> bool {
+        self.provider_type == other.provider_type
+            && self.provider_name == other.provider_name
+            && self
+                .values
+                .iter()
+                .map(|(artifact, _value)| artifact)
+                .eq(other.values.iter().map(|(artifact, _value)| artifact))
+    }
 }
 
 impl Debug for ProviderArtifacts {
@@ -482,6 +1905,9 @@ impl Debug for ProviderArtifacts {
         f.debug_struct("ProviderArtifacts")
             .field("values", &self.values.iter().collect::<Vec<_>>())
             .field("provider_type", &self.provider_type)
+            .field("duration", &self.duration)
+            .field("materialization_duration", &self.materialization_duration)
+            .field("provider_name", &self.provider_name)
             .finish()
     }
 }
@@ -491,36 +1917,207 @@ pub async fn materialize_artifact_group(
     artifact_group: &ArtifactGroup,
     materialization_context: &MaterializationContext,
 ) -> anyhow::Result<ArtifactGroupValues> {
+    let (values, _newly_enqueued) =
+        materialize_artifact_group_reporting(ctx, artifact_group, materialization_context).await?;
+    Ok(values)
+}
+
+/// Like `materialize_artifact_group`, but also returns the artifacts which this call enqueued for
+/// materialization. Artifacts already requested through the same `MaterializationContext` are not
+/// included.
+pub async fn materialize_artifact_group_reporting(
+    ctx: &DiceComputations,
+    artifact_group: &ArtifactGroup,
+    materialization_context: &MaterializationContext,
+) -> anyhow::Result<(ArtifactGroupValues, Vec<BuildArtifact>)> {
     let values = ctx.ensure_artifact_group(artifact_group).await?;
+    let newly_enqueued = materialize_values(ctx, &values, materialization_context).await?;
+    Ok((values, newly_enqueued))
+}
 
-    if let MaterializationContext::Materialize { map, force } = materialization_context {
-        future::try_join_all(values.iter().filter_map(|(artifact, _value)| {
-            match artifact.as_parts().0 {
-                BaseArtifactKind::Build(artifact) => {
-                    match map.entry(artifact.dupe()) {
-                        Entry::Vacant(v) => {
-                            // Ensure we won't request this artifact elsewhere, and proceed to request
-                            // it.
-                            v.insert(());
-                        }
-                        Entry::Occupied(..) => {
-                            // We've already requested this artifact, no use requesting it again.
-                            return None;
-                        }
-                    }
+/// Materialize the build artifacts in `values` as requested by `materialization_context`,
+/// returning those which this call enqueued.
+async fn materialize_values(
+    ctx: &DiceComputations,
+    values: &ArtifactGroupValues,
+    materialization_context: &MaterializationContext,
+) -> anyhow::Result<Vec<BuildArtifact>> {
+    let (map, force, on_materialization_error, matcher) = match materialization_context {
+        MaterializationContext::Skip => return Ok(Vec::new()),
+        MaterializationContext::Materialize {
+            map,
+            force,
+            on_materialization_error,
+        } => (map, force, on_materialization_error, None),
+        MaterializationContext::MaterializeMatching {
+            map,
+            force,
+            on_materialization_error,
+            matcher,
+        } => (map, force, on_materialization_error, Some(matcher)),
+    };
+
+    let claimed = ClaimedArtifacts {
+        map,
+        artifacts: claim_new_artifacts(
+            map,
+            values
+                .iter()
+                .map(|(artifact, _value)| artifact)
+                .filter(|artifact| is_matching_artifact(matcher, artifact)),
+        ),
+    };
+
+    let res = future::try_join_all(claimed.artifacts.iter().map(|artifact| {
+        retry_materialization(artifact, on_materialization_error.as_ref(), move || {
+            ctx.try_materialize_requested_artifact(artifact, *force)
+        })
+    }))
+    .await;
+    // Whether this succeeded or not, these artifacts were dealt with and must not be requested
+    // again.
+    let newly_enqueued = claimed.finish();
+    res.context("Failed to materialize artifacts")?;
+
+    Ok(newly_enqueued)
+}
+
+/// Artifacts claimed in a materialization map by `claim_new_artifacts`. If this is dropped before
+/// `finish` is called (i.e. the future materializing them was dropped, e.g. because the build was
+/// cancelled), they are released from the map, so that later requests materialize them.
+struct ClaimedArtifacts<'a> {
+    map: &'a DashMap<BuildArtifact, ()>,
+    artifacts: Vec<BuildArtifact>,
+}
+
+impl ClaimedArtifacts<'_> {
+    fn finish(mut self) -> Vec<BuildArtifact> {
+        mem::take(&mut self.artifacts)
+    }
+}
+
+impl Drop for ClaimedArtifacts<'_> {
+    fn drop(&mut self) {
+        for artifact in &self.artifacts {
+            self.map.remove(artifact);
+        }
+    }
+}
+
+/// Whether `artifact` should be materialized according to `matcher` (everything matches if there
+/// is no matcher). Source artifacts are never materialized, so they always match.
+fn is_matching_artifact(matcher: Option<&MaterializationMatcher>, artifact: &Artifact) -> bool {
+    match (matcher, artifact.as_parts().0) {
+        (Some(matcher), BaseArtifactKind::Build(artifact)) => matcher(artifact),
+        _ => true,
+    }
+}
 
-                    Some(ctx.try_materialize_requested_artifact(artifact, *force))
+/// Record the build artifacts among `artifacts` in `map`, returning those which were not in it
+/// already.
+fn claim_new_artifacts<'a>(
+    map: &DashMap<BuildArtifact, ()>,
+    artifacts: impl IntoIterator<Item = &'a Artifact>,
+) -> Vec<BuildArtifact> {
+    artifacts
+        .into_iter()
+        .filter_map(|artifact| match artifact.as_parts().0 {
+            BaseArtifactKind::Build(artifact) => {
+                match map.entry(artifact.dupe()) {
+                    Entry::Vacant(v) => {
+                        // Ensure we won't request this artifact elsewhere, and proceed to request
+                        // it.
+                        v.insert(());
+                        Some(artifact.dupe())
+                    }
+                    Entry::Occupied(..) => {
+                        // We've already requested this artifact, no use requesting it again.
+                        None
+                    }
                 }
-                BaseArtifactKind::Source(..) => None,
             }
-        }))
-        .await
-        .context("Failed to materialize artifacts")?;
+            BaseArtifactKind::Source(..) => None,
+        })
+        .collect()
+}
+
+/// How many times we retry materializing a requested artifact when that fails with a retryable
+/// error, regardless of what `on_error` decides.
+const MAX_MATERIALIZATION_RETRIES: u32 = 2;
+
+/// How many times we retry materializing a requested artifact because `on_error` asked to, after
+/// the automatic retries. Past that, the error is returned whatever `on_error` decides.
+const MAX_CALLBACK_MATERIALIZATION_RETRIES: u32 = 10;
+
+/// How long we wait before the first materialization retry. Doubles on every retry, up to
+/// `MAX_MATERIALIZATION_RETRY_BACKOFF`.
+const MATERIALIZATION_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+const MAX_MATERIALIZATION_RETRY_BACKOFF: Duration = Duration::from_millis(800);
+
+fn materialization_retry_backoff(retries: u32) -> Duration {
+    MATERIALIZATION_RETRY_BACKOFF
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_MATERIALIZATION_RETRY_BACKOFF)
+}
+
+/// Run `f` until it succeeds, or until it fails and `on_error` (if any) decides not to retry.
+/// Retryable errors (see `buck2_error::Error::is_retryable`) are first retried with backoff up
+/// to `MAX_MATERIALIZATION_RETRIES` times without consulting `on_error`. After that, `on_error`
+/// can ask for up to `MAX_CALLBACK_MATERIALIZATION_RETRIES` more retries, also with backoff.
+async fn retry_materialization<T, Fut>(
+    artifact: &BuildArtifact,
+    on_error: Option<&MaterializationErrorCallback>,
+    mut f: impl FnMut() -> Fut,
+) -> anyhow::Result<T>
+where
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut retries = 0;
+    let mut callback_retries = 0;
+    loop {
+        let err = match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) => buck2_error::Error::from(e),
+        };
+
+        if retries < MAX_MATERIALIZATION_RETRIES && err.is_retryable() {
+            tokio::time::sleep(materialization_retry_backoff(retries)).await;
+            retries += 1;
+            continue;
+        }
+
+        let err = anyhow::Error::from(err);
+        match on_error.map(|on_error| on_error(artifact, &err)) {
+            Some(RetryDecision::Retry)
+                if callback_retries < MAX_CALLBACK_MATERIALIZATION_RETRIES =>
+            {
+                tokio::time::sleep(materialization_retry_backoff(callback_retries)).await;
+                callback_retries += 1;
+            }
+            Some(RetryDecision::Retry | RetryDecision::Fail) | None => return Err(err),
+        }
     }
+}
 
-    Ok(values)
+/// What to do after a requested artifact failed to materialize.
+#[derive(Clone, Copy, Dupe, Debug, Eq, PartialEq)]
+pub enum RetryDecision {
+    /// Try to materialize the artifact again.
+    Retry,
+    /// Give up, failing with the error that was just reported.
+    Fail,
 }
 
+/// Called every time materializing a requested artifact fails, to decide whether to retry. Only
+/// a bounded number of retries is honored, so returning `Retry` unconditionally is safe.
+pub type MaterializationErrorCallback =
+    Arc<dyn Fn(&BuildArtifact, &anyhow::Error) -> RetryDecision + Send + Sync>;
+
+/// Decides which requested artifacts to materialize, see
+/// `MaterializationContext::MaterializeMatching`.
+pub type MaterializationMatcher = Arc<dyn Fn(&BuildArtifact) -> bool + Send + Sync>;
+
 #[derive(Clone, Dupe)]
 pub enum MaterializationContext {
     Skip,
@@ -531,15 +2128,71 @@ pub enum MaterializationContext {
         /// Whether we should force the materialization of requested artifacts, or defer to the
         /// config.
         force: bool,
+        /// If set, consulted when materializing a requested artifact fails. Without it, failures
+        /// are never retried.
+        on_materialization_error: Option<MaterializationErrorCallback>,
+    },
+    /// Like `Materialize`, but only materialize the requested artifacts accepted by `matcher`. The
+    /// others are still built.
+    MaterializeMatching {
+        map: Arc<DashMap<BuildArtifact, ()>>,
+        force: bool,
+        on_materialization_error: Option<MaterializationErrorCallback>,
+        matcher: MaterializationMatcher,
     },
 }
 
 impl MaterializationContext {
     /// Create a new MaterializationContext that will force all materializations.
     pub fn force_materializations() -> Self {
+        Self::force_materializations_with_map(&Arc::new(DashMap::new()))
+    }
+
+    /// Like `force_materializations`, but share `map` with the rest of a session, so that artifacts
+    /// that are already enqueued for materialization there aren't enqueued again.
+    pub fn force_materializations_with_map(map: &Arc<DashMap<BuildArtifact, ()>>) -> Self {
         Self::Materialize {
-            map: Arc::new(DashMap::new()),
+            map: map.dupe(),
             force: true,
+            on_materialization_error: None,
+        }
+    }
+
+    /// The artifacts enqueued for materialization so far, in no particular order. This includes
+    /// those enqueued by anything else sharing the map of this context.
+    pub fn enqueued_artifacts(&self) -> Vec<BuildArtifact> {
+        match self {
+            Self::Skip => Vec::new(),
+            Self::Materialize { map, .. } | Self::MaterializeMatching { map, .. } => {
+                map.iter().map(|entry| entry.key().dupe()).collect()
+            }
+        }
+    }
+
+    /// Consult `callback` whenever materializing a requested artifact fails. This has no effect if
+    /// materializations are skipped.
+    pub fn with_materialization_error_callback(
+        self,
+        callback: MaterializationErrorCallback,
+    ) -> Self {
+        match self {
+            Self::Skip => Self::Skip,
+            Self::Materialize { map, force, .. } => Self::Materialize {
+                map,
+                force,
+                on_materialization_error: Some(callback),
+            },
+            Self::MaterializeMatching {
+                map,
+                force,
+                matcher,
+                ..
+            } => Self::MaterializeMatching {
+                map,
+                force,
+                on_materialization_error: Some(callback),
+                matcher,
+            },
         }
     }
 }
@@ -557,10 +2210,12 @@ impl ConvertMaterializationContext for Materializations {
             Materializations::Default => MaterializationContext::Materialize {
                 map: Arc::new(DashMap::new()),
                 force: false,
+                on_materialization_error: None,
             },
             Materializations::Materialize => MaterializationContext::Materialize {
                 map: Arc::new(DashMap::new()),
                 force: true,
+                on_materialization_error: None,
             },
         }
     }
@@ -571,10 +2226,12 @@ impl ConvertMaterializationContext for Materializations {
             Materializations::Default => MaterializationContext::Materialize {
                 map: map.dupe(),
                 force: false,
+                on_materialization_error: None,
             },
             Materializations::Materialize => MaterializationContext::Materialize {
                 map: map.dupe(),
                 force: true,
+                on_materialization_error: None,
             },
         }
     }
@@ -598,3 +2255,1785 @@ impl HasCreateUnhashedSymlinkLock for UserComputationData {
             .dupe()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::collections::BTreeSet;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+    use buck2_artifact::artifact::artifact_type::Artifact;
+    use buck2_artifact::artifact::build_artifact::BuildArtifact;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_common::file_ops::FileMetadata;
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::configuration::compatibility::IncompatiblePlatformReason;
+    use buck2_core::configuration::compatibility::IncompatiblePlatformReasonCause;
+    use buck2_core::configuration::compatibility::MaybeCompatible;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+    use buck2_core::fs::buck_out_path::BuckOutPathResolver;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::fs::project::ProjectRoot;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+    use buck2_core::provider::label::ConfiguredProvidersLabel;
+    use buck2_core::provider::label::NonDefaultProvidersName;
+    use buck2_core::provider::label::ProviderName;
+    use buck2_core::provider::label::ProvidersName;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use buck2_core::target::label::TargetLabel;
+    use buck2_error::Category;
+    use buck2_events::create_source_sink_pair;
+    use buck2_events::dispatch::with_dispatcher;
+    use buck2_events::dispatch::EventDispatcher;
+    use buck2_execute::artifact_value::ArtifactValue;
+    use buck2_execute::digest_config::DigestConfig;
+    use buck2_wrapper_common::invocation_id::TraceId;
+    use dashmap::DashMap;
+    use dupe::Dupe;
+    use futures::future;
+    use futures::StreamExt;
+    use prost::Message;
+    use smallvec::smallvec;
+
+    use crate::artifact_groups::ArtifactGroup;
+    use crate::artifact_groups::ArtifactGroupValues;
+    use crate::build::chain_graph_size;
+    use crate::build::claim_new_artifacts;
+    use crate::build::dedupe_outputs;
+    use crate::build::default_providers_label;
+    use crate::build::is_matching_artifact;
+    use crate::build::provider_name;
+    use crate::build::retry_materialization;
+    use crate::build::skipped_incompatible;
+    use crate::build::BuildConfiguredLabelOptions;
+    use crate::build::BuildEvent;
+    use crate::build::BuildProviderType;
+    use crate::build::BuildTargetResult;
+    use crate::build::ConfiguredBuildEvent;
+    use crate::build::ConfiguredBuildEventVariant;
+    use crate::build::ConfiguredBuildTargetResult;
+    use crate::build::FailurePolicy;
+    use crate::build::MaterializationContext;
+    use crate::build::MaterializationErrorCallback;
+    use crate::build::MaterializationMatcher;
+    use crate::build::MessageSink;
+    use crate::build::OtherError;
+    use crate::build::ProviderArtifacts;
+    use crate::build::ProvidersToBuild;
+    use crate::build::RetryDecision;
+    use crate::build::TargetBuildStatus;
+    use crate::build::WantConfiguredGraphSize;
+    use crate::build::MATERIALIZATION_RETRY_BACKOFF;
+    use crate::build::MAX_CALLBACK_MATERIALIZATION_RETRIES;
+    use crate::build::MAX_MATERIALIZATION_RETRIES;
+
+    fn label(target: &str) -> ConfiguredProvidersLabel {
+        ConfiguredProvidersLabel::default_for(ConfiguredTargetLabel::testing_parse(
+            target,
+            ConfigurationData::testing_new(),
+        ))
+    }
+
+    fn artifact(owner: &str, path: &str) -> Artifact {
+        Artifact::from(BuildArtifact::testing_new(
+            ConfiguredTargetLabel::testing_parse(owner, ConfigurationData::testing_new()),
+            ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+            DeferredId::testing_new(0),
+        ))
+    }
+
+    fn other_error(error: buck2_error::Error) -> OtherError {
+        OtherError {
+            error,
+            received_after: Duration::ZERO,
+        }
+    }
+
+    fn output(owner: &str, path: &str, provider_type: BuildProviderType) -> ProviderArtifacts {
+        let value = ArtifactValue::file(DigestConfig::testing_default().empty_file());
+        ProviderArtifacts {
+            values: ArtifactGroupValues::from_artifact(artifact(owner, path), value),
+            provider_type,
+            duration: None,
+            materialization_duration: Duration::ZERO,
+            provider_name: None,
+        }
+    }
+
+    fn target_result(outputs: Vec<ProviderArtifacts>) -> Option<ConfiguredBuildTargetResult> {
+        Some(ConfiguredBuildTargetResult {
+            status: if outputs.is_empty() {
+                TargetBuildStatus::BuiltNoOutputs
+            } else {
+                TargetBuildStatus::Built
+            },
+            outputs: outputs.into_iter().map(Ok).collect(),
+            run_args: None,
+            target_rule_type_name: None,
+            configured_graph_size: None,
+            configured_graph_size_truncated: false,
+            built_provider_types: BTreeSet::new(),
+            analysis_duration: None,
+            materialization_duration: Duration::ZERO,
+            errors: Vec::new(),
+            resolved_outputs: Vec::new(),
+        })
+    }
+
+    fn output_paths<'a>(outputs: impl Iterator<Item = &'a ProviderArtifacts>) -> Vec<String> {
+        outputs
+            .flat_map(|output| output.values.iter())
+            .map(|(artifact, _value)| artifact.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_unique_outputs() {
+        let result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (
+                    label("cell//pkg:a"),
+                    target_result(vec![
+                        output("cell//pkg:gen", "header.h", BuildProviderType::Default),
+                        output("cell//pkg:a", "a.o", BuildProviderType::Default),
+                    ]),
+                ),
+                (
+                    label("cell//pkg:b"),
+                    target_result(vec![
+                        output("cell//pkg:gen", "header.h", BuildProviderType::Default),
+                        output("cell//pkg:b", "b.o", BuildProviderType::Default),
+                    ]),
+                ),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+
+        let all = result
+            .configured
+            .values()
+            .flatten()
+            .flat_map(|result| result.outputs.iter())
+            .map(|output| output.as_ref().unwrap());
+        assert_eq!(4, output_paths(all).len());
+
+        let unique = output_paths(result.unique_outputs());
+        assert_eq!(3, unique.len(), "{:?}", unique);
+        assert_eq!(1, unique.iter().filter(|p| p.contains("header.h")).count());
+    }
+
+    #[test]
+    fn test_unique_outputs_partial_overlap() -> anyhow::Result<()> {
+        let value = || ArtifactValue::file(DigestConfig::testing_default().empty_file());
+        // An output which contains the shared header along with another artifact.
+        let group = ArtifactGroupValues::new(
+            smallvec![
+                (artifact("cell//pkg:gen", "header.h"), value()),
+                (artifact("cell//pkg:c", "c.o"), value()),
+            ],
+            Vec::new(),
+            &artifact_fs()?,
+            DigestConfig::testing_default(),
+        )?;
+        let result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (
+                    label("cell//pkg:a"),
+                    target_result(vec![output(
+                        "cell//pkg:gen",
+                        "header.h",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+                (
+                    label("cell//pkg:c"),
+                    target_result(vec![ProviderArtifacts {
+                        values: group,
+                        ..output("cell//pkg:c", "unused", BuildProviderType::Default)
+                    }]),
+                ),
+                // Only has an artifact that is already part of the output of `c`.
+                (
+                    label("cell//pkg:d"),
+                    target_result(vec![output(
+                        "cell//pkg:c",
+                        "c.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+
+        // The output of `c` partially overlaps with that of `a`, so it is still returned whole,
+        // but the output of `d` is entirely covered by it.
+        let unique = result.unique_outputs().collect::<Vec<_>>();
+        assert_eq!(2, unique.len(), "{:?}", unique);
+        let paths = output_paths(unique.into_iter());
+        assert_eq!(2, paths.iter().filter(|p| p.contains("header.h")).count());
+        assert_eq!(1, paths.iter().filter(|p| p.contains("c.o")).count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() {
+        let built = |rule_type: Option<&str>| {
+            target_result(vec![output(
+                "cell//pkg:a",
+                "a.o",
+                BuildProviderType::Default,
+            )])
+            .map(|result| ConfiguredBuildTargetResult {
+                target_rule_type_name: rule_type.map(str::to_owned),
+                ..result
+            })
+        };
+
+        let mut result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (label("cell//pkg:a"), built(Some("foo_binary"))),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+        assert_eq!(Ok(()), result.validate());
+
+        // The same target is also skipped under another providers label.
+        let a_sub = ConfiguredProvidersLabel::new(
+            label("cell//pkg:a").target().dupe(),
+            ProvidersName::NonDefault(Box::new(NonDefaultProvidersName::Named(Box::new([
+                ProviderName::new("sub".to_owned()).unwrap(),
+            ])))),
+        );
+        result.configured.insert(a_sub, None);
+        // An output was recorded without `Prepared`.
+        result.configured.insert(label("cell//pkg:b"), built(None));
+        result.other_errors.insert(None, Vec::new());
+
+        assert_eq!(
+            Err(vec![
+                format!(
+                    "`{}` is both skipped and built",
+                    label("cell//pkg:a").target()
+                ),
+                format!("`{}` has outputs but no rule type", label("cell//pkg:b")),
+                "Empty list of unlabelled errors".to_owned(),
+            ]),
+            result.validate()
+        );
+    }
+
+    fn artifact_fs() -> anyhow::Result<ArtifactFs> {
+        Ok(ArtifactFs::new(
+            CellResolver::testing_with_name_and_path(
+                CellName::testing_new("cell"),
+                CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new("cell_path".into())),
+            ),
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new("buck_out".into())),
+            ProjectRoot::new(AbsNormPathBuf::try_from(std::env::current_dir()?)?)?,
+        ))
+    }
+
+    #[test]
+    fn test_to_proto() -> anyhow::Result<()> {
+        let fs = artifact_fs()?;
+
+        let user_error = || {
+            buck2_error::Error::from(anyhow::anyhow!("bad target"))
+                .context(buck2_error::Category::User)
+        };
+        let mut failed = target_result(vec![output("cell//pkg:b", "b.o", BuildProviderType::Run)]);
+        if let Some(failed) = &mut failed {
+            failed.outputs.push(Err(user_error()));
+        }
+        let result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (
+                    label("cell//pkg:a"),
+                    target_result(vec![output(
+                        "cell//pkg:a",
+                        "a.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+                (label("cell//pkg:b"), failed),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::from([(None, vec![other_error(user_error())])]),
+        };
+
+        let proto = result.to_proto(&fs)?;
+        let [a, b, skipped] = proto.targets.as_slice() else {
+            panic!("expected 3 targets, got {:?}", proto.targets);
+        };
+
+        assert_eq!(label("cell//pkg:a").to_string(), a.label);
+        assert_eq!(
+            buck2_data::BuildTargetStatus::BuildTargetSucceeded as i32,
+            a.status
+        );
+        assert_eq!(1, a.outputs.len());
+        assert!(
+            a.outputs[0].path.starts_with("buck_out/"),
+            "{}",
+            a.outputs[0].path
+        );
+        assert!(a.outputs[0].path.ends_with("/a.o"), "{}", a.outputs[0].path);
+        assert_eq!(
+            buck2_data::BuildProviderType::BuildProviderDefault as i32,
+            a.outputs[0].provider_type
+        );
+        assert!(a.errors.is_empty());
+
+        assert_eq!(
+            buck2_data::BuildTargetStatus::BuildTargetFailed as i32,
+            b.status
+        );
+        assert_eq!(
+            buck2_data::BuildProviderType::BuildProviderRun as i32,
+            b.outputs[0].provider_type
+        );
+        assert_eq!(1, b.errors.len());
+        assert_eq!(
+            Some(buck2_data::error::ErrorCategory::User as i32),
+            b.errors[0].category
+        );
+        assert!(b.errors[0].message.contains("bad target"));
+
+        assert_eq!(label("cell//pkg:skipped").to_string(), skipped.label);
+        assert_eq!(
+            buck2_data::BuildTargetStatus::BuildTargetSkipped as i32,
+            skipped.status
+        );
+        assert!(skipped.outputs.is_empty());
+
+        assert_eq!(1, proto.other_errors.len());
+        assert_eq!(None, proto.other_errors[0].label);
+        assert_eq!(1, proto.other_errors[0].errors.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_proto_round_trip() -> anyhow::Result<()> {
+        let mut run = target_result(vec![output("cell//pkg:run", "bin", BuildProviderType::Run)]);
+        if let Some(run) = &mut run {
+            run.run_args = Some(vec!["bin".to_owned(), "--flag".to_owned()]);
+            run.configured_graph_size = Some(Ok(MaybeCompatible::Compatible(17)));
+        }
+        let result = BuildTargetResult {
+            // Inserted out of order, to check the targets come out sorted.
+            configured: BTreeMap::from([
+                (label("cell//pkg:skipped"), None),
+                (label("cell//pkg:run"), run),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+
+        let proto = result.to_proto(&artifact_fs()?)?;
+        let decoded = buck2_data::BuildResult::decode(proto.encode_to_vec().as_slice())?;
+        assert_eq!(proto, decoded);
+
+        let [run, skipped] = decoded.targets.as_slice() else {
+            panic!("expected 2 targets, got {:?}", decoded.targets);
+        };
+        assert_eq!(label("cell//pkg:run").to_string(), run.label);
+        assert_eq!(vec!["bin".to_owned(), "--flag".to_owned()], run.run_args);
+        assert_eq!(Some(17), run.configured_graph_size);
+        assert_eq!(label("cell//pkg:skipped").to_string(), skipped.label);
+        assert_eq!(
+            buck2_data::BuildTargetStatus::BuildTargetSkipped as i32,
+            skipped.status
+        );
+        assert!(skipped.run_args.is_empty());
+        assert_eq!(None, skipped.configured_graph_size);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_counts_by_category() {
+        let error = |category: Option<Category>| {
+            let error = buck2_error::Error::from(anyhow::anyhow!("error"));
+            match category {
+                Some(category) => error.context(category),
+                None => error,
+            }
+        };
+
+        let mut failed = target_result(vec![]).unwrap();
+        failed.outputs = vec![
+            Err(error(Some(Category::User))),
+            Ok(output("cell//pkg:a", "a.o", BuildProviderType::Default)),
+        ];
+        failed.errors = vec![error(Some(Category::Infra)), error(None)];
+
+        let result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (label("cell//pkg:a"), Some(failed)),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::from([
+                (None, vec![other_error(error(Some(Category::User)))]),
+                (
+                    Some(label("cell//pkg:b").unconfigured()),
+                    vec![
+                        other_error(error(Some(Category::Infra))),
+                        other_error(error(None)),
+                    ],
+                ),
+            ]),
+        };
+
+        assert_eq!(
+            BTreeMap::from([
+                (None, 2),
+                (Some(Category::User), 2),
+                (Some(Category::Infra), 2),
+            ]),
+            result.error_counts_by_category()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_stream_with_callback() {
+        let event = |target: &str, variant| {
+            BuildEvent::Configured(ConfiguredBuildEvent {
+                label: Arc::new(label(target)),
+                variant,
+                request_id: None,
+            })
+        };
+        let prepared = |target: &str| {
+            event(
+                target,
+                ConfiguredBuildEventVariant::Prepared {
+                    run_args: None,
+                    target_rule_type_name: "foo_binary".to_owned(),
+                    output_count: 2,
+                    analysis_duration: Duration::ZERO,
+                },
+            )
+        };
+        let built = |target: &str, path: &str, index| {
+            event(
+                target,
+                ConfiguredBuildEventVariant::Output {
+                    output: Ok(output(target, path, BuildProviderType::Default)),
+                    index,
+                },
+            )
+        };
+        // The outputs of `b` and `a` interleave, and `c` is skipped.
+        let events = vec![
+            prepared("cell//pkg:b"),
+            prepared("cell//pkg:a"),
+            built("cell//pkg:b", "b1.o", 1),
+            built("cell//pkg:a", "a0.o", 0),
+            event(
+                "cell//pkg:c",
+                ConfiguredBuildEventVariant::SkippedIncompatible,
+            ),
+            built("cell//pkg:b", "b0.o", 0),
+            built("cell//pkg:a", "a1.o", 1),
+        ];
+
+        let mut seen = Vec::new();
+        let result = BuildTargetResult::collect_stream_with_callback(
+            futures::stream::iter(events),
+            FailurePolicy::Never,
+            |label, result| seen.push((label.to_string(), result.outputs.len())),
+        )
+        .await
+        .unwrap();
+
+        // Once per built label, in label order, with all of its outputs.
+        assert_eq!(
+            vec![
+                (label("cell//pkg:a").to_string(), 2),
+                (label("cell//pkg:b").to_string(), 2),
+            ],
+            seen
+        );
+        assert_eq!(3, result.configured.len());
+    }
+
+    #[tokio::test]
+    async fn test_failure_policy() {
+        let label = Arc::new(label("cell//pkg:a"));
+        let event = |variant| {
+            BuildEvent::Configured(ConfiguredBuildEvent {
+                label: label.dupe(),
+                variant,
+                request_id: None,
+            })
+        };
+        let failed_output = |index| {
+            event(ConfiguredBuildEventVariant::Output {
+                output: Err(anyhow::anyhow!("output failed").into()),
+                index,
+            })
+        };
+        let events = || {
+            vec![
+                event(ConfiguredBuildEventVariant::Prepared {
+                    run_args: None,
+                    target_rule_type_name: "foo_binary".to_owned(),
+                    output_count: 3,
+                    analysis_duration: Duration::ZERO,
+                }),
+                failed_output(0),
+                event(ConfiguredBuildEventVariant::Output {
+                    output: Ok(output("cell//pkg:a", "a.o", BuildProviderType::Default)),
+                    index: 1,
+                }),
+                failed_output(2),
+                event(ConfiguredBuildEventVariant::Error {
+                    err: anyhow::anyhow!("failed").into(),
+                }),
+            ]
+        };
+        // Returns how many outputs and errors were collected.
+        let collected = |policy| {
+            let events = events();
+            let label = label.dupe();
+            async move {
+                let result = BuildTargetResult::collect_stream_with_policy(
+                    futures::stream::iter(events),
+                    policy,
+                    false,
+                )
+                .await
+                .unwrap();
+                let result = result.configured[&*label].as_ref().unwrap();
+                (result.outputs.len(), result.errors.len())
+            }
+        };
+
+        assert_eq!((1, 0), collected(FailurePolicy::FailFast).await);
+        assert_eq!((1, 0), collected(FailurePolicy::FailAfter(1)).await);
+        // The successful output doesn't count towards the threshold.
+        assert_eq!((3, 0), collected(FailurePolicy::FailAfter(2)).await);
+        assert_eq!((3, 1), collected(FailurePolicy::FailAfter(3)).await);
+        assert_eq!((3, 1), collected(FailurePolicy::Never).await);
+    }
+
+    #[test]
+    fn test_run_args() {
+        let run_args = |args: &[&str]| {
+            let mut result = target_result(Vec::new());
+            result.as_mut().unwrap().run_args =
+                Some(args.iter().map(|arg| (*arg).to_owned()).collect());
+            result
+        };
+        let result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (label("cell//pkg:bin"), run_args(&["bin", "--flag"])),
+                (label("cell//pkg:lib"), target_result(Vec::new())),
+                (label("cell//pkg:other_bin"), run_args(&["other_bin"])),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            Some(&["bin".to_owned(), "--flag".to_owned()][..]),
+            result.run_args_for(&label("cell//pkg:bin"))
+        );
+        assert_eq!(None, result.run_args_for(&label("cell//pkg:lib")));
+        assert_eq!(None, result.run_args_for(&label("cell//pkg:skipped")));
+        assert_eq!(None, result.run_args_for(&label("cell//pkg:missing")));
+
+        assert_eq!(
+            vec![
+                (
+                    &label("cell//pkg:bin"),
+                    &["bin".to_owned(), "--flag".to_owned()][..]
+                ),
+                (&label("cell//pkg:other_bin"), &["other_bin".to_owned()][..]),
+            ],
+            result.all_run_args().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_merge_skipped_and_built() {
+        let mut result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (label("cell//pkg:a"), None),
+                (
+                    label("cell//pkg:b"),
+                    target_result(vec![output(
+                        "cell//pkg:b",
+                        "b.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+        result.merge(BuildTargetResult {
+            configured: BTreeMap::from([
+                (
+                    label("cell//pkg:a"),
+                    target_result(vec![output(
+                        "cell//pkg:a",
+                        "a.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+                (label("cell//pkg:b"), None),
+                (label("cell//pkg:c"), None),
+            ]),
+            other_errors: BTreeMap::new(),
+        });
+
+        // Built results win over skipped ones, whichever side they are on.
+        for (target, path) in [("cell//pkg:a", "a.o"), ("cell//pkg:b", "b.o")] {
+            let built = result.configured[&label(target)]
+                .as_ref()
+                .expect("built results should be kept");
+            assert_eq!(
+                vec![format!("{}", artifact(target, path))],
+                output_paths(built.outputs.iter().map(|output| output.as_ref().unwrap()))
+            );
+        }
+        assert!(result.configured[&label("cell//pkg:c")].is_none());
+    }
+
+    #[test]
+    fn test_merge_concatenates_errors() {
+        let failed = |err: &str| {
+            let mut result = target_result(vec![output(
+                "cell//pkg:a",
+                "a.o",
+                BuildProviderType::Default,
+            )]);
+            result
+                .as_mut()
+                .unwrap()
+                .errors
+                .push(anyhow::anyhow!(err.to_owned()).into());
+            result
+        };
+        let mut result = BuildTargetResult {
+            configured: BTreeMap::from([(label("cell//pkg:a"), target_result(Vec::new()))]),
+            other_errors: BTreeMap::from([(
+                None,
+                vec![other_error(anyhow::anyhow!("first").into())],
+            )]),
+        };
+        result.merge(BuildTargetResult {
+            configured: BTreeMap::from([(label("cell//pkg:a"), failed("second"))]),
+            other_errors: BTreeMap::from([
+                (None, vec![other_error(anyhow::anyhow!("second").into())]),
+                (
+                    Some(label("cell//pkg:b").unconfigured()),
+                    vec![other_error(anyhow::anyhow!("b").into())],
+                ),
+            ]),
+        });
+
+        let a = result.configured[&label("cell//pkg:a")].as_ref().unwrap();
+        assert_eq!(1, a.outputs.len());
+        assert_eq!(
+            vec!["second"],
+            a.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(TargetBuildStatus::Failed, a.status);
+
+        assert_eq!(
+            vec!["first", "second"],
+            result.other_errors[&None]
+                .iter()
+                .map(|e| e.error.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(2, result.other_errors.len());
+    }
+
+    #[test]
+    fn test_merge_combines_built_results() {
+        let timed = |path: &str, provider_type: BuildProviderType, millis: u64| ProviderArtifacts {
+            materialization_duration: Duration::from_millis(millis),
+            ..output("cell//pkg:a", path, provider_type)
+        };
+        let built = |outputs: Vec<ProviderArtifacts>, graph_size: Option<u64>, truncated: bool| {
+            let mut result = target_result(outputs).unwrap();
+            result.built_provider_types = result
+                .outputs
+                .iter()
+                .map(|output| output.as_ref().unwrap().provider_type.dupe())
+                .collect();
+            result.materialization_duration = result
+                .outputs
+                .iter()
+                .map(|output| output.as_ref().unwrap().materialization_duration)
+                .sum();
+            result.configured_graph_size =
+                graph_size.map(|size| Ok(MaybeCompatible::Compatible(size)));
+            result.configured_graph_size_truncated = truncated;
+            Some(result)
+        };
+
+        let mut result = BuildTargetResult {
+            configured: BTreeMap::from([(
+                label("cell//pkg:a"),
+                built(
+                    vec![
+                        timed("a.o", BuildProviderType::Default, 10),
+                        timed("a.test", BuildProviderType::Test, 20),
+                    ],
+                    Some(5),
+                    true,
+                ),
+            )]),
+            other_errors: BTreeMap::new(),
+        };
+        result.merge(BuildTargetResult {
+            configured: BTreeMap::from([(
+                label("cell//pkg:a"),
+                built(
+                    vec![
+                        timed("a.o", BuildProviderType::Default, 10),
+                        timed("a.run", BuildProviderType::Run, 5),
+                    ],
+                    Some(7),
+                    false,
+                ),
+            )]),
+            other_errors: BTreeMap::new(),
+        });
+
+        let a = result.configured[&label("cell//pkg:a")].as_ref().unwrap();
+        // The shared output is kept once, and outputs are in provider type order.
+        assert_eq!(
+            vec![
+                format!("{}", artifact("cell//pkg:a", "a.o")),
+                format!("{}", artifact("cell//pkg:a", "a.run")),
+                format!("{}", artifact("cell//pkg:a", "a.test")),
+            ],
+            output_paths(a.outputs.iter().map(|output| output.as_ref().unwrap()))
+        );
+        assert_eq!(
+            BTreeSet::from([
+                BuildProviderType::Default,
+                BuildProviderType::Run,
+                BuildProviderType::Test,
+            ]),
+            a.built_provider_types
+        );
+        assert_eq!(Duration::from_millis(35), a.materialization_duration);
+        // The exact graph size is preferred over a truncated one.
+        assert!(matches!(
+            a.configured_graph_size,
+            Some(Ok(MaybeCompatible::Compatible(7)))
+        ));
+        assert!(!a.configured_graph_size_truncated);
+        assert_eq!(TargetBuildStatus::Built, a.status);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let file = |content: &[u8]| {
+            ArtifactValue::file(FileMetadata {
+                digest: TrackedFileDigest::from_content(
+                    content,
+                    DigestConfig::testing_default().cas_digest_config(),
+                ),
+                is_executable: false,
+            })
+        };
+        let result = |content: &[u8], millis| BuildTargetResult {
+            configured: BTreeMap::from([
+                (
+                    label("cell//pkg:a"),
+                    target_result(vec![ProviderArtifacts {
+                        values: ArtifactGroupValues::from_artifact(
+                            artifact("cell//pkg:a", "a.o"),
+                            file(content),
+                        ),
+                        duration: Some(Duration::from_millis(millis)),
+                        ..output("cell//pkg:a", "a.o", BuildProviderType::Default)
+                    }]),
+                ),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+
+        // Durations don't matter.
+        assert_eq!(
+            result(b"a", 1).fingerprint(),
+            result(b"a", 1000).fingerprint()
+        );
+        assert_ne!(result(b"a", 1).fingerprint(), result(b"b", 1).fingerprint());
+
+        let mut failed = result(b"a", 1);
+        if let Some(Some(a)) = failed.configured.get_mut(&label("cell//pkg:a")) {
+            a.status = TargetBuildStatus::Failed;
+        }
+        assert_ne!(result(b"a", 1).fingerprint(), failed.fingerprint());
+    }
+
+    #[tokio::test]
+    async fn test_other_error_timing() {
+        let other = |message: &'static str| BuildEvent::OtherError {
+            label: None,
+            err: anyhow::anyhow!(message).into(),
+        };
+        let label = Arc::new(label("cell//pkg:a"));
+        let events = vec![
+            other("setup"),
+            BuildEvent::Configured(ConfiguredBuildEvent {
+                label: label.dupe(),
+                variant: ConfiguredBuildEventVariant::Prepared {
+                    run_args: None,
+                    target_rule_type_name: "foo_binary".to_owned(),
+                    output_count: 0,
+                    analysis_duration: Duration::ZERO,
+                },
+                request_id: None,
+            }),
+            other("mid-build"),
+            other("finish"),
+        ];
+        let events = futures::stream::iter(events)
+            .then(|event| async move {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                event
+            })
+            .boxed();
+
+        let result = BuildTargetResult::collect_stream(events, false)
+            .await
+            .unwrap();
+        let errors = &result.other_errors[&None];
+        assert_eq!(
+            vec!["setup", "mid-build", "finish"],
+            errors
+                .iter()
+                .map(|e| format!("{:#}", e.error))
+                .collect::<Vec<_>>()
+        );
+        assert!(errors[0].received_after > Duration::ZERO);
+        assert!(
+            errors
+                .windows(2)
+                .all(|w| w[0].received_after < w[1].received_after),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dedupe_other_errors() {
+        let events = || {
+            let error = |target: &str, message: &str| BuildEvent::OtherError {
+                label: Some(label(target).unconfigured()),
+                err: anyhow::anyhow!(message.to_owned()).into(),
+            };
+            vec![
+                error("cell//pkg:a", "package failed"),
+                error("cell//pkg:a", "other"),
+                error("cell//pkg:a", "package failed"),
+                error("cell//pkg:b", "package failed"),
+                error("cell//pkg:a", "package failed"),
+            ]
+        };
+        let collected = |dedupe| async move {
+            let result = BuildTargetResult::collect_stream_with_policy(
+                futures::stream::iter(events()),
+                FailurePolicy::Never,
+                dedupe,
+            )
+            .await
+            .unwrap();
+            result
+                .other_errors
+                .iter()
+                .map(|(label, errors)| {
+                    (
+                        label.as_ref().unwrap().to_string(),
+                        errors
+                            .iter()
+                            .map(|e| e.error.to_string())
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Only the first of the identical errors for `a` survives, in the order first seen. The
+        // same error for another label is kept.
+        assert_eq!(
+            vec![
+                (
+                    "cell//pkg:a".to_owned(),
+                    vec!["package failed".to_owned(), "other".to_owned()]
+                ),
+                ("cell//pkg:b".to_owned(), vec!["package failed".to_owned()]),
+            ],
+            collected(true).await
+        );
+
+        let raw = collected(false).await;
+        assert_eq!(4, raw[0].1.len());
+    }
+
+    #[test]
+    fn test_coalesced_other_errors() {
+        let error = |message: &'static str| {
+            buck2_error::Error::from(anyhow::anyhow!(message)).context(Category::User)
+        };
+        let labels = ["cell//pkg:a", "cell//pkg:b", "cell//pkg:c"]
+            .map(|target| Some(label(target).unconfigured()));
+
+        let mut other_errors: BTreeMap<_, _> = labels
+            .iter()
+            .map(|label| (label.clone(), vec![other_error(error("dep failed"))]))
+            .collect();
+        other_errors.insert(
+            None,
+            vec![
+                other_error(error("unrelated")),
+                other_error(error("unrelated").context(Category::Infra)),
+            ],
+        );
+        let result = BuildTargetResult {
+            configured: BTreeMap::new(),
+            other_errors,
+        };
+
+        let coalesced = result.coalesced_other_errors();
+        let coalesced = coalesced
+            .iter()
+            .map(|(labels, error)| {
+                (
+                    labels.as_slice(),
+                    format!("{:#}", error),
+                    error.get_category(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (&[None][..], "unrelated".to_owned(), Some(Category::User)),
+                (&[None][..], "unrelated".to_owned(), Some(Category::Infra)),
+                (&labels[..], "dep failed".to_owned(), Some(Category::User)),
+            ],
+            coalesced
+        );
+    }
+
+    #[test]
+    fn test_providers_to_build_builder() {
+        let flags = |p: ProvidersToBuild| {
+            (
+                p.wants_default(),
+                p.wants_default_other(),
+                p.wants_run(),
+                p.wants_tests(),
+            )
+        };
+
+        assert_eq!(
+            (false, false, false, false),
+            flags(ProvidersToBuild::default())
+        );
+        assert!(ProvidersToBuild::builder().build().is_empty());
+        assert_eq!(
+            (true, false, true, false),
+            flags(ProvidersToBuild::builder().default().run().build())
+        );
+        assert_eq!(
+            (false, true, false, true),
+            flags(ProvidersToBuild::builder().tests().default_other().build())
+        );
+        assert_eq!(
+            (true, true, true, true),
+            flags(ProvidersToBuild::builder().all().build())
+        );
+    }
+
+    #[test]
+    fn test_summary_line() {
+        let succeeded = || {
+            target_result(vec![output(
+                "cell//pkg:a",
+                "a.o",
+                BuildProviderType::Default,
+            )])
+        };
+        let failed = || {
+            let mut result = succeeded();
+            if let Some(result) = &mut result {
+                result.outputs.push(Err(anyhow::anyhow!("failed").into()));
+            }
+            result
+        };
+        let resolved = || {
+            let mut result = target_result(Vec::new());
+            if let Some(result) = &mut result {
+                result.resolved_outputs.push((
+                    ArtifactGroup::Artifact(artifact("cell//pkg:a", "a.o")),
+                    BuildProviderType::Default,
+                ));
+                result.status = TargetBuildStatus::Resolved;
+            }
+            result
+        };
+        let summary = |targets: Vec<Option<ConfiguredBuildTargetResult>>, millis| {
+            BuildTargetResult {
+                configured: targets
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, result)| (label(&format!("cell//pkg:t{}", i)), result))
+                    .collect(),
+                other_errors: BTreeMap::new(),
+            }
+            .summary_line(Duration::from_millis(millis))
+        };
+
+        assert_eq!(
+            "Built 1 target, 0 failed in 0.5s",
+            summary(vec![succeeded()], 500)
+        );
+        assert_eq!(
+            "Built 2 targets, 0 failed in 12.3s",
+            summary(vec![succeeded(), succeeded()], 12_345)
+        );
+        assert_eq!(
+            "Built 3 targets, 2 failed in 1.0s",
+            summary(vec![succeeded(), failed(), failed()], 1_000)
+        );
+        assert_eq!(
+            "Built 3 targets (1 skipped), 1 failed in 2.0s",
+            summary(vec![succeeded(), None, failed()], 2_000)
+        );
+        assert_eq!(
+            "Built 3 targets (1 skipped, 1 resolved only), 0 failed in 2.0s",
+            summary(vec![succeeded(), None, resolved()], 2_000)
+        );
+    }
+
+    #[test]
+    fn test_labels_on_critical_path() {
+        let action_key = |owner: &str| {
+            BuildArtifact::testing_new(
+                ConfiguredTargetLabel::testing_parse(owner, ConfigurationData::testing_new()),
+                ForwardRelativePathBuf::unchecked_new("unused".to_owned()),
+                DeferredId::testing_new(0),
+            )
+            .key()
+            .dupe()
+        };
+
+        let mut failed = target_result(vec![]).unwrap();
+        failed.outputs = vec![Err(anyhow::anyhow!("failed").into())];
+        let result = BuildTargetResult {
+            configured: BTreeMap::from([
+                (
+                    label("cell//pkg:a"),
+                    target_result(vec![output(
+                        "cell//pkg:a",
+                        "a.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+                (
+                    label("cell//pkg:b"),
+                    target_result(vec![output(
+                        "cell//pkg:b",
+                        "b.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+                // `c` has an output produced by `a`'s action.
+                (
+                    label("cell//pkg:c"),
+                    target_result(vec![output(
+                        "cell//pkg:a",
+                        "c.o",
+                        BuildProviderType::Default,
+                    )]),
+                ),
+                (label("cell//pkg:failed"), Some(failed)),
+                (label("cell//pkg:skipped"), None),
+            ]),
+            other_errors: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            vec![&label("cell//pkg:a"), &label("cell//pkg:c")],
+            result.labels_on_critical_path([&action_key("cell//pkg:a")])
+        );
+        assert_eq!(
+            vec![&label("cell//pkg:b")],
+            result.labels_on_critical_path([
+                &action_key("cell//pkg:b"),
+                &action_key("cell//pkg:failed"),
+            ])
+        );
+        assert!(result.labels_on_critical_path([]).is_empty());
+    }
+
+    #[test]
+    fn test_into_configured_vec() {
+        let mut configured = BTreeMap::new();
+        for target in ["cell//pkg:c", "cell//pkg:a", "cell//pkg:b"] {
+            configured.insert(label(target), None);
+        }
+        let result = BuildTargetResult {
+            configured,
+            other_errors: BTreeMap::new(),
+        };
+
+        let labels = result
+            .into_configured_vec()
+            .into_iter()
+            .map(|(label, _result)| label)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                label("cell//pkg:a"),
+                label("cell//pkg:b"),
+                label("cell//pkg:c")
+            ],
+            labels
+        );
+    }
+
+    #[test]
+    fn test_incompatible_message_sink() {
+        let providers_label = Arc::new(label("cell//pkg:foo"));
+        let reason = IncompatiblePlatformReason {
+            target: providers_label.target().dupe(),
+            cause: IncompatiblePlatformReasonCause::UnsatisfiedConfig(TargetLabel::testing_parse(
+                "cell//constraints:bar",
+            )),
+        };
+
+        for (sink, expect_console_message) in [
+            (MessageSink::Console, true),
+            (MessageSink::EventOnly, false),
+            (MessageSink::Suppressed, false),
+        ] {
+            let opts = BuildConfiguredLabelOptions {
+                skippable: true,
+                want_configured_graph_size: WantConfiguredGraphSize::for_all(false),
+                configured_graph_size_max_depth: None,
+                request_id: None,
+                rule_type_filter: None,
+                output_path_filter: None,
+                register_as_top_level: true,
+                max_concurrent_materializations: None,
+                incompatible_message_sink: sink,
+                path_separator: None,
+                resolve_only: false,
+                message_sink: None,
+                cancellation: None,
+                analysis_only: false,
+            };
+            let (mut source, events_sink) = create_source_sink_pair();
+            let event = with_dispatcher(EventDispatcher::new(TraceId::new(), events_sink), || {
+                skipped_incompatible(&providers_label, &reason, &opts)
+            });
+
+            let mut console_messages = Vec::new();
+            while let Some(event) = source.try_receive() {
+                if let Some(buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                    data: Some(buck2_data::instant_event::Data::ConsoleMessage(message)),
+                })) = event.unpack_buck().map(|event| event.data())
+                {
+                    console_messages.push(message.message.clone());
+                }
+            }
+            assert_eq!(
+                expect_console_message,
+                !console_messages.is_empty(),
+                "{:?}",
+                sink
+            );
+            assert!(
+                matches!(
+                    event,
+                    ConfiguredBuildEvent {
+                        variant: ConfiguredBuildEventVariant::SkippedIncompatible,
+                        ..
+                    }
+                ),
+                "{:?}",
+                sink
+            );
+        }
+    }
+
+    #[test]
+    fn test_dedupe_outputs_prefers_errors() {
+        static SOFT_ERRORS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            buck2_core::error::initialize(Box::new(|category, _err, _loc, _options| {
+                SOFT_ERRORS.lock().unwrap().push(category.to_owned());
+            }))
+            .unwrap();
+        });
+        let collisions = || {
+            SOFT_ERRORS
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|category| *category == "build_output_index_collision")
+                .count()
+        };
+
+        let label = label("cell//pkg:a");
+        let ok = |path| Ok(output("cell//pkg:a", path, BuildProviderType::Default));
+        let err = || Err(anyhow::anyhow!("failed").into());
+
+        // Identical results are deduped quietly.
+        let outputs = dedupe_outputs(&label, vec![(0, ok("a.o")), (0, ok("a.o")), (1, err())]);
+        assert_eq!(2, outputs.len());
+        assert_eq!(0, collisions());
+
+        for colliding in [vec![ok("b.o"), err()], vec![err(), ok("b.o")]] {
+            let outputs = dedupe_outputs(
+                &label,
+                std::iter::once((0, ok("a.o")))
+                    .chain(colliding.into_iter().map(|output| (1, output)))
+                    .collect(),
+            );
+            assert_eq!(2, outputs.len());
+            assert!(outputs[0].is_ok());
+            assert!(outputs[1].is_err());
+        }
+        assert_eq!(2, collisions());
+    }
+
+    #[tokio::test]
+    async fn test_built_provider_types() {
+        // Both `Default` and `Run` were requested, but the target has no `RunInfo`, so only
+        // default outputs are produced.
+        let label = Arc::new(label("cell//pkg:a"));
+        let event = |variant| {
+            BuildEvent::Configured(ConfiguredBuildEvent {
+                label: label.dupe(),
+                variant,
+                request_id: None,
+            })
+        };
+        let events = vec![
+            event(ConfiguredBuildEventVariant::Prepared {
+                run_args: None,
+                target_rule_type_name: "foo_binary".to_owned(),
+                output_count: 2,
+                analysis_duration: Duration::from_millis(3),
+            }),
+            event(ConfiguredBuildEventVariant::Output {
+                output: Ok(ProviderArtifacts {
+                    materialization_duration: Duration::from_millis(10),
+                    ..output("cell//pkg:a", "a.o", BuildProviderType::Default)
+                }),
+                index: 0,
+            }),
+            event(ConfiguredBuildEventVariant::Output {
+                output: Ok(ProviderArtifacts {
+                    materialization_duration: Duration::from_millis(5),
+                    ..output("cell//pkg:a", "b.o", BuildProviderType::Default)
+                }),
+                index: 1,
+            }),
+        ];
+
+        let result = BuildTargetResult::collect_stream(futures::stream::iter(events), false)
+            .await
+            .unwrap();
+        let built = result.configured[&*label].as_ref().unwrap();
+        assert_eq!(
+            BTreeSet::from([BuildProviderType::Default]),
+            built.built_provider_types
+        );
+        assert_eq!(Some(Duration::from_millis(3)), built.analysis_duration);
+        assert_eq!(Duration::from_millis(15), built.materialization_duration);
+    }
+
+    #[tokio::test]
+    async fn test_target_build_status() {
+        let status = |variants: Vec<ConfiguredBuildEventVariant>| {
+            let label = Arc::new(label("cell//pkg:a"));
+            let events = variants
+                .into_iter()
+                .map(|variant| {
+                    BuildEvent::Configured(ConfiguredBuildEvent {
+                        label: label.dupe(),
+                        variant,
+                        request_id: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+            async move {
+                let result =
+                    BuildTargetResult::collect_stream(futures::stream::iter(events), false)
+                        .await
+                        .unwrap();
+                result.configured[&*label].as_ref().map(|r| r.status)
+            }
+        };
+        let prepared = |output_count| ConfiguredBuildEventVariant::Prepared {
+            run_args: None,
+            target_rule_type_name: "foo_binary".to_owned(),
+            output_count,
+            analysis_duration: Duration::ZERO,
+        };
+
+        assert_eq!(
+            Some(TargetBuildStatus::Built),
+            status(vec![
+                prepared(1),
+                ConfiguredBuildEventVariant::Output {
+                    output: Ok(output("cell//pkg:a", "a.o", BuildProviderType::Default)),
+                    index: 0,
+                },
+            ])
+            .await
+        );
+        assert_eq!(
+            Some(TargetBuildStatus::BuiltNoOutputs),
+            status(vec![prepared(0)]).await
+        );
+        assert_eq!(
+            Some(TargetBuildStatus::Resolved),
+            status(vec![
+                prepared(1),
+                ConfiguredBuildEventVariant::ResolvedOutput {
+                    output: ArtifactGroup::Artifact(artifact("cell//pkg:a", "a.o")),
+                    provider_type: BuildProviderType::Default,
+                    index: 0,
+                },
+            ])
+            .await
+        );
+        assert_eq!(
+            Some(TargetBuildStatus::Failed),
+            status(vec![
+                prepared(1),
+                ConfiguredBuildEventVariant::Output {
+                    output: Err(anyhow::anyhow!("failed").into()),
+                    index: 0,
+                },
+            ])
+            .await
+        );
+        assert_eq!(
+            Some(TargetBuildStatus::Failed),
+            status(vec![ConfiguredBuildEventVariant::Error {
+                err: anyhow::anyhow!("analysis failed").into(),
+            }])
+            .await
+        );
+        // Skipped targets have no result, and so no status.
+        assert_eq!(
+            None,
+            status(vec![ConfiguredBuildEventVariant::SkippedIncompatible]).await
+        );
+    }
+
+    #[test]
+    fn test_total_duration() {
+        let timed = |path, millis: Option<u64>| ProviderArtifacts {
+            duration: millis.map(Duration::from_millis),
+            ..output("cell//pkg:a", path, BuildProviderType::Default)
+        };
+
+        let result = target_result(vec![timed("a", None), timed("b", None)]).unwrap();
+        assert_eq!(None, result.total_duration());
+
+        let mut result = target_result(vec![
+            timed("a", Some(10)),
+            timed("b", None),
+            timed("c", Some(5)),
+        ])
+        .unwrap();
+        assert_eq!(Some(Duration::from_millis(15)), result.total_duration());
+
+        // Failed outputs don't count, even though they may have taken a while.
+        result.outputs.push(Err(anyhow::anyhow!("failed").into()));
+        assert_eq!(Some(Duration::from_millis(15)), result.total_duration());
+    }
+
+    #[test]
+    fn test_is_success() {
+        let message = |error: Option<&buck2_error::Error>| error.map(|e| format!("{:#}", e));
+
+        let mut result =
+            target_result(vec![output("cell//pkg:a", "a", BuildProviderType::Default)]).unwrap();
+        assert!(result.is_success());
+        assert_eq!(None, message(result.first_error()));
+
+        result
+            .outputs
+            .push(Err(anyhow::anyhow!("output failed").into()));
+        assert!(!result.is_success());
+        assert_eq!(
+            Some("output failed"),
+            message(result.first_error()).as_deref()
+        );
+
+        // Errors of the target itself come first.
+        result
+            .errors
+            .push(anyhow::anyhow!("analysis failed").into());
+        assert!(!result.is_success());
+        assert_eq!(
+            Some("analysis failed"),
+            message(result.first_error()).as_deref()
+        );
+
+        result.outputs.pop();
+        assert!(!result.is_success());
+    }
+
+    #[test]
+    fn test_outputs_by_provider_type() {
+        let mut result = target_result(vec![
+            output("cell//pkg:a", "a.default", BuildProviderType::Default),
+            output("cell//pkg:a", "a.other", BuildProviderType::DefaultOther),
+            output("cell//pkg:a", "a.run", BuildProviderType::Run),
+            output("cell//pkg:a", "a.test1", BuildProviderType::Test),
+            output("cell//pkg:a", "a.test2", BuildProviderType::Test),
+        ])
+        .unwrap();
+        // Failed outputs have no provider type, so they are never included.
+        result.outputs.push(Err(anyhow::anyhow!("failed").into()));
+
+        let paths = |values: Vec<&ArtifactGroupValues>| {
+            values
+                .into_iter()
+                .flat_map(|values| values.iter())
+                .map(|(artifact, _value)| {
+                    artifact.get_path().with_short_path(|path| path.to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["a.default"], paths(result.default_outputs().collect()));
+        assert_eq!(vec!["a.run"], paths(result.run_outputs().collect()));
+        assert_eq!(
+            vec!["a.test1", "a.test2"],
+            paths(result.test_outputs().collect())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_graph_size_cancelled_on_drop() {
+        struct DropGuard(Arc<AtomicBool>);
+
+        impl Drop for DropGuard {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let label = Arc::new(label("cell//pkg:a"));
+        let event = |variant| ConfiguredBuildEvent {
+            label: label.dupe(),
+            variant,
+            request_id: None,
+        };
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let graph_size = {
+            let guard = DropGuard(dropped.dupe());
+            async move {
+                let _guard = guard;
+                // A graph size computation that never finishes.
+                future::pending::<()>().await;
+                unreachable!()
+            }
+        };
+        let output = event(ConfiguredBuildEventVariant::Output {
+            output: Err(anyhow::anyhow!("failed").into()),
+            index: 0,
+        });
+
+        let mut stream = chain_graph_size(futures::stream::iter([output]), graph_size);
+        assert!(stream.next().await.is_some());
+        // This starts the graph size computation.
+        assert!(futures::poll!(stream.next()).is_pending());
+        assert!(!dropped.load(Ordering::SeqCst));
+
+        drop(stream);
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_provider_name() {
+        let default = label("cell//pkg:a");
+        assert_eq!(None, provider_name(&default));
+
+        let sub_target = ConfiguredProvidersLabel::new(
+            default.target().dupe(),
+            ProvidersName::Default.push(ProviderName::new_unchecked("sub".to_owned())),
+        );
+        assert_eq!(Some("[sub]".to_owned()), provider_name(&sub_target));
+
+        let output = ProviderArtifacts {
+            provider_name: provider_name(&sub_target),
+            ..output("cell//pkg:a", "a.o", BuildProviderType::Default)
+        };
+        assert!(
+            format!("{:?}", output).contains("provider_name: Some(\"[sub]\")"),
+            "{:?}",
+            output
+        );
+    }
+
+    #[derive(Debug, buck2_error::Error)]
+    #[error("flaky")]
+    struct FlakyError;
+
+    async fn flaky(
+        calls: &AtomicU32,
+        failures: u32,
+        err: impl Fn() -> anyhow::Result<()>,
+    ) -> anyhow::Result<u32> {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        if call < failures {
+            err()?;
+        }
+        Ok(call)
+    }
+
+    #[tokio::test]
+    async fn test_retry_materialization() {
+        let artifact = BuildArtifact::testing_new(
+            ConfiguredTargetLabel::testing_parse("cell//pkg:a", ConfigurationData::testing_new()),
+            ForwardRelativePathBuf::unchecked_new("a.out".to_owned()),
+            DeferredId::testing_new(0),
+        );
+
+        // Retry the first failure, give up on the second.
+        let decisions = Arc::new(AtomicU32::new(0));
+        let on_error: MaterializationErrorCallback = Arc::new({
+            let decisions = decisions.dupe();
+            let expected = artifact.dupe();
+            move |artifact, _err| {
+                assert_eq!(&expected, artifact);
+                if decisions.fetch_add(1, Ordering::SeqCst) == 0 {
+                    RetryDecision::Retry
+                } else {
+                    RetryDecision::Fail
+                }
+            }
+        });
+
+        let calls = AtomicU32::new(0);
+        let res = retry_materialization(&artifact, Some(&on_error), || {
+            flaky(&calls, u32::MAX, || Err(FlakyError.into()))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        assert_eq!(2, decisions.load(Ordering::SeqCst));
+
+        // Without a callback, failures are not retried.
+        let calls = AtomicU32::new(0);
+        let res = retry_materialization(&artifact, None, || {
+            flaky(&calls, u32::MAX, || Err(FlakyError.into()))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        // Retryable failures are retried even without a callback, up to a limit.
+        let retryable = || {
+            Err(buck2_error::Error::from(FlakyError)
+                .tag([buck2_error::ErrorTag::Retryable])
+                .into())
+        };
+        let calls = AtomicU32::new(0);
+        let res = retry_materialization(&artifact, None, || flaky(&calls, 2, retryable)).await;
+        assert_eq!(2, res.unwrap());
+
+        let calls = AtomicU32::new(0);
+        let res =
+            retry_materialization(&artifact, None, || flaky(&calls, u32::MAX, retryable)).await;
+        assert!(res.is_err());
+        assert_eq!(
+            MAX_MATERIALIZATION_RETRIES + 1,
+            calls.load(Ordering::SeqCst)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_materialization_callback_bound() {
+        let artifact = BuildArtifact::testing_new(
+            ConfiguredTargetLabel::testing_parse("cell//pkg:a", ConfigurationData::testing_new()),
+            ForwardRelativePathBuf::unchecked_new("a.out".to_owned()),
+            DeferredId::testing_new(0),
+        );
+
+        // A callback which always asks to retry doesn't keep us retrying forever.
+        let on_error: MaterializationErrorCallback = Arc::new(|_, _| RetryDecision::Retry);
+        let calls = AtomicU32::new(0);
+        let start = tokio::time::Instant::now();
+        let res = retry_materialization(&artifact, Some(&on_error), || {
+            flaky(&calls, u32::MAX, || Err(FlakyError.into()))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(
+            MAX_CALLBACK_MATERIALIZATION_RETRIES + 1,
+            calls.load(Ordering::SeqCst)
+        );
+        // And the retries are spaced out.
+        assert!(
+            start.elapsed() >= MATERIALIZATION_RETRY_BACKOFF * MAX_CALLBACK_MATERIALIZATION_RETRIES
+        );
+    }
+
+    #[test]
+    fn test_default_providers_label() {
+        let target =
+            ConfiguredTargetLabel::testing_parse("cell//pkg:a", ConfigurationData::testing_new());
+        let providers_label = default_providers_label(target.dupe());
+        assert_eq!(&target, providers_label.target());
+        assert_eq!(&ProvidersName::Default, providers_label.name());
+        assert_eq!(label("cell//pkg:a"), providers_label);
+    }
+
+    #[test]
+    fn test_claim_new_artifacts() {
+        let map = DashMap::new();
+        let [a, b, c] = ["a.out", "b.out", "c.out"].map(|path| {
+            BuildArtifact::testing_new(
+                ConfiguredTargetLabel::testing_parse(
+                    "cell//pkg:a",
+                    ConfigurationData::testing_new(),
+                ),
+                ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+                DeferredId::testing_new(0),
+            )
+        });
+        let group = |artifacts: &[&BuildArtifact]| -> Vec<Artifact> {
+            artifacts
+                .iter()
+                .map(|a| Artifact::from((*a).dupe()))
+                .collect()
+        };
+
+        let first = claim_new_artifacts(&map, &group(&[&a, &b]));
+        assert_eq!(vec![a.dupe(), b.dupe()], first);
+
+        // `b` overlaps with the first group, so only `c` is new.
+        let second = claim_new_artifacts(&map, &group(&[&b, &c]));
+        assert_eq!(vec![c.dupe()], second);
+        assert!(second.len() < first.len());
+
+        assert!(claim_new_artifacts(&map, &group(&[&a, &c])).is_empty());
+    }
+
+    #[test]
+    fn test_force_materializations_with_map() {
+        let [a, b] = ["a.out", "b.out"].map(|path| {
+            BuildArtifact::testing_new(
+                ConfiguredTargetLabel::testing_parse(
+                    "cell//pkg:a",
+                    ConfigurationData::testing_new(),
+                ),
+                ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+                DeferredId::testing_new(0),
+            )
+        });
+        let session_map = Arc::new(DashMap::new());
+        session_map.insert(a.dupe(), ());
+
+        let MaterializationContext::Materialize { map, force, .. } =
+            MaterializationContext::force_materializations_with_map(&session_map)
+        else {
+            panic!("materializations should not be skipped");
+        };
+        assert!(force);
+
+        // `a` was already enqueued by the session, so only `b` is.
+        let claimed =
+            claim_new_artifacts(&map, &[Artifact::from(a.dupe()), Artifact::from(b.dupe())]);
+        assert_eq!(vec![b.dupe()], claimed);
+        assert!(session_map.contains_key(&b));
+    }
+
+    #[test]
+    fn test_enqueued_artifacts() {
+        let [a, b] = ["a.out", "b.out"].map(|path| {
+            BuildArtifact::testing_new(
+                ConfiguredTargetLabel::testing_parse(
+                    "cell//pkg:a",
+                    ConfigurationData::testing_new(),
+                ),
+                ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+                DeferredId::testing_new(0),
+            )
+        });
+        assert!(MaterializationContext::Skip.enqueued_artifacts().is_empty());
+
+        let context = MaterializationContext::force_materializations();
+        assert!(context.enqueued_artifacts().is_empty());
+        let MaterializationContext::Materialize { map, .. } = &context else {
+            panic!("materializations should not be skipped");
+        };
+        claim_new_artifacts(map, &[Artifact::from(a.dupe())]);
+        claim_new_artifacts(map, &[Artifact::from(a.dupe()), Artifact::from(b.dupe())]);
+
+        let mut enqueued = context.enqueued_artifacts();
+        enqueued.sort_by_key(|artifact| artifact.get_path().path().to_string());
+        assert_eq!(vec![a, b], enqueued);
+    }
+
+    #[test]
+    fn test_is_matching_artifact() {
+        let [so, a] = ["lib.so", "lib.a"].map(|path| {
+            BuildArtifact::testing_new(
+                ConfiguredTargetLabel::testing_parse(
+                    "cell//pkg:a",
+                    ConfigurationData::testing_new(),
+                ),
+                ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+                DeferredId::testing_new(0),
+            )
+        });
+        let artifacts = [Artifact::from(so.dupe()), Artifact::from(a.dupe())];
+        let matcher: MaterializationMatcher =
+            Arc::new(|artifact| artifact.get_path().path().as_str().ends_with(".so"));
+
+        assert!(is_matching_artifact(Some(&matcher), &artifacts[0]));
+        assert!(!is_matching_artifact(Some(&matcher), &artifacts[1]));
+
+        // Without a matcher, everything is materialized.
+        assert!(is_matching_artifact(None, &artifacts[0]));
+        assert!(is_matching_artifact(None, &artifacts[1]));
+
+        // Only matching artifacts are claimed for materialization.
+        let map = DashMap::new();
+        let claimed = claim_new_artifacts(
+            &map,
+            artifacts
+                .iter()
+                .filter(|artifact| is_matching_artifact(Some(&matcher), artifact)),
+        );
+        assert_eq!(vec![so], claimed);
+        assert!(!map.contains_key(&a));
+    }
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
+
+/// Include and exclude globs, matched against the project-relative paths of build outputs
+/// (e.g. `buck-out/v2/gen/...`). As with ignore specs, `*` does not match `/`, so to select
+/// outputs anywhere under an `include` directory use `**/include/**`.
+#[derive(Debug)]
+pub struct PathFilter {
+    /// If set, only paths matching at least one of these are kept.
+    include: Option<GlobSet>,
+    /// Paths matching any of these are dropped, even if they are also included.
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    /// An empty `include` list means all paths are included.
+    pub fn new(include: &[impl AsRef<str>], exclude: &[impl AsRef<str>]) -> anyhow::Result<Self> {
+        fn build(patterns: &[impl AsRef<str>]) -> anyhow::Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(
+                    globset::GlobBuilder::new(pattern.as_ref())
+                        .literal_separator(true)
+                        .build()?,
+                );
+            }
+            Ok(builder.build()?)
+        }
+
+        Ok(Self {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(build(include)?)
+            },
+            exclude: build(exclude)?,
+        })
+    }
+
+    pub fn is_match(&self, path: &ProjectRelativePath) -> bool {
+        let path = path.as_str();
+        self.include
+            .as_ref()
+            .map_or(true, |include| include.is_match(path))
+            && !self.exclude.is_match(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+
+    use crate::build::path_filter::PathFilter;
+
+    const PATHS: &[&str] = &[
+        "buck-out/v2/gen/cell/pkg/__foo__/include/a.h",
+        "buck-out/v2/gen/cell/pkg/__foo__/include/debug/a.pdb",
+        "buck-out/v2/gen/cell/pkg/__foo__/debug/b.pdb",
+        "buck-out/v2/gen/cell/pkg/__foo__/c.o",
+    ];
+
+    fn matching(filter: &PathFilter) -> Vec<&'static str> {
+        PATHS
+            .iter()
+            .copied()
+            .filter(|path| filter.is_match(ProjectRelativePath::unchecked_new(path)))
+            .collect()
+    }
+
+    const NONE: &[&str] = &[];
+
+    #[test]
+    fn test_include_only() {
+        let filter = PathFilter::new(&["**/include/**"], NONE).unwrap();
+        assert_eq!(
+            vec![
+                "buck-out/v2/gen/cell/pkg/__foo__/include/a.h",
+                "buck-out/v2/gen/cell/pkg/__foo__/include/debug/a.pdb",
+            ],
+            matching(&filter)
+        );
+    }
+
+    #[test]
+    fn test_exclude_only() {
+        let filter = PathFilter::new(NONE, &["**/debug/**"]).unwrap();
+        assert_eq!(
+            vec![
+                "buck-out/v2/gen/cell/pkg/__foo__/include/a.h",
+                "buck-out/v2/gen/cell/pkg/__foo__/c.o",
+            ],
+            matching(&filter)
+        );
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let filter = PathFilter::new(&["**/include/**", "**/*.o"], &["**/debug/**"]).unwrap();
+        assert_eq!(
+            vec![
+                "buck-out/v2/gen/cell/pkg/__foo__/include/a.h",
+                "buck-out/v2/gen/cell/pkg/__foo__/c.o",
+            ],
+            matching(&filter)
+        );
+    }
+
+    #[test]
+    fn test_no_patterns() {
+        let filter = PathFilter::new(NONE, NONE).unwrap();
+        assert_eq!(PATHS, matching(&filter));
+    }
+}
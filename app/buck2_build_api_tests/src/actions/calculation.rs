@@ -151,6 +151,30 @@ fn mock_deferred_resolution_calculation(
     )
 }
 
+/// Runs commands with a `DryRunExecutor`, recording them in `dry_run_tracker`.
+pub(crate) struct CommandExecutorProvider {
+    pub(crate) dry_run_tracker: Arc<Mutex<Vec<DryRunEntry>>>,
+}
+
+impl HasCommandExecutor for CommandExecutorProvider {
+    fn get_command_executor(
+        &self,
+        artifact_fs: &ArtifactFs,
+        _config: &CommandExecutorConfig,
+    ) -> anyhow::Result<CommandExecutorResponse> {
+        let executor = Arc::new(DryRunExecutor::new(
+            self.dry_run_tracker.dupe(),
+            artifact_fs.clone(),
+        ));
+        Ok(CommandExecutorResponse {
+            executor,
+            cache_checker: Arc::new(NoOpCommandOptionalExecutor {}),
+            platform: Default::default(),
+            cache_uploader: Arc::new(NoOpCacheUploader {}),
+        })
+    }
+}
+
 async fn make_default_dice_state(
     dry_run_tracker: Arc<Mutex<Vec<DryRunEntry>>>,
     temp_fs: &ProjectRootTemp,
@@ -176,28 +200,6 @@ async fn make_default_dice_state(
 
     let mut extra = UserComputationData::new();
     extra.set_keep_going(true);
-    struct CommandExecutorProvider {
-        dry_run_tracker: Arc<Mutex<Vec<DryRunEntry>>>,
-    }
-    impl HasCommandExecutor for CommandExecutorProvider {
-        fn get_command_executor(
-            &self,
-            artifact_fs: &ArtifactFs,
-            _config: &CommandExecutorConfig,
-        ) -> anyhow::Result<CommandExecutorResponse> {
-            let executor = Arc::new(DryRunExecutor::new(
-                self.dry_run_tracker.dupe(),
-                artifact_fs.clone(),
-            ));
-            Ok(CommandExecutorResponse {
-                executor,
-                cache_checker: Arc::new(NoOpCommandOptionalExecutor {}),
-                platform: Default::default(),
-                cache_uploader: Arc::new(NoOpCacheUploader {}),
-            })
-        }
-    }
-
     set_fallback_executor_config(&mut extra.data, CommandExecutorConfig::testing_local());
     extra.set_command_executor(Box::new(CommandExecutorProvider { dry_run_tracker }));
     extra.set_blocking_executor(Arc::new(DummyBlockingExecutor { fs }));
@@ -7,7 +7,7 @@
  * of this source tree.
  */
 
-mod calculation;
+pub(crate) mod calculation;
 mod impls;
 pub(crate) mod registry;
 pub(crate) mod testings;
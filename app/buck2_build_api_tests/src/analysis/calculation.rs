@@ -7,18 +7,39 @@
  * of this source tree.
  */
 
+use std::any::Any;
 use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
 
+use allocative::Allocative;
+use buck2_analysis::analysis::calculation::AnalysisKey;
 use buck2_build_api::actions::execute::dice_data::set_fallback_executor_config;
+use buck2_build_api::actions::execute::dice_data::SetCommandExecutor;
+use buck2_build_api::actions::execute::dice_data::SetReClient;
+use buck2_build_api::actions::impls::run_action_knobs::RunActionKnobs;
 use buck2_build_api::analysis::calculation::RuleAnalysisCalculation;
+use buck2_build_api::build::prewarm_analysis;
+use buck2_build_api::build_signals::BuildSignals;
+use buck2_build_api::build_signals::SetBuildSignals;
+use buck2_build_api::context::SetBuildContextData;
 use buck2_build_api::deferred::types::testing::DeferredAnalysisResultExt;
+use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineArgLike;
+use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineArtifactVisitor;
+use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineBuilder;
+use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineContext;
+use buck2_build_api::interpreter::rule_defs::cmd_args::WriteToFileMacroVisitor;
 use buck2_build_api::interpreter::rule_defs::provider::builtin::default_info::DefaultInfoCallable;
 use buck2_build_api::interpreter::rule_defs::provider::callable::register_provider;
 use buck2_build_api::interpreter::rule_defs::provider::registration::register_builtin_providers;
 use buck2_build_api::keep_going::HasKeepGoing;
 use buck2_build_api::spawner::BuckSpawner;
 use buck2_common::dice::data::testing::SetTestingIoProvider;
+use buck2_common::http::SetHttpClient;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_common::legacy_configs::LegacyBuckConfigs;
 use buck2_common::package_listing::listing::testing::PackageListingExt;
@@ -38,10 +59,18 @@ use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::package::PackageLabel;
 use buck2_core::provider::id::testing::ProviderIdExt;
 use buck2_core::provider::id::ProviderId;
+use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_core::target::label::TargetLabel;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::digest_config::SetDigestConfig;
+use buck2_execute::execute::blocking::testing::DummyBlockingExecutor;
+use buck2_execute::execute::blocking::SetBlockingExecutor;
+use buck2_execute::materialize::materializer::SetMaterializer;
+use buck2_execute::materialize::nodisk::NoDiskMaterializer;
+use buck2_execute::re::manager::ManagedRemoteExecutionClient;
+use buck2_file_watcher::mergebase::SetMergebase;
+use buck2_http::HttpClientBuilder;
 use buck2_interpreter::dice::starlark_debug::SetStarlarkDebugger;
 use buck2_interpreter::extra::InterpreterHostArchitecture;
 use buck2_interpreter::extra::InterpreterHostPlatform;
@@ -55,15 +84,103 @@ use buck2_interpreter_for_build::interpreter::interpreter_setup::setup_interpret
 use buck2_interpreter_for_build::interpreter::testing::Tester;
 use buck2_interpreter_for_build::rule::register_rule_function;
 use dice::testing::DiceBuilder;
+use dice::ActivationData;
+use dice::ActivationTracker;
+use dice::DiceTransaction;
 use dice::UserComputationData;
 use dupe::Dupe;
 use indoc::indoc;
 use itertools::Itertools;
 use maplit::hashmap;
+use starlark::any::ProvidesStaticType;
+use starlark::environment::GlobalsBuilder;
+use starlark::starlark_module;
+use starlark::starlark_simple_value;
+use starlark::values::starlark_value;
+use starlark::values::Demand;
+use starlark::values::NoSerialize;
+use starlark::values::StarlarkValue;
 use starlark_map::ordered_map::OrderedMap;
 
-#[tokio::test]
-async fn test_analysis_calculation() -> anyhow::Result<()> {
+use crate::actions::calculation::CommandExecutorProvider;
+
+/// An argument whose artifacts can't be visited.
+#[derive(Debug, ProvidesStaticType, NoSerialize, Allocative)]
+struct FailingArg;
+
+starlark_simple_value!(FailingArg);
+
+impl Display for FailingArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failing_arg()")
+    }
+}
+
+#[starlark_value(type = "failing_arg")]
+impl<'v> StarlarkValue<'v> for FailingArg {
+    fn provide(&'v self, demand: &mut Demand<'_, 'v>) {
+        demand.provide_value::<&dyn CommandLineArgLike>(self);
+    }
+}
+
+impl CommandLineArgLike for FailingArg {
+    fn add_to_command_line(
+        &self,
+        cli: &mut dyn CommandLineBuilder,
+        _context: &mut dyn CommandLineContext,
+    ) -> anyhow::Result<()> {
+        cli.push_arg("failing".to_owned());
+        Ok(())
+    }
+
+    fn visit_artifacts(&self, _visitor: &mut dyn CommandLineArtifactVisitor) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Failed to visit the artifacts of `failing_arg()`"
+        ))
+    }
+
+    fn contains_arg_attr(&self) -> bool {
+        false
+    }
+
+    fn visit_write_to_file_macros(
+        &self,
+        _visitor: &mut dyn WriteToFileMacroVisitor,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[starlark_module]
+fn register_failing_arg(builder: &mut GlobalsBuilder) {
+    fn failing_arg() -> anyhow::Result<FailingArg> {
+        Ok(FailingArg)
+    }
+}
+
+pub(crate) async fn setup_analysis(
+    activation_tracker: Option<Arc<dyn ActivationTracker>>,
+) -> anyhow::Result<DiceTransaction> {
+    setup_analysis_with_build_signals(activation_tracker, None).await
+}
+
+pub(crate) async fn setup_analysis_with_build_signals(
+    activation_tracker: Option<Arc<dyn ActivationTracker>>,
+    build_signals: Option<Arc<dyn BuildSignals>>,
+) -> anyhow::Result<DiceTransaction> {
+    setup_analysis_with_data(activation_tracker, |data| {
+        if let Some(build_signals) = build_signals {
+            data.set_build_signals(build_signals);
+        }
+    })
+    .await
+}
+
+/// Like `setup_analysis`, with `configure_data` called to set additional per-transaction data.
+pub(crate) async fn setup_analysis_with_data(
+    activation_tracker: Option<Arc<dyn ActivationTracker>>,
+    configure_data: impl FnOnce(&mut UserComputationData),
+) -> anyhow::Result<DiceTransaction> {
     let bzlfile = ImportPath::testing_new("cell//pkg:foo.bzl");
     let resolver = {
         let mut cells = CellsAggregator::new();
@@ -94,6 +211,7 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
     interpreter.additional_globals(register_provider);
     interpreter.additional_globals(register_builtin_providers);
     interpreter.additional_globals(register_attrs);
+    interpreter.additional_globals(register_failing_arg);
     let module = interpreter
         .eval_import(
             &bzlfile,
@@ -106,16 +224,51 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
                                     str = ctx.attrs.dep[FooInfo].str
                                 return [FooInfo(str=(str + ctx.attrs.str)), DefaultInfo()]
                             foo_binary = rule(impl=impl, attrs={"dep": attrs.option(attrs.dep(providers=[FooInfo]), default = None), "str": attrs.string()})
+
+                            def run_impl(ctx):
+                                return [DefaultInfo(), RunInfo(args = ["run", ctx.attrs.str])]
+                            run_binary = rule(impl=run_impl, attrs={"str": attrs.string()})
+
+                            def run_src_impl(ctx):
+                                return [DefaultInfo(), RunInfo(args = [ctx.attrs.src])]
+                            run_src_binary = rule(impl=run_src_impl, attrs={"src": attrs.source()})
+
+                            def srcs_impl(ctx):
+                                return [DefaultInfo(default_outputs = ctx.attrs.srcs)]
+                            srcs_files = rule(impl=srcs_impl, attrs={"srcs": attrs.list(attrs.source())})
+
+                            def touch(ctx, name):
+                                out = ctx.actions.declare_output(name)
+                                ctx.actions.run(["touch", out.as_output()], category = "touch", identifier = name)
+                                return out
+
+                            def touch_impl(ctx):
+                                return [DefaultInfo(default_outputs = [touch(ctx, "out{}.txt".format(i)) for i in range(ctx.attrs.count)])]
+                            touch_files = rule(impl=touch_impl, attrs={"count": attrs.int()})
+
+                            def shared_output_impl(ctx):
+                                out = touch(ctx, "out.txt")
+                                return [DefaultInfo(default_outputs = [out], other_outputs = [out])]
+                            shared_output = rule(impl=shared_output_impl, attrs={})
+
+                            def failing_run_impl(ctx):
+                                return [DefaultInfo(default_outputs = [touch(ctx, "out.txt")]), RunInfo(args = [failing_arg()])]
+                            failing_run = rule(impl=failing_run_impl, attrs={})
                         "#),
             LoadedModules::default(),
         )?;
 
     let buildfile = BuildFilePath::testing_new("cell//pkg:BUCK");
+    let files = ["a.txt", "b.txt", "c.txt"]
+        .into_iter()
+        .map(str::to_owned)
+        .chain((0..100).map(|i| format!("src{}.txt", i)))
+        .collect::<Vec<_>>();
     let eval_res = interpreter.eval_build_file_with_loaded_modules(
         &buildfile,
         indoc!(
             r#"
-                    load(":foo.bzl", "FooInfo", "foo_binary")
+                    load(":foo.bzl", "FooInfo", "failing_run", "foo_binary", "run_binary", "run_src_binary", "shared_output", "srcs_files", "touch_files")
 
                     foo_binary(
                         name = "rule1",
@@ -132,6 +285,33 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
                         str = "c",
                         dep = None,
                     )
+                    run_binary(
+                        name = "run1",
+                        str = "d",
+                    )
+                    run_src_binary(
+                        name = "run_src",
+                        src = "a.txt",
+                    )
+                    srcs_files(
+                        name = "srcs1",
+                        srcs = ["a.txt", "b.txt", "c.txt"],
+                    )
+                    srcs_files(
+                        name = "srcs_many",
+                        srcs = ["src{}.txt".format(i) for i in range(100)],
+                    )
+                    touch_files(
+                        name = "touch_many",
+                        count = 20,
+                    )
+                    [touch_files(name = "touch{}".format(i), count = 1) for i in range(4)]
+                    shared_output(
+                        name = "shared",
+                    )
+                    failing_run(
+                        name = "failing_run",
+                    )
                 "#
         ),
         LoadedModules {
@@ -140,7 +320,10 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
                 module.dupe(),
             )]),
         },
-        PackageListing::testing_new(&[], "BUCK"),
+        PackageListing::testing_new(
+            &files.iter().map(String::as_str).collect::<Vec<_>>(),
+            "BUCK",
+        ),
     )?;
 
     let fs = ProjectRootTemp::new()?;
@@ -163,8 +346,24 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
             data.set_keep_going(true);
             data.set_starlark_debugger_handle(None);
             set_fallback_executor_config(&mut data.data, CommandExecutorConfig::testing_local());
+            data.set_command_executor(Box::new(CommandExecutorProvider {
+                dry_run_tracker: Arc::new(Mutex::new(Vec::new())),
+            }));
+            data.set_blocking_executor(Arc::new(DummyBlockingExecutor {
+                fs: fs.path().dupe(),
+            }));
+            data.set_materializer(Arc::new(NoDiskMaterializer));
+            data.set_re_client(ManagedRemoteExecutionClient::testing_new_dummy());
+            data.set_http_client(HttpClientBuilder::https_with_system_roots()?.build());
+            data.set_mergebase(Default::default());
+            data.data.set(RunActionKnobs::default());
+            // Actions write their outputs to the project root, so keep it for as long as the
+            // transaction.
+            data.data.set(Arc::new(fs));
             data.data.set(EventDispatcher::null());
             data.spawner = Arc::new(BuckSpawner::current_runtime().unwrap());
+            data.activation_tracker = activation_tracker;
+            configure_data(&mut data);
             data
         })?;
     setup_interpreter_basic(
@@ -185,7 +384,14 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
         )?,
         configs,
     )?;
-    let dice = dice.commit().await;
+    dice.set_buck_out_path(None)?;
+    Ok(dice.commit().await)
+}
+
+#[tokio::test]
+async fn test_analysis_calculation() -> anyhow::Result<()> {
+    let bzlfile = ImportPath::testing_new("cell//pkg:foo.bzl");
+    let dice = setup_analysis(None).await?;
 
     let analysis = dice
         .get_analysis_result(
@@ -227,3 +433,43 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Counts how many times analysis was evaluated (as opposed to reused).
+#[derive(Default)]
+struct AnalysisEvaluations(AtomicUsize);
+
+impl ActivationTracker for AnalysisEvaluations {
+    fn key_activated(
+        &self,
+        key: &dyn Any,
+        _deps: &mut dyn Iterator<Item = &dyn Any>,
+        activation_data: ActivationData,
+    ) {
+        if key.is::<AnalysisKey>() && matches!(activation_data, ActivationData::Evaluated(..)) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_prewarm_analysis() -> anyhow::Result<()> {
+    let evaluations = Arc::new(AnalysisEvaluations::default());
+    let dice = setup_analysis(Some(evaluations.dupe() as _)).await?;
+
+    let labels = ["cell//pkg:rule1", "cell//pkg:rule3"].map(|label| {
+        ConfiguredProvidersLabel::default_for(
+            TargetLabel::testing_parse(label).configure(ConfigurationData::testing_new()),
+        )
+    });
+
+    prewarm_analysis(&dice, &labels).await;
+    // `rule1` depends on `rule2`, which depends on `rule3`.
+    assert_eq!(evaluations.0.load(Ordering::SeqCst), 3);
+
+    for label in &labels {
+        dice.get_providers(label).await?.require_compatible()?;
+    }
+    assert_eq!(evaluations.0.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
@@ -7,4 +7,4 @@
  * of this source tree.
  */
 
-mod calculation;
+pub(crate) mod calculation;
@@ -0,0 +1,1133 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+use buck2_artifact::artifact::build_artifact::BuildArtifact;
+use buck2_artifact::deferred::id::DeferredId;
+use buck2_build_api::actions::artifact::get_artifact_fs::GetArtifactFs;
+use buck2_build_api::actions::artifact::materializer::ArtifactMaterializer;
+use buck2_build_api::actions::artifact::materializer::HasMaterializerOverride;
+use buck2_build_api::actions::artifact::materializer::MaterializerOverride;
+use buck2_build_api::artifact_groups::ArtifactGroup;
+use buck2_build_api::build::build_configured_label;
+use buck2_build_api::build::build_configured_labels;
+use buck2_build_api::build::graph_size::get_configured_graph_size;
+use buck2_build_api::build::graph_size::get_configured_graph_size_bounded;
+use buck2_build_api::build::graph_size::BoundedGraphSize;
+use buck2_build_api::build::BuildConfiguredLabelOptions;
+use buck2_build_api::build::BuildEvent;
+use buck2_build_api::build::BuildProviderType;
+use buck2_build_api::build::BuildTargetResult;
+use buck2_build_api::build::ConfiguredBuildEvent;
+use buck2_build_api::build::ConsoleMessageSink;
+use buck2_build_api::build::MaterializationContext;
+use buck2_build_api::build::MessageSink;
+use buck2_build_api::build::ProvidersToBuild;
+use buck2_build_api::build::TargetBuildStatus;
+use buck2_build_api::build::WantConfiguredGraphSize;
+use buck2_build_api::build_signals::BuildSignals;
+use buck2_build_signals::NodeDuration;
+use buck2_core::configuration::compatibility::MaybeCompatible;
+use buck2_core::configuration::data::ConfigurationData;
+use buck2_core::execution_types::executor_config::PathSeparatorKind;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_core::provider::label::ConfiguredProvidersLabel;
+use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+use buck2_core::target::label::TargetLabel;
+use buck2_error::Category;
+use buck2_events::span::SpanId;
+use buck2_interpreter::dice::starlark_debug::SetStarlarkDebugger;
+use buck2_interpreter::starlark_debug::StarlarkDebugController;
+use buck2_interpreter::starlark_debug::StarlarkDebuggerHandle;
+use dice::DiceTransaction;
+use dupe::Dupe;
+use futures::future;
+use futures::StreamExt;
+use itertools::Itertools;
+use starlark::eval::Evaluator;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::analysis::calculation::setup_analysis;
+use crate::analysis::calculation::setup_analysis_with_build_signals;
+use crate::analysis::calculation::setup_analysis_with_data;
+
+fn opts() -> BuildConfiguredLabelOptions {
+    BuildConfiguredLabelOptions {
+        skippable: false,
+        want_configured_graph_size: WantConfiguredGraphSize::for_all(false),
+        configured_graph_size_max_depth: None,
+        request_id: None,
+        rule_type_filter: None,
+        output_path_filter: None,
+        register_as_top_level: true,
+        max_concurrent_materializations: None,
+        incompatible_message_sink: MessageSink::Console,
+        path_separator: None,
+        resolve_only: false,
+        message_sink: None,
+        cancellation: None,
+        analysis_only: false,
+    }
+}
+
+fn label(target: &str) -> ConfiguredProvidersLabel {
+    ConfiguredProvidersLabel::default_for(
+        TargetLabel::testing_parse(target).configure(ConfigurationData::testing_new()),
+    )
+}
+
+async fn build(
+    dice: &DiceTransaction,
+    target: &str,
+    opts: BuildConfiguredLabelOptions,
+) -> Vec<ConfiguredBuildEvent> {
+    build_providers(
+        dice,
+        target,
+        &ProvidersToBuild::builder().all().build(),
+        opts,
+    )
+    .await
+}
+
+async fn build_providers(
+    dice: &DiceTransaction,
+    target: &str,
+    providers_to_build: &ProvidersToBuild,
+    opts: BuildConfiguredLabelOptions,
+) -> Vec<ConfiguredBuildEvent> {
+    build_configured_label(
+        dice,
+        &MaterializationContext::Skip,
+        label(target),
+        providers_to_build,
+        opts,
+    )
+    .await
+    .collect()
+    .await
+}
+
+/// Build all the outputs of `target`, materializing them as requested by `materialization_context`.
+async fn build_materializing(
+    dice: &DiceTransaction,
+    materialization_context: &MaterializationContext,
+    target: &str,
+    opts: BuildConfiguredLabelOptions,
+) -> Vec<ConfiguredBuildEvent> {
+    build_configured_label(
+        dice,
+        materialization_context,
+        label(target),
+        &ProvidersToBuild::builder().all().build(),
+        opts,
+    )
+    .await
+    .collect()
+    .await
+}
+
+async fn collect(events: Vec<ConfiguredBuildEvent>) -> anyhow::Result<BuildTargetResult> {
+    BuildTargetResult::collect_stream(
+        futures::stream::iter(events.into_iter().map(BuildEvent::Configured)),
+        false,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_request_id_propagates_to_events() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let opts = BuildConfiguredLabelOptions {
+        want_configured_graph_size: WantConfiguredGraphSize::for_all(true),
+        request_id: Some(42),
+        ..opts()
+    };
+    let events = build(&dice, "cell//pkg:rule1", opts.dupe()).await;
+    assert!(!events.is_empty());
+    for event in &events {
+        assert_eq!(event.request_id(), Some(42));
+    }
+
+    let opts = BuildConfiguredLabelOptions {
+        request_id: None,
+        ..opts
+    };
+    let events = build(&dice, "cell//pkg:rule1", opts).await;
+    assert!(!events.is_empty());
+    for event in &events {
+        assert_eq!(event.request_id(), None);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_want_configured_graph_size_per_label() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let opts = BuildConfiguredLabelOptions {
+        want_configured_graph_size: WantConfiguredGraphSize::new(|label| {
+            label.target().name().as_str() == "rule1"
+        }),
+        ..opts()
+    };
+    for (target, want) in [("cell//pkg:rule1", true), ("cell//pkg:rule2", false)] {
+        let mut result = collect(build(&dice, target, opts.dupe()).await).await?;
+        let built = result
+            .configured
+            .remove(&label(target))
+            .flatten()
+            .expect("the target should have been built");
+        assert_eq!(want, built.configured_graph_size.is_some(), "{}", target);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_configured_graph_size_bounded() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+    // `rule1` -> `rule2` -> `rule3`.
+    let target = label("cell//pkg:rule1");
+    let target = target.target();
+
+    assert_eq!(
+        MaybeCompatible::Compatible(3),
+        get_configured_graph_size(&dice, target).await?
+    );
+    for (max_depth, size, truncated) in [(0, 1, true), (1, 2, true), (2, 3, false), (5, 3, false)] {
+        assert_eq!(
+            MaybeCompatible::Compatible(BoundedGraphSize { size, truncated }),
+            get_configured_graph_size_bounded(&dice, target, max_depth).await?,
+            "max_depth = {}",
+            max_depth
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rule_type_filter() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let build_with_filter = |rule_types: &[&str]| {
+        let opts = BuildConfiguredLabelOptions {
+            rule_type_filter: Some(Arc::new(
+                rule_types.iter().map(|t| (*t).to_owned()).collect(),
+            )),
+            ..opts()
+        };
+        let dice = &dice;
+        async move { collect(build(dice, "cell//pkg:rule1", opts).await).await }
+    };
+
+    let result = build_with_filter(&["foo_binary", "cc_library"]).await?;
+    let built = result
+        .configured
+        .get(&label("cell//pkg:rule1"))
+        .and_then(|result| result.as_ref())
+        .expect("rule1 should have been built");
+    assert_eq!(Some("foo_binary"), built.target_rule_type_name.as_deref());
+
+    let result = build_with_filter(&["cc_library"]).await?;
+    assert!(result.configured.is_empty());
+    assert!(result.other_errors.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_default_outputs_only_skips_run_info() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let run_args = |providers_to_build: ProvidersToBuild| {
+        let dice = &dice;
+        async move {
+            let events = build_providers(dice, "cell//pkg:run1", &providers_to_build, opts()).await;
+            let mut result = collect(events).await?;
+            let built = result
+                .configured
+                .remove(&label("cell//pkg:run1"))
+                .flatten()
+                .expect("run1 should have been built");
+            anyhow::Ok(built.run_args)
+        }
+    };
+
+    assert_eq!(
+        None,
+        run_args(ProvidersToBuild::builder().default().build()).await?
+    );
+    assert_eq!(
+        Some(vec!["run".to_owned(), "d".to_owned()]),
+        run_args(ProvidersToBuild::builder().default().run().build()).await?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_path_separator() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let run_args = |path_separator| {
+        let opts = BuildConfiguredLabelOptions {
+            path_separator,
+            ..opts()
+        };
+        let dice = &dice;
+        async move {
+            let events = build_providers(
+                dice,
+                "cell//pkg:run_src",
+                &ProvidersToBuild::builder().default().run().build(),
+                opts,
+            )
+            .await;
+            let mut result = collect(events).await?;
+            let built = result
+                .configured
+                .remove(&label("cell//pkg:run_src"))
+                .flatten()
+                .expect("run_src should have been built");
+            anyhow::Ok(built.run_args)
+        }
+    };
+
+    // The arguments are absolute paths, so only check how they end.
+    let arg = |args: Option<Vec<String>>| args.and_then(|args| args.into_iter().exactly_one().ok());
+    let unix = arg(run_args(Some(PathSeparatorKind::Unix)).await?).unwrap();
+    assert!(unix.ends_with("cell/pkg/a.txt"), "{}", unix);
+    let windows = arg(run_args(Some(PathSeparatorKind::Windows)).await?).unwrap();
+    assert!(windows.ends_with("cell\\pkg\\a.txt"), "{}", windows);
+    assert_eq!(
+        if cfg!(windows) {
+            run_args(Some(PathSeparatorKind::Windows)).await?
+        } else {
+            run_args(Some(PathSeparatorKind::Unix)).await?
+        },
+        run_args(None).await?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_count() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    for target in ["cell//pkg:srcs1", "cell//pkg:rule1"] {
+        let events = build(&dice, target, opts()).await;
+        // The count is reported before any output.
+        let output_count = events
+            .iter()
+            .take_while(|event| !event.is_output())
+            .find_map(|event| event.output_count())
+            .expect("the output count should be reported before any output");
+        assert_eq!(
+            output_count,
+            events.iter().filter(|event| event.is_output()).count(),
+            "{}",
+            target
+        );
+    }
+    assert_eq!(
+        Some(3),
+        build(&dice, "cell//pkg:srcs1", opts())
+            .await
+            .iter()
+            .find_map(|event| event.output_count())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resolve_only() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    for target in ["cell//pkg:srcs1", "cell//pkg:rule1"] {
+        let built = collect(build(&dice, target, opts()).await)
+            .await?
+            .configured
+            .remove(&label(target))
+            .flatten()
+            .unwrap();
+
+        let events = build(
+            &dice,
+            target,
+            BuildConfiguredLabelOptions {
+                resolve_only: true,
+                ..opts()
+            },
+        )
+        .await;
+        assert!(!events.iter().any(|event| event.is_output()), "{}", target);
+        assert!(
+            !events
+                .iter()
+                .any(|event| event.materialization_progress().is_some()),
+            "{}",
+            target
+        );
+        let resolved = collect(events)
+            .await?
+            .configured
+            .remove(&label(target))
+            .flatten()
+            .unwrap();
+
+        // The same outputs are known, without building any of them (building the sources of
+        // `srcs1` would actually fail, since they don't exist).
+        assert!(resolved.outputs.is_empty(), "{}", target);
+        assert_eq!(
+            built.outputs.len(),
+            resolved.resolved_outputs.len(),
+            "{}",
+            target
+        );
+        assert!(
+            resolved
+                .resolved_outputs
+                .iter()
+                .all(|(_output, provider_type)| *provider_type == BuildProviderType::Default),
+            "{}",
+            target
+        );
+        assert!(built.resolved_outputs.is_empty(), "{}", target);
+        // A target without outputs has nothing to resolve either.
+        let expected_status = if built.outputs.is_empty() {
+            TargetBuildStatus::BuiltNoOutputs
+        } else {
+            TargetBuildStatus::Resolved
+        };
+        assert_eq!(expected_status, resolved.status, "{}", target);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_materialization_progress() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let events = build(&dice, "cell//pkg:srcs1", opts()).await;
+    let progress = events
+        .iter()
+        .filter_map(|event| event.materialization_progress())
+        .collect::<Vec<_>>();
+    assert_eq!(vec![(1, 3), (2, 3), (3, 3)], progress);
+
+    // Every output is immediately followed by the progress it made.
+    for (i, event) in events.iter().enumerate() {
+        if event.is_output() {
+            assert!(
+                events
+                    .get(i + 1)
+                    .and_then(|event| event.materialization_progress())
+                    .is_some()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes a little while to materialize each artifact, keeping track of how many materializations
+/// were in flight at most, and of the artifacts whose materialization finished.
+#[derive(Default)]
+struct SlowMaterializer {
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+    materialized: Mutex<Vec<BuildArtifact>>,
+}
+
+#[async_trait]
+impl MaterializerOverride for SlowMaterializer {
+    async fn materialize_requested_artifact(
+        &self,
+        artifact: &BuildArtifact,
+        _path: ProjectRelativePathBuf,
+        _required: bool,
+    ) -> anyhow::Result<()> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.materialized.lock().unwrap().push(artifact.dupe());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_max_concurrent_materializations() -> anyhow::Result<()> {
+    let build_with_limit = |max_concurrent_materializations| async move {
+        let materializer = Arc::new(SlowMaterializer::default());
+        let dice = setup_analysis_with_data(None, |data| {
+            data.set_materializer_override(materializer.dupe())
+        })
+        .await?;
+        let target = "cell//pkg:touch_many";
+        let built = collect(
+            build_materializing(
+                &dice,
+                &MaterializationContext::force_materializations(),
+                target,
+                BuildConfiguredLabelOptions {
+                    max_concurrent_materializations,
+                    ..opts()
+                },
+            )
+            .await,
+        )
+        .await?
+        .configured
+        .remove(&label(target))
+        .flatten()
+        .expect("the target should have been built");
+        assert_eq!(20, materializer.materialized.lock().unwrap().len());
+        let paths = built
+            .outputs
+            .iter()
+            .map(|output| {
+                let output = output.as_ref().expect("the output should have been built");
+                output
+                    .values
+                    .iter()
+                    .map(|(artifact, _)| artifact.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        anyhow::Ok((materializer.peak.load(Ordering::SeqCst), paths))
+    };
+
+    let (bounded_peak, bounded) = build_with_limit(Some(4)).await?;
+    assert!(
+        (1..=4).contains(&bounded_peak),
+        "{} materializations were in flight at once",
+        bounded_peak
+    );
+    // Without the limit, more of them run at once.
+    let (unbounded_peak, unbounded) = build_with_limit(None).await?;
+    assert!(unbounded_peak > 4, "{}", unbounded_peak);
+
+    // Outputs are sorted by their index, whatever order they finished in.
+    assert_eq!(20, bounded.len());
+    for (i, paths) in bounded.iter().enumerate() {
+        assert_eq!(1, paths.len());
+        assert!(paths[0].contains(&format!("/out{}.txt`", i)), "{:?}", paths);
+    }
+    assert_eq!(unbounded, bounded);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_analysis_and_materialization_duration() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    for target in ["cell//pkg:srcs1", "cell//pkg:rule1"] {
+        let mut result = collect(build(&dice, target, opts()).await).await?;
+        let built = result
+            .configured
+            .remove(&label(target))
+            .flatten()
+            .expect("the target should have been built");
+        assert!(built.analysis_duration.is_some(), "{}", target);
+        // Nothing is materialized with `MaterializationContext::Skip`.
+        assert_eq!(Duration::ZERO, built.materialization_duration, "{}", target);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_providers_requested() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let events = build_providers(
+        &dice,
+        "cell//pkg:rule1",
+        &ProvidersToBuild::default(),
+        opts(),
+    )
+    .await;
+    let mut result = collect(events).await?;
+    let errors = result
+        .configured
+        .remove(&label("cell//pkg:rule1"))
+        .flatten()
+        .expect("rule1 should have a result")
+        .errors;
+    assert_eq!(1, errors.len());
+    assert_eq!(Some(Category::User), errors[0].get_category());
+    assert!(
+        format!("{:#}", errors[0])
+            .contains("No provider categories requested for `cell//pkg:rule1"),
+        "{:#}",
+        errors[0]
+    );
+
+    // Skippable builds (e.g. of patterns) still go ahead, they just don't build anything.
+    let opts = BuildConfiguredLabelOptions {
+        skippable: true,
+        ..opts()
+    };
+    let events =
+        build_providers(&dice, "cell//pkg:rule1", &ProvidersToBuild::default(), opts).await;
+    let result = collect(events).await?;
+    let rule1 = result.configured[&label("cell//pkg:rule1")]
+        .as_ref()
+        .expect("rule1 should have been built");
+    assert!(rule1.errors.is_empty());
+    assert!(rule1.outputs.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_analysis_only() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    // Requesting no provider categories is expected here, and not worth a warning either.
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let opts = BuildConfiguredLabelOptions {
+        analysis_only: true,
+        message_sink: Some(ConsoleMessageSink::new({
+            let messages = messages.dupe();
+            move |message| messages.lock().unwrap().push(message)
+        })),
+        ..opts()
+    };
+    let events =
+        build_providers(&dice, "cell//pkg:rule1", &ProvidersToBuild::default(), opts).await;
+    let result = collect(events).await?;
+    let rule1 = result.configured[&label("cell//pkg:rule1")]
+        .as_ref()
+        .expect("rule1 should have been analyzed");
+    assert!(rule1.errors.is_empty());
+    assert!(rule1.outputs.is_empty());
+    assert!(messages.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_message_sink() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let opts = BuildConfiguredLabelOptions {
+        message_sink: Some(ConsoleMessageSink::new({
+            let messages = messages.dupe();
+            move |message| messages.lock().unwrap().push(message)
+        })),
+        ..opts()
+    };
+    // `rule1` only returns an empty `DefaultInfo`.
+    build(&dice, "cell//pkg:rule1", opts).await;
+
+    let messages = messages.lock().unwrap();
+    assert_eq!(1, messages.len(), "{:?}", messages);
+    assert!(
+        messages[0].starts_with("Target cell//pkg:rule1 (")
+            && messages[0].contains(") does not have any outputs."),
+        "{}",
+        messages[0]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_outputs_warning_per_provider_type() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let warnings = |providers_to_build: ProvidersToBuild| {
+        let dice = &dice;
+        async move {
+            let messages = Arc::new(Mutex::new(Vec::new()));
+            let opts = BuildConfiguredLabelOptions {
+                message_sink: Some(ConsoleMessageSink::new({
+                    let messages = messages.dupe();
+                    move |message| messages.lock().unwrap().push(message)
+                })),
+                ..opts()
+            };
+            build_providers(dice, "cell//pkg:run_src", &providers_to_build, opts).await;
+            let messages = messages.lock().unwrap().clone();
+            messages
+        }
+    };
+
+    // `run_src` has no default outputs, only a source in its `RunInfo`.
+    let default_only = warnings(ProvidersToBuild::builder().default().build()).await;
+    assert_eq!(1, default_only.len(), "{:?}", default_only);
+    assert!(
+        default_only[0].contains("does not have any outputs"),
+        "{}",
+        default_only[0]
+    );
+    let run_only = warnings(ProvidersToBuild::builder().run().build()).await;
+    assert!(run_only.is_empty(), "{:?}", run_only);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancellation() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    let cancellation = CancellationToken::new();
+    let opts = BuildConfiguredLabelOptions {
+        // Build one output at a time, so that there are outputs left to build after the first.
+        max_concurrent_materializations: Some(1),
+        cancellation: Some(cancellation.clone()),
+        ..opts()
+    };
+    let mut events = build_configured_label(
+        &dice,
+        &MaterializationContext::Skip,
+        label("cell//pkg:srcs_many"),
+        &ProvidersToBuild::builder().all().build(),
+        opts,
+    )
+    .await;
+
+    let mut outputs = 0;
+    while let Some(event) = events.next().await {
+        if event.is_output() {
+            outputs += 1;
+            cancellation.cancel();
+        }
+    }
+    assert_eq!(1, outputs);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancellation_releases_claimed_artifacts() -> anyhow::Result<()> {
+    let materializer = Arc::new(SlowMaterializer::default());
+    let dice = setup_analysis_with_data(None, |data| {
+        data.set_materializer_override(materializer.dupe())
+    })
+    .await?;
+    let target = "cell//pkg:touch_many";
+    // Both builds share this context, and so the map of artifacts enqueued for materialization.
+    let materialization_context = MaterializationContext::force_materializations();
+
+    let cancellation = CancellationToken::new();
+    let mut events = build_configured_label(
+        &dice,
+        &materialization_context,
+        label(target),
+        &ProvidersToBuild::builder().all().build(),
+        BuildConfiguredLabelOptions {
+            cancellation: Some(cancellation.clone()),
+            ..opts()
+        },
+    )
+    .await;
+    while let Some(event) = events.next().await {
+        if event.is_output() {
+            cancellation.cancel();
+        }
+    }
+    drop(events);
+    // The other materializations were still in flight when the build was cancelled.
+    assert!(materializer.materialized.lock().unwrap().len() < 20);
+
+    let built = collect(build_materializing(&dice, &materialization_context, target, opts()).await)
+        .await?
+        .configured
+        .remove(&label(target))
+        .flatten()
+        .expect("the target should have been built");
+    assert_eq!(20, built.outputs.len());
+    // Those which were in flight are materialized by the second build.
+    let materialized = materializer
+        .materialized
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|artifact| artifact.to_string())
+        .sorted()
+        .collect::<Vec<_>>();
+    let outputs = built
+        .outputs
+        .iter()
+        .flat_map(|output| {
+            output
+                .as_ref()
+                .expect("the output should have been built")
+                .values
+                .iter()
+                .map(|(artifact, _)| artifact.to_string())
+                .collect::<Vec<_>>()
+        })
+        .sorted()
+        .collect::<Vec<_>>();
+    assert_eq!(outputs, materialized);
+
+    Ok(())
+}
+
+/// Records the top-level targets it is notified of.
+#[derive(Default)]
+struct TopLevelTargets(Mutex<Vec<ConfiguredTargetLabel>>);
+
+impl BuildSignals for TopLevelTargets {
+    fn top_level_target(&self, label: ConfiguredTargetLabel, _artifacts: Vec<ArtifactGroup>) {
+        self.0.lock().unwrap().push(label);
+    }
+
+    fn final_materialization(
+        &self,
+        _artifact: BuildArtifact,
+        _duration: NodeDuration,
+        _span_id: Option<SpanId>,
+    ) {
+    }
+}
+
+#[tokio::test]
+async fn test_register_as_top_level() -> anyhow::Result<()> {
+    let signals = Arc::new(TopLevelTargets::default());
+    let dice = setup_analysis_with_build_signals(None, Some(signals.dupe())).await?;
+
+    let not_top_level = BuildConfiguredLabelOptions {
+        register_as_top_level: false,
+        ..opts()
+    };
+    assert!(
+        !build(&dice, "cell//pkg:rule1", not_top_level)
+            .await
+            .is_empty()
+    );
+    assert!(signals.0.lock().unwrap().is_empty());
+
+    assert!(!build(&dice, "cell//pkg:rule1", opts()).await.is_empty());
+    assert_eq!(
+        vec![label("cell//pkg:rule1").target().dupe()],
+        *signals.0.lock().unwrap()
+    );
+
+    Ok(())
+}
+
+/// Records the artifacts it's asked to materialize, without materializing anything.
+#[derive(Default)]
+struct RecordingMaterializer(Mutex<Vec<(BuildArtifact, ProjectRelativePathBuf, bool)>>);
+
+#[async_trait]
+impl MaterializerOverride for RecordingMaterializer {
+    async fn materialize_requested_artifact(
+        &self,
+        artifact: &BuildArtifact,
+        path: ProjectRelativePathBuf,
+        required: bool,
+    ) -> anyhow::Result<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .push((artifact.dupe(), path, required));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_materializer_override() -> anyhow::Result<()> {
+    let materializer = Arc::new(RecordingMaterializer::default());
+    let dice = setup_analysis_with_data(None, |data| {
+        data.set_materializer_override(materializer.dupe())
+    })
+    .await?;
+
+    let artifact = BuildArtifact::testing_new(
+        ConfiguredTargetLabel::testing_parse("cell//pkg:rule1", ConfigurationData::testing_new()),
+        ForwardRelativePathBuf::unchecked_new("out.txt".to_owned()),
+        DeferredId::testing_new(0),
+    );
+    dice.try_materialize_requested_artifact(&artifact, true)
+        .await?;
+    dice.try_materialize_requested_artifact(&artifact, false)
+        .await?;
+
+    let path = dice
+        .get_artifact_fs()
+        .await?
+        .resolve_build(artifact.get_path());
+    assert_eq!(
+        vec![
+            (artifact.dupe(), path.clone(), true),
+            (artifact, path, false),
+        ],
+        *materializer.0.lock().unwrap()
+    );
+
+    Ok(())
+}
+
+/// Fails to start the first `failures` analyses with an error of `category`.
+struct FailingAnalysisStart {
+    failures: usize,
+    category: Category,
+    calls: Arc<AtomicUsize>,
+}
+
+struct NoopDebugController;
+
+impl StarlarkDebugController for NoopDebugController {
+    fn initialize(&mut self, _eval: &mut Evaluator) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StarlarkDebuggerHandle for FailingAnalysisStart {
+    async fn start_eval(
+        &self,
+        _description: &str,
+    ) -> anyhow::Result<Box<dyn StarlarkDebugController>> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err(
+                buck2_error::Error::from(anyhow::anyhow!("Failed to start analysis"))
+                    .context(self.category)
+                    .into(),
+            );
+        }
+        Ok(Box::new(NoopDebugController))
+    }
+}
+
+// Starting to debug an evaluation blocks, which needs the multi-threaded runtime.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_analysis_retries_infra_errors() -> anyhow::Result<()> {
+    let build_with_failures = |failures, category| async move {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let dice = setup_analysis_with_data(None, |data| {
+            data.set_starlark_debugger_handle(Some(Box::new(FailingAnalysisStart {
+                failures,
+                category,
+                calls: calls.dupe(),
+            })))
+        })
+        .await?;
+        // `rule3` has no dependencies, so this is its only analysis.
+        let result = collect(build(&dice, "cell//pkg:rule3", opts()).await).await?;
+        let errors = result
+            .configured
+            .get(&label("cell//pkg:rule3"))
+            .and_then(|result| result.as_ref())
+            .map_or(0, |result| result.errors.len())
+            + result.other_errors.len();
+        anyhow::Ok((calls.load(Ordering::SeqCst), errors))
+    };
+
+    // The retry runs the analysis again, rather than getting the cached error back.
+    assert_eq!((2, 0), build_with_failures(1, Category::Infra).await?);
+    // User errors are not retried.
+    assert_eq!((1, 1), build_with_failures(1, Category::User).await?);
+    // Infra errors are only retried a bounded number of times.
+    assert_eq!(
+        (3, 1),
+        build_with_failures(usize::MAX, Category::Infra).await?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failing_run_info_visit() -> anyhow::Result<()> {
+    let dice = setup_analysis(None).await?;
+
+    // The `RunInfo` of `failing_run` has an argument whose artifacts can't be visited.
+    let target = "cell//pkg:failing_run";
+    let result = collect(build(&dice, target, opts()).await).await?;
+    assert!(result.other_errors.is_empty());
+    let built = result.configured[&label(target)]
+        .as_ref()
+        .expect("the target should have been built");
+    assert_eq!(1, built.errors.len(), "{:?}", built.errors);
+    assert!(
+        format!("{:#}", built.errors[0]).contains("Error visiting artifacts of `RunInfo`"),
+        "{:#}",
+        built.errors[0]
+    );
+    // The default output is still built.
+    assert_eq!(1, built.outputs.len());
+    let output = built.outputs[0]
+        .as_ref()
+        .expect("the default output should have been built");
+    assert_eq!(BuildProviderType::Default, output.provider_type);
+
+    Ok(())
+}
+
+/// Only lets materializations finish once `count` of them are in flight, so that they are all ready
+/// at the same time.
+struct GateMaterializer {
+    count: usize,
+    waiting: AtomicUsize,
+    open: Notify,
+}
+
+impl GateMaterializer {
+    fn new(count: usize) -> Self {
+        Self {
+            count,
+            waiting: AtomicUsize::new(0),
+            open: Notify::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl MaterializerOverride for GateMaterializer {
+    async fn materialize_requested_artifact(
+        &self,
+        _artifact: &BuildArtifact,
+        _path: ProjectRelativePathBuf,
+        _required: bool,
+    ) -> anyhow::Result<()> {
+        let open = self.open.notified();
+        if self.waiting.fetch_add(1, Ordering::SeqCst) + 1 == self.count {
+            self.open.notify_waiters();
+            // Don't finish before the others, which only get polled again after this yields.
+            tokio::task::yield_now().await;
+        } else {
+            open.await;
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_build_configured_labels_priority() -> anyhow::Result<()> {
+    let dice = setup_analysis_with_data(None, |data| {
+        data.set_materializer_override(Arc::new(GateMaterializer::new(4)))
+    })
+    .await?;
+
+    // Each of these has a single output, and those are all ready at the same time.
+    let labels = ["touch2", "touch0", "touch3", "touch1"]
+        .map(|name| label(&format!("cell//pkg:{}", name)))
+        .to_vec();
+    let providers_to_build = ProvidersToBuild::builder().all().build();
+    let materialization_context = MaterializationContext::force_materializations();
+    let outputs = build_configured_labels(
+        &dice,
+        &materialization_context,
+        labels.clone(),
+        &providers_to_build,
+        opts(),
+        None,
+    )
+    .filter(|event| future::ready(event.is_output()))
+    .map(|event| event.label().clone())
+    .collect::<Vec<_>>()
+    .await;
+    assert_eq!(labels, outputs);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_concurrent_targets() -> anyhow::Result<()> {
+    let build_with_limit = |max_concurrent_targets| async move {
+        let materializer = Arc::new(SlowMaterializer::default());
+        let dice = setup_analysis_with_data(None, |data| {
+            data.set_materializer_override(materializer.dupe())
+        })
+        .await?;
+        let labels = (0..4)
+            .map(|i| label(&format!("cell//pkg:touch{}", i)))
+            .collect::<Vec<_>>();
+        let providers_to_build = ProvidersToBuild::builder().all().build();
+        let materialization_context = MaterializationContext::force_materializations();
+        let result = BuildTargetResult::collect_stream(
+            build_configured_labels(
+                &dice,
+                &materialization_context,
+                labels.clone(),
+                &providers_to_build,
+                opts(),
+                max_concurrent_targets,
+            )
+            .map(BuildEvent::Configured),
+            false,
+        )
+        .await?;
+        for label in &labels {
+            let built = result.configured[label]
+                .as_ref()
+                .expect("the target should have been built");
+            assert_eq!(1, built.outputs.len(), "{}", label);
+        }
+        assert_eq!(4, materializer.materialized.lock().unwrap().len());
+        anyhow::Ok(materializer.peak.load(Ordering::SeqCst))
+    };
+
+    // Each target has a single output, so no more of them are materialized at once than targets are
+    // built at once.
+    let bounded = build_with_limit(Some(2)).await?;
+    assert!((1..=2).contains(&bounded), "{}", bounded);
+    let unbounded = build_with_limit(None).await?;
+    assert!(unbounded > 2, "{}", unbounded);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_in_both_default_categories() -> anyhow::Result<()> {
+    let materializer = Arc::new(RecordingMaterializer::default());
+    let dice = setup_analysis_with_data(None, |data| {
+        data.set_materializer_override(materializer.dupe())
+    })
+    .await?;
+
+    // `shared` has the same artifact in its default outputs and in its other outputs.
+    let target = "cell//pkg:shared";
+    let events = build_materializing(
+        &dice,
+        &MaterializationContext::force_materializations(),
+        target,
+        opts(),
+    )
+    .await;
+    assert_eq!(1, events.iter().filter(|event| event.is_output()).count());
+    let built = collect(events)
+        .await?
+        .configured
+        .remove(&label(target))
+        .flatten();
+    let built = built.expect("the target should have been built");
+    assert_eq!(1, built.outputs.len());
+    assert_eq!(
+        BuildProviderType::Default,
+        built.outputs[0].as_ref().unwrap().provider_type
+    );
+    assert_eq!(1, materializer.0.lock().unwrap().len());
+
+    Ok(())
+}
@@ -14,6 +14,7 @@ mod actions;
 mod analysis;
 mod artifact_groups;
 mod attrs;
+mod build;
 mod deferred;
 mod interpreter;
 mod nodes;
@@ -22,6 +23,7 @@ mod nodes;
 fn init_late_bindings_for_test() {
     #[ctor::ctor]
     fn init() {
+        buck2_action_impl::init_late_bindings();
         buck2_analysis::init_late_bindings();
         buck2_anon_target::init_late_bindings();
         buck2_configured::init_late_bindings();
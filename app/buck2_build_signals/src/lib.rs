@@ -54,6 +54,9 @@ pub enum CriticalPathBackendName {
     LongestPathGraph,
     #[display(fmt = "default")]
     Default,
+    /// Like `Default`, but also reports when every action started and finished.
+    #[display(fmt = "timeline")]
+    Timeline,
 }
 
 impl FromStr for CriticalPathBackendName {
@@ -68,6 +71,10 @@ impl FromStr for CriticalPathBackendName {
             return Ok(Self::Default);
         }
 
+        if s == "timeline" {
+            return Ok(Self::Timeline);
+        }
+
         Err(anyhow::anyhow!("Invalid backend name: `{}`", s))
     }
 }
@@ -76,6 +83,13 @@ pub struct BuildSignalsContext {
     pub command_name: String,
     pub metadata: HashMap<String, String>,
     pub isolation_prefix: FileNameBuf,
+    /// A free-form label supplied by the user (e.g. a commit hash or experiment name) to group
+    /// critical paths by in offline analysis.
+    pub invocation_label: Option<String>,
+    /// Whether to keep transitive set projections in the reported critical path, where they
+    /// otherwise get dropped. They take no time, but show why a path goes through a tset. The
+    /// server sets this from `BUCK2_CRITICAL_PATH_INCLUDE_TRANSITIVE_SET_PROJECTIONS`.
+    pub include_transitive_set_projections: bool,
 }
 
 /// Created along with the BuildSignalsInstaller (ideally, BuildSignalsInstaller's definition would
@@ -109,6 +123,9 @@ pub trait FinishBuildSignals: Send {
 /// invoking the called function with a live BuildSignalSender that can be used to send events to
 /// the listening receiver. Upon return of `scope`, the sender terminates the receiver by sending a
 /// `BuildFinished` signal and joins the receiver task.
+///
+/// `backend` selects how the critical path is computed. The server reads it from the
+/// `buck2.critical_path_backend2` buckconfig, but embedders and tests can pass any backend.
 pub async fn scope<F, R, Fut>(
     deferred: Box<dyn DeferredBuildSignals>,
     events: EventDispatcher,
@@ -8,6 +8,8 @@
  */
 
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use buck2_build_api::actions::RegisteredAction;
 use buck2_build_signals::CriticalPathBackendName;
@@ -24,6 +26,7 @@ pub(crate) trait BuildListenerBackend {
         key: NodeKey,
         value: Option<Arc<RegisteredAction>>,
         duration: NodeDuration,
+        interval: Option<(Instant, Instant)>,
         dep_keys: impl IntoIterator<Item = NodeKey>,
         span_ids: SmallVec<[SpanId; 1]>,
     );
@@ -34,6 +37,12 @@ pub(crate) trait BuildListenerBackend {
         artifacts: impl IntoIterator<Item = NodeKey>,
     );
 
+    /// The total duration of the critical path through the nodes processed so far, if the backend
+    /// can compute it cheaply enough for live reporting.
+    fn current_critical_path_total(&self) -> Option<Duration> {
+        None
+    }
+
     fn finish(self) -> anyhow::Result<BuildInfo>;
 
     fn name() -> CriticalPathBackendName;
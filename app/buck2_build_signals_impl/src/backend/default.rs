@@ -13,11 +13,16 @@ use std::fmt::Display;
 use std::hash::Hash;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context as _;
+use buck2_artifact::actions::key::ActionKey;
+use buck2_build_api::actions::calculation::BuildKey;
 use buck2_build_api::actions::RegisteredAction;
 use buck2_build_signals::CriticalPathBackendName;
 use buck2_build_signals::NodeDuration;
+use buck2_core::buck2_env;
+use buck2_events::dispatch::console_warning;
 use buck2_events::span::SpanId;
 use dupe::Dupe;
 use gazebo::prelude::VecExt;
@@ -25,7 +30,9 @@ use itertools::Itertools;
 use smallvec::SmallVec;
 
 use crate::backend::backend::BuildListenerBackend;
+use crate::peak_concurrency;
 use crate::BuildInfo;
+use crate::CriticalPathEntry;
 use crate::NodeData;
 use crate::NodeKey;
 
@@ -36,6 +43,8 @@ struct CriticalPathNode<TKey: Eq, TValue> {
     /// The value of this node. If None, this node just won't be included when displaying.
     pub value: TValue,
     pub prev: Option<TKey>,
+    /// How many of the nodes processed so far depend on this node.
+    pub in_degree: u64,
 }
 
 fn extract_critical_path<TKey: Hash + Eq, TValue>(
@@ -44,11 +53,21 @@ fn extract_critical_path<TKey: Hash + Eq, TValue>(
 where
     TKey: Display,
 {
-    let mut tail = predecessors
+    let tail = predecessors
         .iter()
         .max_by_key(|(_key, data)| data.duration)
         .map(|q| q.0);
+    extract_critical_path_to(predecessors, tail)
+}
 
+/// Extract the critical path ending at `tail`.
+fn extract_critical_path_to<'a, TKey: Hash + Eq, TValue>(
+    predecessors: &'a HashMap<TKey, CriticalPathNode<TKey, TValue>>,
+    mut tail: Option<&'a TKey>,
+) -> anyhow::Result<Vec<(&'a TKey, &'a TValue, Duration)>>
+where
+    TKey: Display,
+{
     let mut path = vec![];
     let mut visited = HashSet::new();
 
@@ -75,20 +94,305 @@ where
     Ok(path)
 }
 
+/// Extract the critical path as if the nodes in `excluded` had taken no time at all, to estimate
+/// how much faster the build would be if they were. `nodes` has the time spent in each node itself
+/// (not including its dependencies) and all of its dependencies: a dependency that wasn't the
+/// longest one originally can become part of the path once `excluded` nodes are instantaneous.
+fn critical_path_without<'a, TKey: Hash + Eq>(
+    nodes: &'a HashMap<TKey, (Duration, Vec<TKey>)>,
+    excluded: &HashSet<TKey>,
+) -> anyhow::Result<Vec<(&'a TKey, Duration)>>
+where
+    TKey: Display,
+{
+    let mut predecessors =
+        HashMap::<&TKey, CriticalPathNode<&TKey, ()>>::with_capacity(nodes.len());
+    let mut in_progress = HashSet::new();
+
+    for root in nodes.keys() {
+        let mut stack = vec![root];
+        while let Some(&key) = stack.last() {
+            if predecessors.contains_key(key) {
+                stack.pop();
+                continue;
+            }
+
+            let (duration, deps) = &nodes[key];
+            let pending = deps
+                .iter()
+                .filter(|dep| nodes.contains_key(*dep) && !predecessors.contains_key(*dep))
+                .collect::<Vec<_>>();
+            if !pending.is_empty() {
+                // We only come back to a node once all its dependencies are done, unless one of
+                // them depends on it.
+                if !in_progress.insert(key) {
+                    return Err(anyhow::anyhow!(
+                        "Cycle in critical path: visited {} twice",
+                        key
+                    ));
+                }
+                stack.extend(pending);
+                continue;
+            }
+
+            let longest = deps
+                .iter()
+                .filter_map(|dep| predecessors.get_key_value(dep))
+                .max_by_key(|(_dep, node)| node.duration)
+                .map(|(dep, node)| (*dep, node.duration));
+            let duration = if excluded.contains(key) {
+                Duration::ZERO
+            } else {
+                *duration
+            };
+            predecessors.insert(
+                key,
+                CriticalPathNode {
+                    duration: longest.map_or(Duration::ZERO, |(_dep, d)| d) + duration,
+                    value: (),
+                    prev: longest.map(|(dep, _d)| dep),
+                    in_degree: 0,
+                },
+            );
+            in_progress.remove(key);
+            stack.pop();
+        }
+    }
+
+    Ok(extract_critical_path(&predecessors)?.into_map(|(key, _value, duration)| (*key, duration)))
+}
+
+struct FinishPathNode<TKey> {
+    /// When this node finished. For nodes we have no timestamps for, this is when the last of
+    /// their dependencies finished, if known.
+    pub end: Option<Instant>,
+    /// The dependency which finished last.
+    pub prev: Option<TKey>,
+    /// The number of nodes in the chain ending at this node. Nodes without timestamps finish at
+    /// the same time as their last dependency, so this breaks the tie in favor of the dependent.
+    pub depth: usize,
+}
+
+/// Extract the chain of nodes which gated the end of the build: start from the node which finished
+/// last, and repeatedly step to whichever of its dependencies finished last.
+///
+/// Unlike `extract_critical_path`, which follows the longest cumulative duration, this follows
+/// when nodes actually finished. The two diverge when nodes start late (e.g. because they waited
+/// for resources), which is typical of builds that tail off slowly.
+fn extract_finish_critical_path<TKey: Hash + Eq>(
+    nodes: &HashMap<TKey, FinishPathNode<TKey>>,
+) -> anyhow::Result<Vec<&TKey>>
+where
+    TKey: Display,
+{
+    let mut tail = nodes
+        .iter()
+        .filter_map(|(key, node)| Some((key, (node.end?, node.depth))))
+        .max_by_key(|(_key, end)| *end)
+        .map(|(key, _end)| key);
+
+    let mut path = vec![];
+    let mut visited = HashSet::new();
+
+    while let Some(v) = tail.take() {
+        if !visited.insert(v) {
+            return Err(anyhow::anyhow!(
+                "Cycle in critical path: visited {} twice",
+                v
+            ));
+        }
+
+        path.push(v);
+        tail = nodes.get(v).and_then(|node| node.prev.as_ref());
+    }
+
+    path.reverse();
+    Ok(path)
+}
+
+/// An action which accounts for most of the critical path of a top-level target, making it the
+/// obvious thing to optimize.
+#[derive(Debug, PartialEq)]
+pub(crate) struct DominantAction {
+    /// The analysis of the top-level target.
+    pub(crate) target: NodeKey,
+    pub(crate) action: NodeKey,
+    /// The fraction of the target's critical path spent in `action`.
+    pub(crate) share: f64,
+}
+
+/// Analyses `DefaultBackend` can do on top of finding the critical path, which are too expensive to
+/// do by default.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct DefaultBackendOptions {
+    /// Compute how much shorter the critical path would be if each of its nodes took no time at
+    /// all (see `CriticalPathEntry::potential_improvement`). This keeps every edge of the build
+    /// graph in memory, and recomputes the critical path once per node on it.
+    pub(crate) potentials: bool,
+    /// Compute the chain of nodes which gated the end of the build (see
+    /// `extract_finish_critical_path`), which keeps track of when every node finished.
+    pub(crate) finish_critical_path: bool,
+}
+
+impl DefaultBackendOptions {
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            potentials: buck2_env!("BUCK2_CRITICAL_PATH_POTENTIALS", bool)?,
+            finish_critical_path: buck2_env!("BUCK2_FINISH_CRITICAL_PATH", bool)?,
+        })
+    }
+}
+
 pub(crate) struct DefaultBackend {
     predecessors: HashMap<NodeKey, CriticalPathNode<NodeKey, NodeData>>,
+    /// The time spent in each node itself and all of its dependencies, if computing potentials.
+    graph: Option<HashMap<NodeKey, (Duration, Vec<NodeKey>)>>,
     num_nodes: u64,
     num_edges: u64,
+    /// The longest cumulative duration of any node processed so far, i.e. the total duration of
+    /// the critical path `finish` would return right now.
+    current_critical_path_total: Duration,
+    /// Tracks the last dependency to finish for each node, if computing the finish critical path.
+    finish_predecessors: Option<HashMap<NodeKey, FinishPathNode<NodeKey>>>,
+    /// The analysis of each top-level target, and the nodes producing its artifacts.
+    top_level_targets: Vec<(NodeKey, Vec<NodeKey>)>,
+    /// The node with the highest in-degree so far. Of nodes with the same in-degree, the first to
+    /// reach it is kept, so that the result doesn't depend on the iteration order of any map.
+    max_fan_out: Option<(NodeKey, u64)>,
+    /// The start and end of every action, to compute peak concurrency.
+    action_intervals: Vec<(Instant, Instant)>,
 }
 
 impl DefaultBackend {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(options: DefaultBackendOptions) -> Self {
         Self {
             predecessors: HashMap::new(),
+            graph: options.potentials.then(HashMap::new),
             num_nodes: 0,
             num_edges: 0,
+            current_critical_path_total: Duration::ZERO,
+            finish_predecessors: options.finish_critical_path.then(HashMap::new),
+            top_level_targets: Vec::new(),
+            max_fan_out: None,
+            action_intervals: Vec::new(),
         }
     }
+
+    /// The chain of nodes which gated the end of the build, see `extract_finish_critical_path`.
+    /// Empty unless computing it.
+    pub(crate) fn finish_critical_path(&self) -> anyhow::Result<Vec<NodeKey>> {
+        match &self.finish_predecessors {
+            Some(finish_predecessors) => {
+                Ok(extract_finish_critical_path(finish_predecessors)?.into_map(|key| key.dupe()))
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The critical path (with the time spent in each node itself) as if the nodes in `excluded`
+    /// had taken no time at all. `None` unless computing potentials, which is what keeps track of
+    /// every dependency this needs.
+    pub(crate) fn critical_path_without(
+        &self,
+        excluded: &HashSet<NodeKey>,
+    ) -> anyhow::Result<Option<Vec<(NodeKey, Duration)>>> {
+        let Some(graph) = &self.graph else {
+            return Ok(None);
+        };
+        Ok(Some(
+            critical_path_without(graph, excluded)?
+                .into_map(|(key, duration)| (key.dupe(), duration)),
+        ))
+    }
+
+    /// How much shorter the critical path would be if each of its nodes took no time at all, or
+    /// `None` unless computing potentials.
+    fn potentials(
+        &self,
+        critical_path: &[(&NodeKey, &NodeData, Duration)],
+    ) -> anyhow::Result<Option<Vec<Duration>>> {
+        if self.graph.is_none() {
+            return Ok(None);
+        }
+        let total = critical_path
+            .iter()
+            .map(|(_key, _data, duration)| *duration)
+            .sum::<Duration>();
+        let mut potentials = Vec::with_capacity(critical_path.len());
+        for (key, _data, _duration) in critical_path {
+            let without = self
+                .critical_path_without(&HashSet::from([(*key).dupe()]))?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(_key, duration)| duration)
+                .sum::<Duration>();
+            potentials.push(total.saturating_sub(without));
+        }
+        Ok(Some(potentials))
+    }
+
+    /// The top-level targets whose critical path spends more than `threshold` (a fraction) of its
+    /// duration in a single action. Paths with a single action are trivially dominated by it, so
+    /// they are ignored.
+    pub(crate) fn dominant_actions(&self, threshold: f64) -> anyhow::Result<Vec<DominantAction>> {
+        let mut dominant_actions = Vec::new();
+        for (target, artifacts) in &self.top_level_targets {
+            let tail = std::iter::once(target)
+                .chain(artifacts)
+                .filter_map(|key| self.predecessors.get_key_value(key))
+                .max_by_key(|(_key, node)| node.duration)
+                .map(|(key, _node)| key);
+            let path = extract_critical_path_to(&self.predecessors, tail)?;
+
+            let total = path
+                .iter()
+                .map(|(_key, _data, duration)| *duration)
+                .sum::<Duration>();
+            let actions = path
+                .iter()
+                .filter(|(key, _data, _duration)| matches!(key, NodeKey::BuildKey(..)))
+                .collect::<Vec<_>>();
+            if total.is_zero() || actions.len() < 2 {
+                continue;
+            }
+
+            if let Some((action, _data, duration)) = actions
+                .into_iter()
+                .max_by_key(|(_key, _data, duration)| *duration)
+            {
+                let share = duration.as_secs_f64() / total.as_secs_f64();
+                if share > threshold {
+                    dominant_actions.push(DominantAction {
+                        target: target.dupe(),
+                        action: (*action).dupe(),
+                        share,
+                    });
+                }
+            }
+        }
+        Ok(dominant_actions)
+    }
+
+    /// When each action started and ended relative to the start of the build, assuming every node
+    /// started as soon as its longest dependency finished: an action ends at its cumulative
+    /// duration, and starts at that of its longest dependency.
+    pub(crate) fn action_timeline(&self) -> Vec<(ActionKey, (Duration, Duration))> {
+        let mut timeline = self
+            .predecessors
+            .iter()
+            .filter_map(|(key, node)| match key {
+                NodeKey::BuildKey(BuildKey(action)) => {
+                    let end = node.duration;
+                    let start = end.saturating_sub(node.value.duration.critical_path_duration());
+                    Some((action.dupe(), (start, end)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Sort by key too, so that the order doesn't depend on the iteration order of the map.
+        timeline.sort_by_cached_key(|(action, times)| (*times, action.to_string()));
+        timeline
+    }
 }
 
 impl BuildListenerBackend for DefaultBackend {
@@ -97,15 +401,42 @@ impl BuildListenerBackend for DefaultBackend {
         key: NodeKey,
         value: Option<Arc<RegisteredAction>>,
         duration: NodeDuration,
+        interval: Option<(Instant, Instant)>,
         dep_keys: impl IntoIterator<Item = NodeKey>,
         span_ids: SmallVec<[SpanId; 1]>,
     ) {
+        let mut last_finished_dep: Option<(NodeKey, Instant)> = None;
+        let mut deps = Vec::new();
         let longest_ancestor = dep_keys
             .into_iter()
             .unique()
             .filter_map(|node_key| {
                 self.num_edges += 1;
-                let node_data = self.predecessors.get(&node_key)?;
+                if self.graph.is_some() {
+                    deps.push(node_key.dupe());
+                }
+                if let Some(end) = self
+                    .finish_predecessors
+                    .as_ref()
+                    .and_then(|finish_predecessors| finish_predecessors.get(&node_key))
+                    .and_then(|node| node.end)
+                {
+                    if last_finished_dep
+                        .as_ref()
+                        .map_or(true, |(_key, last_end)| end > *last_end)
+                    {
+                        last_finished_dep = Some((node_key.dupe(), end));
+                    }
+                }
+                let node_data = self.predecessors.get_mut(&node_key)?;
+                node_data.in_degree += 1;
+                if self
+                    .max_fan_out
+                    .as_ref()
+                    .map_or(true, |(_key, max)| node_data.in_degree > *max)
+                {
+                    self.max_fan_out = Some((node_key.dupe(), node_data.in_degree));
+                }
                 Some((node_key, node_data.duration))
             })
             .max_by_key(|d| d.1);
@@ -121,34 +452,104 @@ impl BuildListenerBackend for DefaultBackend {
                 prev: Some(key.dupe()),
                 value,
                 duration: ancestor_duration + duration.critical_path_duration(),
+                in_degree: 0,
             },
             None => CriticalPathNode {
                 prev: None,
                 value,
                 duration: duration.critical_path_duration(),
+                in_degree: 0,
             },
         };
 
         self.num_nodes += 1;
+        self.current_critical_path_total =
+            std::cmp::max(self.current_critical_path_total, node.duration);
+        if let Some(finish_predecessors) = &mut self.finish_predecessors {
+            let depth = last_finished_dep
+                .as_ref()
+                .and_then(|(key, _end)| finish_predecessors.get(key))
+                .map_or(1, |node| node.depth + 1);
+            finish_predecessors.insert(
+                key.dupe(),
+                FinishPathNode {
+                    end: interval
+                        .map(|(_start, end)| end)
+                        .or(last_finished_dep.as_ref().map(|(_key, end)| *end)),
+                    depth,
+                    prev: last_finished_dep.map(|(key, _end)| key),
+                },
+            );
+        }
+        if let Some(graph) = &mut self.graph {
+            graph.insert(key.dupe(), (duration.critical_path_duration(), deps));
+        }
+        if let Some(interval) = interval {
+            self.action_intervals.push(interval);
+        }
         self.predecessors.insert(key, node);
     }
 
     fn process_top_level_target(
         &mut self,
-        _analysis: NodeKey,
-        _artifacts: impl IntoIterator<Item = NodeKey>,
+        analysis: NodeKey,
+        artifacts: impl IntoIterator<Item = NodeKey>,
     ) {
+        self.top_level_targets
+            .push((analysis, artifacts.into_iter().collect()));
+    }
+
+    /// Unlike `finish`, this doesn't walk the path, so it's O(1).
+    fn current_critical_path_total(&self) -> Option<Duration> {
+        Some(self.current_critical_path_total)
     }
 
     fn finish(self) -> anyhow::Result<BuildInfo> {
-        let critical_path = extract_critical_path(&self.predecessors)
-            .context("Error extracting critical path")?
-            .into_map(|(key, data, _duration)| (key.dupe(), data.clone(), None));
+        if let Some(threshold) =
+            buck2_env!("BUCK2_CRITICAL_PATH_DOMINANT_ACTION_THRESHOLD", type=f64)?
+        {
+            for DominantAction {
+                target,
+                action,
+                share,
+            } in self.dominant_actions(threshold)?
+            {
+                console_warning(format!(
+                    "`{}` accounts for {:.0}% of the critical path of `{}`",
+                    action,
+                    share * 100.0,
+                    target
+                ));
+            }
+        }
+
+        let critical_path =
+            extract_critical_path(&self.predecessors).context("Error extracting critical path")?;
+        let potentials = self
+            .potentials(&critical_path)
+            .context("Error computing critical path potentials")?;
+        let critical_path = critical_path
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, data, _duration))| CriticalPathEntry {
+                key: key.dupe(),
+                data: data.clone(),
+                potential_improvement: potentials.as_ref().map(|potentials| potentials[i]),
+            })
+            .collect();
 
         Ok(BuildInfo {
             critical_path,
             num_nodes: self.num_nodes,
             num_edges: self.num_edges,
+            // Nodes are weighed by `NodeDuration::critical_path_duration`.
+            uses_total_duration: true,
+            max_fan_out: self.max_fan_out.clone(),
+            action_timeline: Vec::new(),
+            finish_critical_path: self
+                .finish_critical_path()
+                .context("Error extracting finish critical path")?,
+            peak_concurrency: peak_concurrency(&self.action_intervals),
         })
     }
 
@@ -159,7 +560,16 @@ impl BuildListenerBackend for DefaultBackend {
 
 #[cfg(test)]
 mod tests {
+    use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+    use buck2_artifact::artifact::build_artifact::BuildArtifact;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_build_api::actions::calculation::BuildKey;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+
     use super::*;
+    use crate::folded_stacks;
 
     type CriticalPathMap = HashMap<i32, CriticalPathNode<i32, Option<i32>>>;
 
@@ -175,6 +585,7 @@ mod tests {
                 duration,
                 value: Some(key),
                 prev,
+                in_degree: 0,
             },
         );
     }
@@ -217,6 +628,434 @@ mod tests {
         );
     }
 
+    #[test]
+    fn long_path_folded_stacks() {
+        let mut predecessors = HashMap::new();
+        cp_insert(&mut predecessors, 1, None, Duration::from_secs(5));
+        cp_insert(&mut predecessors, 2, Some(1), Duration::from_secs(11));
+        cp_insert(&mut predecessors, 3, Some(2), Duration::from_secs(18));
+        cp_insert(&mut predecessors, 4, Some(1), Duration::from_secs(14));
+        let path = extract_critical_path(&predecessors).unwrap();
+        assert_eq!(
+            folded_stacks(path.iter().map(|(key, _value, duration)| (key, *duration))),
+            "1 5000000\n1;2 6000000\n1;2;3 7000000\n",
+        );
+    }
+
+    fn graph(nodes: &[(i32, u64, &[i32])]) -> HashMap<i32, (Duration, Vec<i32>)> {
+        nodes
+            .iter()
+            .map(|(key, secs, deps)| (*key, (Duration::from_secs(*secs), deps.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn long_path_without_bottleneck() {
+        // Same shape as `long_path`, with 5 depending on both 3 and 4.
+        let graph = graph(&[
+            (1, 5, &[]),
+            (2, 6, &[1]),
+            (3, 7, &[2]),
+            (4, 9, &[1]),
+            (5, 1, &[3, 4]),
+        ]);
+
+        // With 2 taking no time, 1 -> 2 -> 3 only takes 12s, so 1 -> 4 becomes the critical path,
+        // even though 4 wasn't the longest dependency of 5 originally.
+        let path = critical_path_without(&graph, &HashSet::from([2])).unwrap();
+        assert_eq!(
+            path,
+            vec![
+                (&1, Duration::from_secs(5)),
+                (&4, Duration::from_secs(9)),
+                (&5, Duration::from_secs(1)),
+            ],
+        );
+        assert_eq!(
+            Duration::from_secs(15),
+            path.iter()
+                .map(|(_key, duration)| *duration)
+                .sum::<Duration>()
+        );
+
+        // Excluding nothing gives the actual critical path.
+        assert_eq!(
+            vec![
+                (&1, Duration::from_secs(5)),
+                (&2, Duration::from_secs(6)),
+                (&3, Duration::from_secs(7)),
+                (&5, Duration::from_secs(1)),
+            ],
+            critical_path_without(&graph, &HashSet::new()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn cycle_path_without() {
+        let graph = graph(&[(1, 5, &[2]), (2, 6, &[1])]);
+        assert!(critical_path_without(&graph, &HashSet::new()).is_err());
+    }
+
+    fn key(path: &str) -> NodeKey {
+        NodeKey::Materialization(BuildArtifact::testing_new(
+            ConfiguredTargetLabel::testing_parse("cell//pkg:a", ConfigurationData::testing_new()),
+            ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+            DeferredId::testing_new(0),
+        ))
+    }
+
+    fn duration(secs: u64) -> NodeDuration {
+        NodeDuration {
+            user: Duration::from_secs(secs),
+            total: Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn current_critical_path_total() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        assert_eq!(Some(Duration::ZERO), backend.current_critical_path_total());
+
+        // Same shape as `long_path`: 1 -> 2 -> 3 and 1 -> 4.
+        let mut process = |name: &str, secs, deps: &[&str]| {
+            backend.process_node(
+                key(name),
+                None,
+                duration(secs),
+                None,
+                deps.iter().map(|dep| key(dep)),
+                SmallVec::new(),
+            );
+        };
+        process("1", 5, &[]);
+        process("2", 6, &["1"]);
+        process("4", 9, &["1"]);
+        process("3", 7, &["2"]);
+        assert_eq!(
+            Some(Duration::from_secs(18)),
+            backend.current_critical_path_total()
+        );
+
+        let total = backend.current_critical_path_total().unwrap();
+        let path = backend.finish().unwrap().critical_path;
+        assert_eq!(
+            total,
+            path.iter().map(|entry| entry.duration()).sum::<Duration>()
+        );
+    }
+
+    #[test]
+    fn finish_critical_path() {
+        let base = Instant::now();
+        let mut backend = DefaultBackend::new(DefaultBackendOptions {
+            finish_critical_path: true,
+            ..Default::default()
+        });
+        let mut process = |name: &str, interval: Option<(u64, u64)>, deps: &[&str]| {
+            backend.process_node(
+                key(name),
+                None,
+                duration(interval.map_or(0, |(start, end)| end - start)),
+                interval.map(|(start, end)| {
+                    (
+                        base + Duration::from_secs(start),
+                        base + Duration::from_secs(end),
+                    )
+                }),
+                deps.iter().map(|dep| key(dep)),
+                SmallVec::new(),
+            );
+        };
+        // 1 runs for longest, but 3 only started after waiting on resources, so 2 -> 3 is what
+        // the build finished on.
+        process("1", Some((0, 10)), &[]);
+        process("2", Some((0, 1)), &[]);
+        process("3", Some((12, 14)), &["2"]);
+        // 4 has no timestamps, so it's considered to finish with its dependencies.
+        process("4", None, &["3"]);
+
+        assert_eq!(
+            vec![key("2"), key("3"), key("4")],
+            backend.finish_critical_path().unwrap()
+        );
+        assert_eq!(
+            vec![key("1")],
+            extract_critical_path(&backend.predecessors)
+                .unwrap()
+                .into_map(|(key, _value, _duration)| key.dupe())
+        );
+        assert_eq!(
+            vec![key("2"), key("3"), key("4")],
+            backend.finish().unwrap().finish_critical_path
+        );
+    }
+
+    #[test]
+    fn no_finish_critical_path() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        let now = Instant::now();
+        backend.process_node(
+            key("1"),
+            None,
+            duration(1),
+            Some((now, now + Duration::from_secs(1))),
+            std::iter::empty(),
+            SmallVec::new(),
+        );
+        assert!(backend.finish().unwrap().finish_critical_path.is_empty());
+    }
+
+    #[test]
+    fn cycle_finish_path() {
+        let mut nodes = HashMap::new();
+        let end = Some(Instant::now());
+        let node = |prev| FinishPathNode {
+            end,
+            prev: Some(prev),
+            depth: 1,
+        };
+        nodes.insert(1, node(2));
+        nodes.insert(2, node(1));
+        assert!(extract_finish_critical_path(&nodes).is_err());
+    }
+
+    fn action_key(name: &str) -> NodeKey {
+        let target = format!("cell//pkg:{}", name);
+        NodeKey::BuildKey(BuildKey(
+            BuildArtifact::testing_new(
+                ConfiguredTargetLabel::testing_parse(&target, ConfigurationData::testing_new()),
+                ForwardRelativePathBuf::unchecked_new("out".to_owned()),
+                DeferredId::testing_new(0),
+            )
+            .key()
+            .dupe(),
+        ))
+    }
+
+    #[test]
+    fn dominant_actions() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        let mut process = |name: &str, secs, deps: &[&str]| {
+            backend.process_node(
+                action_key(name),
+                None,
+                duration(secs),
+                None,
+                deps.iter().map(|dep| action_key(dep)),
+                SmallVec::new(),
+            );
+        };
+        // `slow` dominates the path of `top1`, while the path of `top2` is evenly spread.
+        process("fast", 1, &[]);
+        process("slow", 9, &["fast"]);
+        process("even1", 5, &[]);
+        process("even2", 5, &["even1"]);
+        backend.process_top_level_target(key("top1"), [action_key("slow")]);
+        backend.process_top_level_target(key("top2"), [action_key("even2")]);
+
+        assert_eq!(
+            vec![DominantAction {
+                target: key("top1"),
+                action: action_key("slow"),
+                share: 0.9,
+            }],
+            backend.dominant_actions(0.8).unwrap()
+        );
+        assert_eq!(2, backend.dominant_actions(0.4).unwrap().len());
+    }
+
+    #[test]
+    fn potentials() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions {
+            potentials: true,
+            ..Default::default()
+        });
+        let mut process = |name: &str, secs, deps: &[&str]| {
+            backend.process_node(
+                action_key(name),
+                None,
+                duration(secs),
+                None,
+                deps.iter().map(|dep| action_key(dep)),
+                SmallVec::new(),
+            );
+        };
+        // Same graph as `long_path_without_bottleneck`.
+        process("1", 5, &[]);
+        process("2", 6, &["1"]);
+        process("3", 7, &["2"]);
+        process("4", 9, &["1"]);
+        process("5", 1, &["3", "4"]);
+
+        assert_eq!(
+            Some(vec![
+                (action_key("1"), Duration::from_secs(5)),
+                (action_key("2"), Duration::from_secs(4)),
+                (action_key("3"), Duration::from_secs(4)),
+                (action_key("5"), Duration::from_secs(1)),
+            ]),
+            backend.finish().unwrap().potentials()
+        );
+    }
+
+    #[test]
+    fn no_potentials() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        backend.process_node(
+            action_key("a"),
+            None,
+            duration(1),
+            None,
+            std::iter::empty(),
+            SmallVec::new(),
+        );
+        let info = backend.finish().unwrap();
+        assert_eq!(1, info.critical_path.len());
+        assert!(info.potentials().is_none());
+
+        let info = BuildInfo {
+            critical_path: vec![CriticalPathEntry {
+                key: action_key("a"),
+                data: info.critical_path[0].data.clone(),
+                potential_improvement: Some(Duration::from_secs(1)),
+            }],
+            ..info
+        };
+        assert_eq!(
+            Some(vec![(action_key("a"), Duration::from_secs(1))]),
+            info.potentials()
+        );
+    }
+
+    #[test]
+    fn max_fan_out() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        let mut process = |name: &str, deps: &[&str]| {
+            backend.process_node(
+                action_key(name),
+                None,
+                duration(1),
+                None,
+                deps.iter().map(|dep| action_key(dep)),
+                SmallVec::new(),
+            );
+        };
+        // A diamond: `b` and `c` both depend on `a`, and `d` depends on both of them.
+        process("a", &[]);
+        process("b", &["a"]);
+        process("c", &["a"]);
+        process("d", &["b", "c"]);
+
+        let info = backend.finish().unwrap();
+        assert_eq!(Some((action_key("a").to_string(), 2)), info.max_fan_out());
+    }
+
+    #[test]
+    fn peak_concurrency() {
+        let base = Instant::now();
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        let mut process = |name: &str, interval: Option<(u64, u64)>| {
+            backend.process_node(
+                action_key(name),
+                None,
+                duration(interval.map_or(0, |(start, end)| end - start)),
+                interval.map(|(start, end)| {
+                    (
+                        base + Duration::from_secs(start),
+                        base + Duration::from_secs(end),
+                    )
+                }),
+                std::iter::empty(),
+                SmallVec::new(),
+            );
+        };
+        process("a", Some((0, 10)));
+        process("b", Some((1, 3)));
+        process("c", Some((2, 5)));
+        // Starts when `b` finishes, so it takes its place rather than adding to the peak.
+        process("d", Some((3, 4)));
+        // Nodes without timestamps (i.e. that aren't actions) are not counted.
+        process("e", None);
+
+        assert_eq!(3, backend.finish().unwrap().peak_concurrency());
+    }
+
+    #[test]
+    fn build_info_accessors() {
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        let mut process = |name: &str, secs, deps: &[&str]| {
+            backend.process_node(
+                action_key(name),
+                None,
+                duration(secs),
+                None,
+                deps.iter().map(|dep| action_key(dep)),
+                SmallVec::new(),
+            );
+        };
+        // `c` depends on both `a` and `b`.
+        process("a", 1, &[]);
+        process("b", 2, &[]);
+        process("c", 3, &["a", "b"]);
+
+        let info = backend.finish().unwrap();
+        assert_eq!(3, info.num_nodes());
+        assert_eq!(2, info.num_edges());
+        assert_eq!(
+            vec![
+                (action_key("b").to_string(), Duration::from_secs(2)),
+                (action_key("c").to_string(), Duration::from_secs(3)),
+            ],
+            info.critical_path()
+                .iter()
+                .map(|entry| (entry.name(), entry.duration()))
+                .collect::<Vec<_>>()
+        );
+        assert!(
+            info.critical_path()
+                .iter()
+                .all(|entry| entry.potential_improvement().is_none())
+        );
+    }
+
+    #[test]
+    fn critical_path_json() {
+        let to_json = |info: BuildInfo| {
+            let mut out = Vec::new();
+            info.to_json_writer(&mut out).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&out).unwrap()
+        };
+
+        assert_eq!(
+            serde_json::json!([]),
+            to_json(
+                DefaultBackend::new(DefaultBackendOptions::default())
+                    .finish()
+                    .unwrap()
+            )
+        );
+
+        let mut backend = DefaultBackend::new(DefaultBackendOptions::default());
+        backend.process_node(
+            action_key("a"),
+            None,
+            duration(2),
+            None,
+            std::iter::empty(),
+            SmallVec::new(),
+        );
+        assert_eq!(
+            serde_json::json!([{
+                "name": null,
+                "key": action_key("a").to_string(),
+                "duration_us": 2_000_000,
+                "category": null,
+                "identifier": null,
+            }]),
+            to_json(backend.finish().unwrap())
+        );
+    }
+
     #[test]
     fn cycle_path() {
         let mut predecessors = HashMap::new();
@@ -9,6 +9,7 @@
 
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context as _;
 use buck2_build_api::actions::RegisteredAction;
@@ -21,10 +22,13 @@ use buck2_critical_path::OptionalVertexId;
 use buck2_critical_path::PushError;
 use buck2_events::span::SpanId;
 use dupe::Dupe;
+use itertools::Itertools;
 use smallvec::SmallVec;
 
 use crate::backend::backend::BuildListenerBackend;
+use crate::peak_concurrency;
 use crate::BuildInfo;
+use crate::CriticalPathEntry;
 use crate::NodeData;
 use crate::NodeKey;
 
@@ -33,6 +37,8 @@ use crate::NodeKey;
 pub(crate) struct LongestPathGraphBackend {
     builder: anyhow::Result<GraphBuilder<NodeKey, NodeData>>,
     top_level_analysis: Vec<VisibilityEdge>,
+    /// The start and end of every action, to compute peak concurrency.
+    action_intervals: Vec<(Instant, Instant)>,
 }
 
 /// Represents nodes that block us "seeing" other parts of the graph until they finish evaluating.
@@ -46,6 +52,7 @@ impl LongestPathGraphBackend {
         Self {
             builder: Ok(GraphBuilder::new()),
             top_level_analysis: Vec::new(),
+            action_intervals: Vec::new(),
         }
     }
 }
@@ -56,9 +63,14 @@ impl BuildListenerBackend for LongestPathGraphBackend {
         key: NodeKey,
         action: Option<Arc<RegisteredAction>>,
         duration: NodeDuration,
+        interval: Option<(Instant, Instant)>,
         dep_keys: impl IntoIterator<Item = NodeKey>,
         span_ids: SmallVec<[SpanId; 1]>,
     ) {
+        if let Some(interval) = interval {
+            self.action_intervals.push(interval);
+        }
+
         let builder = match self.builder.as_mut() {
             Ok(b) => b,
             Err(..) => return,
@@ -100,9 +112,30 @@ impl BuildListenerBackend for LongestPathGraphBackend {
     }
 
     fn finish(self) -> anyhow::Result<BuildInfo> {
+        let mut max_fan_out = None;
+
         let (graph, keys, mut data) = {
             let (graph, keys, data) = self.builder?.finish();
 
+            // Compute this before we add the first_analysis edges, since those aren't actual
+            // dependencies.
+            let mut in_degrees = graph.allocate_vertex_data(0u64);
+            for i in graph.iter_vertices() {
+                for j in graph.iter_edges(i) {
+                    in_degrees[j] += 1;
+                }
+            }
+            // `max_by_key` returns the last maximum, and we prefer the first node pushed.
+            if let Some((i, in_degree)) = graph
+                .iter_vertices()
+                .map(|i| (i, in_degrees[i]))
+                .filter(|(_i, in_degree)| *in_degree > 0)
+                .rev()
+                .max_by_key(|(_i, in_degree)| *in_degree)
+            {
+                max_fan_out = Some((keys[i].dupe(), in_degree));
+            }
+
             let mut first_analysis = graph.allocate_vertex_data(OptionalVertexId::none());
             let mut n = 0;
 
@@ -172,8 +205,24 @@ impl BuildListenerBackend for LongestPathGraphBackend {
         })?;
 
         let (critical_path, critical_path_cost, replacement_durations) =
-            compute_critical_path_potentials(&graph, &durations)
-                .context("Error computing critical path potentials")?;
+            match compute_critical_path_potentials(&graph, &durations) {
+                Ok(res) => res,
+                Err(e) => {
+                    // The most likely cause is a cycle, which is a bug, so help find it.
+                    let e = match graph.find_cycle() {
+                        Some(cycle) => e.context(format!(
+                            "action dependency cycle detected: {}",
+                            cycle
+                                .iter()
+                                .chain(cycle.first())
+                                .map(|v| keys[*v].to_string())
+                                .join(" -> ")
+                        )),
+                        None => e,
+                    };
+                    return Err(e.context("Error computing critical path potentials"));
+                }
+            };
 
         drop(durations);
 
@@ -196,7 +245,11 @@ impl BuildListenerBackend for LongestPathGraphBackend {
 
                 let potential = critical_path_cost.runtime - replacement_durations[cp_idx].runtime;
 
-                (key, data, Some(Duration::from_micros(potential)))
+                CriticalPathEntry {
+                    key,
+                    data,
+                    potential_improvement: Some(Duration::from_micros(potential)),
+                }
             })
             .collect();
 
@@ -204,6 +257,12 @@ impl BuildListenerBackend for LongestPathGraphBackend {
             critical_path,
             num_nodes: graph.vertices_count() as _,
             num_edges: graph.edges_count() as _,
+            // Nodes are weighed by `NodeDuration::critical_path_duration`.
+            uses_total_duration: true,
+            max_fan_out,
+            action_timeline: Vec::new(),
+            finish_critical_path: Vec::new(),
+            peak_concurrency: peak_concurrency(&self.action_intervals),
         })
     }
 
@@ -211,3 +270,102 @@ impl BuildListenerBackend for LongestPathGraphBackend {
         CriticalPathBackendName::LongestPathGraph
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_analysis::analysis::calculation::AnalysisKey;
+    use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+    use buck2_artifact::artifact::build_artifact::BuildArtifact;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_build_api::actions::calculation::BuildKey;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+
+    use super::*;
+
+    fn target(name: &str) -> ConfiguredTargetLabel {
+        ConfiguredTargetLabel::testing_parse(
+            &format!("cell//pkg:{}", name),
+            ConfigurationData::testing_new(),
+        )
+    }
+
+    fn action_key(name: &str) -> NodeKey {
+        NodeKey::BuildKey(BuildKey(
+            BuildArtifact::testing_new(
+                target(name),
+                ForwardRelativePathBuf::unchecked_new("out".to_owned()),
+                DeferredId::testing_new(0),
+            )
+            .key()
+            .dupe(),
+        ))
+    }
+
+    fn duration(secs: u64) -> NodeDuration {
+        NodeDuration {
+            user: Duration::from_secs(secs),
+            total: Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn max_fan_out() {
+        let mut backend = LongestPathGraphBackend::new();
+        let mut process = |name: &str, deps: &[&str]| {
+            backend.process_node(
+                action_key(name),
+                None,
+                duration(1),
+                None,
+                deps.iter().map(|dep| action_key(dep)),
+                SmallVec::new(),
+            );
+        };
+        // A diamond: `b` and `c` both depend on `a`, and `d` depends on both of them.
+        process("a", &[]);
+        process("b", &["a"]);
+        process("c", &["a"]);
+        process("d", &["b", "c"]);
+
+        let info = match backend.finish() {
+            Ok(info) => info,
+            Err(e) => panic!("{:#}", e),
+        };
+        assert_eq!(Some((action_key("a").to_string(), 2)), info.max_fan_out());
+    }
+
+    #[test]
+    fn reports_cycle() {
+        let mut backend = LongestPathGraphBackend::new();
+        let analysis = NodeKey::AnalysisKey(AnalysisKey(target("top")));
+
+        // The analysis depends on the action, and also makes it visible, which is a cycle.
+        backend.process_node(
+            action_key("action"),
+            None,
+            duration(1),
+            None,
+            [],
+            SmallVec::new(),
+        );
+        backend.process_node(
+            analysis.dupe(),
+            None,
+            duration(1),
+            None,
+            [action_key("action")],
+            SmallVec::new(),
+        );
+        backend.process_top_level_target(analysis.dupe(), [action_key("action")]);
+
+        let err = match backend.finish() {
+            Ok(..) => panic!("Expected a cycle error"),
+            Err(e) => format!("{:#}", e),
+        };
+        assert!(err.contains("action dependency cycle detected"), "{}", err);
+        assert!(err.contains(&analysis.to_string()), "{}", err);
+        assert!(err.contains(&action_key("action").to_string()), "{}", err);
+    }
+}
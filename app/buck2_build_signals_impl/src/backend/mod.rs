@@ -12,3 +12,4 @@
 pub mod backend;
 pub mod default;
 pub mod longest_path_graph;
+pub mod timeline;
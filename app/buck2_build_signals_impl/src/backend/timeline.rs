@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use buck2_build_api::actions::RegisteredAction;
+use buck2_build_signals::CriticalPathBackendName;
+use buck2_build_signals::NodeDuration;
+use buck2_events::span::SpanId;
+use smallvec::SmallVec;
+
+use crate::backend::backend::BuildListenerBackend;
+use crate::backend::default::DefaultBackend;
+use crate::backend::default::DefaultBackendOptions;
+use crate::BuildInfo;
+use crate::NodeKey;
+
+/// Computes the critical path like `DefaultBackend`, and also reports when every action (not just
+/// those on the critical path) started and finished, e.g. to render the build as a flamegraph.
+pub(crate) struct TimelineBackend {
+    inner: DefaultBackend,
+}
+
+impl TimelineBackend {
+    pub(crate) fn new(options: DefaultBackendOptions) -> Self {
+        Self {
+            inner: DefaultBackend::new(options),
+        }
+    }
+}
+
+impl BuildListenerBackend for TimelineBackend {
+    fn process_node(
+        &mut self,
+        key: NodeKey,
+        value: Option<Arc<RegisteredAction>>,
+        duration: NodeDuration,
+        interval: Option<(Instant, Instant)>,
+        dep_keys: impl IntoIterator<Item = NodeKey>,
+        span_ids: SmallVec<[SpanId; 1]>,
+    ) {
+        self.inner
+            .process_node(key, value, duration, interval, dep_keys, span_ids)
+    }
+
+    fn process_top_level_target(
+        &mut self,
+        analysis: NodeKey,
+        artifacts: impl IntoIterator<Item = NodeKey>,
+    ) {
+        self.inner.process_top_level_target(analysis, artifacts)
+    }
+
+    fn current_critical_path_total(&self) -> Option<Duration> {
+        self.inner.current_critical_path_total()
+    }
+
+    fn finish(self) -> anyhow::Result<BuildInfo> {
+        let action_timeline = self.inner.action_timeline();
+        Ok(BuildInfo {
+            action_timeline,
+            ..self.inner.finish()?
+        })
+    }
+
+    fn name() -> CriticalPathBackendName {
+        CriticalPathBackendName::Timeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use buck2_artifact::actions::key::ActionKey;
+    use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+    use buck2_artifact::artifact::build_artifact::BuildArtifact;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_build_api::actions::calculation::BuildKey;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use dupe::Dupe;
+
+    use super::*;
+
+    fn action_key(name: &str) -> ActionKey {
+        BuildArtifact::testing_new(
+            ConfiguredTargetLabel::testing_parse(
+                &format!("cell//pkg:{}", name),
+                ConfigurationData::testing_new(),
+            ),
+            ForwardRelativePathBuf::unchecked_new("out".to_owned()),
+            DeferredId::testing_new(0),
+        )
+        .key()
+        .dupe()
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn action_timeline() -> anyhow::Result<()> {
+        let mut backend = TimelineBackend::new(DefaultBackendOptions::default());
+        let mut process = |name: &str, duration: u64, deps: &[&str]| {
+            backend.process_node(
+                NodeKey::BuildKey(BuildKey(action_key(name))),
+                None,
+                NodeDuration {
+                    user: secs(duration),
+                    total: secs(duration),
+                },
+                None,
+                deps.iter()
+                    .map(|dep| NodeKey::BuildKey(BuildKey(action_key(dep)))),
+                SmallVec::new(),
+            );
+        };
+        // `a -> b -> c` is a chain, while `d` runs alongside it.
+        process("a", 1, &[]);
+        process("b", 2, &["a"]);
+        process("c", 3, &["b"]);
+        process("d", 5, &[]);
+
+        let info = backend.finish()?;
+        // Every action starts once the actions it depends on took their time.
+        assert_eq!(
+            vec![
+                (action_key("a"), (secs(0), secs(1))),
+                (action_key("d"), (secs(0), secs(5))),
+                (action_key("b"), (secs(1), secs(3))),
+                (action_key("c"), (secs(3), secs(6))),
+            ],
+            info.action_timeline()
+        );
+        // The critical path is still reported.
+        assert_eq!(3, info.critical_path().len());
+
+        Ok(())
+    }
+}
@@ -8,10 +8,13 @@
  */
 
 #![feature(error_generic_member_access)]
+#![feature(used_with_arg)]
 
 use std::any::Any;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt;
+use std::fmt::Write as _;
 use std::hash::Hash;
 use std::sync::Arc;
 use std::time::Duration;
@@ -21,6 +24,7 @@ use anyhow::Context as _;
 use async_trait::async_trait;
 use buck2_analysis::analysis::calculation::AnalysisKey;
 use buck2_analysis::analysis::calculation::AnalysisKeyActivationData;
+use buck2_artifact::actions::key::ActionKey;
 use buck2_artifact::artifact::build_artifact::BuildArtifact;
 use buck2_build_api::actions::calculation::BuildKey;
 use buck2_build_api::actions::calculation::BuildKeyActivationData;
@@ -42,9 +46,11 @@ use buck2_build_signals::NodeDuration;
 use buck2_common::package_listing::dice::PackageListingKey;
 use buck2_common::package_listing::dice::PackageListingKeyActivationData;
 use buck2_configured::nodes::calculation::ConfiguredTargetNodeKey;
+use buck2_core::buck2_env;
 use buck2_core::package::PackageLabel;
 use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
 use buck2_data::ToProtoMessage;
+use buck2_events::dispatch::console_warning;
 use buck2_events::dispatch::instant_event;
 use buck2_events::dispatch::with_dispatcher_async;
 use buck2_events::dispatch::EventDispatcher;
@@ -63,12 +69,15 @@ use static_assertions::assert_eq_size;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_stream::StreamExt;
 
 use crate::backend::backend::BuildListenerBackend;
 use crate::backend::default::DefaultBackend;
+use crate::backend::default::DefaultBackendOptions;
 use crate::backend::longest_path_graph::LongestPathGraphBackend;
+use crate::backend::timeline::TimelineBackend;
 
 mod backend;
 
@@ -193,6 +202,9 @@ pub struct Evaluation {
     /// NodeKey::BuildKey).
     action: Option<Arc<RegisteredAction>>,
 
+    /// When the action started and finished executing (also only present for NodeKey::BuildKey).
+    action_interval: Option<(Instant, Instant)>,
+
     /// The Load result that corresponds to this Evaluation (this will only be pesent for
     /// InterpreterResultsKey).
     load_result: Option<Arc<EvaluationResult>>,
@@ -244,6 +256,7 @@ impl ActivationTracker for BuildSignalSender {
         let mut signal = Evaluation {
             key,
             action: None,
+            action_interval: None,
             duration: NodeDuration::zero(),
             dep_keys: deps.into_iter().filter_map(NodeKey::from_any).collect(),
             spans: Default::default(),
@@ -267,9 +280,12 @@ impl ActivationTracker for BuildSignalSender {
                 action,
                 duration,
                 spans,
+                start,
+                end,
             }) = downcast_and_take(&mut activation_data)
             {
                 signal.action = Some(action);
+                signal.action_interval = Some((start, end));
                 signal.duration = duration;
                 signal.spans = spans;
             } else if let Some(AnalysisKeyActivationData { duration, spans }) =
@@ -321,12 +337,24 @@ impl DeferredBuildSignals for DeferredBuildSignalsImpl {
         ctx: BuildSignalsContext,
     ) -> Box<dyn FinishBuildSignals> {
         let handle = match backend {
-            CriticalPathBackendName::LongestPathGraph => {
-                start_backend(events, self.receiver, LongestPathGraphBackend::new(), ctx)
-            }
-            CriticalPathBackendName::Default => {
-                start_backend(events, self.receiver, DefaultBackend::new(), ctx)
-            }
+            CriticalPathBackendName::LongestPathGraph => start_backend(
+                events,
+                self.receiver,
+                Ok(LongestPathGraphBackend::new()),
+                ctx,
+            ),
+            CriticalPathBackendName::Default => start_backend(
+                events,
+                self.receiver,
+                DefaultBackendOptions::from_env().map(DefaultBackend::new),
+                ctx,
+            ),
+            CriticalPathBackendName::Timeline => start_backend(
+                events,
+                self.receiver,
+                DefaultBackendOptions::from_env().map(TimelineBackend::new),
+                ctx,
+            ),
         };
 
         Box::new(FinishBuildSignalsImpl {
@@ -352,24 +380,32 @@ impl FinishBuildSignals for FinishBuildSignalsImpl {
     }
 }
 
+/// `backend` is an error if it couldn't be configured, which is then reported when the build
+/// finishes, like errors computing the critical path.
 fn start_backend(
     events: EventDispatcher,
     receiver: UnboundedReceiver<BuildSignal>,
-    backend: impl BuildListenerBackend + Send + 'static,
+    backend: anyhow::Result<impl BuildListenerBackend + Send + 'static>,
     ctx: BuildSignalsContext,
 ) -> JoinHandle<anyhow::Result<()>> {
-    let listener = BuildSignalReceiver::new(receiver, backend);
     tokio::spawn(with_dispatcher_async(events.dupe(), async move {
-        listener.run_and_log(ctx).await
+        BuildSignalReceiver::new(receiver, backend?)
+            .run_and_log(ctx)
+            .await
     }))
 }
 
+/// How often to report the critical path so far while the build is running.
+const CRITICAL_PATH_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
 struct BuildSignalReceiver<T> {
     receiver: UnboundedReceiverStream<BuildSignal>,
     // Maps a PackageLabel to the first PackageLabel that had an edge to it. When that PackageLabel
     // shows up, we'll give it a dependency on said first PackageLabel that had an edge to it, which
     // is how we discovered its existence.
     first_edge_to_load: HashMap<PackageLabel, PackageLabel>,
+    /// The total duration of every action we were told about, per category.
+    category_durations: BTreeMap<String, Duration>,
     backend: T,
 }
 
@@ -382,11 +418,26 @@ where
             receiver: UnboundedReceiverStream::new(receiver),
             backend,
             first_edge_to_load: HashMap::new(),
+            category_durations: BTreeMap::new(),
         }
     }
 
     pub async fn run_and_log(mut self, ctx: BuildSignalsContext) -> anyhow::Result<()> {
-        while let Some(event) = self.receiver.next().await {
+        let mut progress = tokio::time::interval(CRITICAL_PATH_PROGRESS_INTERVAL);
+        progress.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut reported_total = Duration::ZERO;
+
+        loop {
+            let event = tokio::select! {
+                event = self.receiver.next() => event,
+                _ = progress.tick() => {
+                    self.report_progress(&mut reported_total)?;
+                    continue;
+                }
+            };
+            let Some(event) = event else {
+                break;
+            };
             match event {
                 BuildSignal::Evaluation(eval) => self.process_evaluation(eval),
                 BuildSignal::TopLevelTarget(top_level) => {
@@ -399,16 +450,57 @@ where
             }
         }
 
+        let json_path = buck2_env!("BUCK2_CRITICAL_PATH_JSON")?;
+
         let now = Instant::now();
 
+        let build_info = self.backend.finish()?;
+        let potentials = build_info
+            .potentials()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, potential)| {
+                anyhow::Ok(buck2_data::CriticalPathPotential {
+                    node: key.to_string(),
+                    potential: Some(potential.try_into()?),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         let BuildInfo {
             critical_path,
             num_nodes,
             num_edges,
-        } = self.backend.finish()?;
+            uses_total_duration,
+            max_fan_out,
+            action_timeline,
+            finish_critical_path,
+            peak_concurrency,
+        } = &build_info;
+        let category_durations = self
+            .category_durations
+            .iter()
+            .map(|(category, duration)| {
+                anyhow::Ok(buck2_data::CategoryDuration {
+                    category: category.clone(),
+                    duration: Some((*duration).try_into()?),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let action_timeline = action_timeline
+            .iter()
+            .map(|(action, (start, end))| {
+                anyhow::Ok(buck2_data::ActionTiming {
+                    action: Some(action.as_proto()),
+                    start: Some((*start).try_into()?),
+                    end: Some((*end).try_into()?),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         let compute_elapsed = now.elapsed();
 
+        let mut categories = CategoryCache::default();
+
         let meta_entry_data = NodeData {
             action: None,
             duration: NodeDuration {
@@ -424,57 +516,85 @@ where
             &Some(compute_elapsed),
         );
 
+        let include_transitive_set_projections = ctx.include_transitive_set_projections;
         let critical_path2 = critical_path
             .iter()
-            .filter_map(|(key, data, potential_improvement)| {
-                let entry: buck2_data::critical_path_entry2::Entry = match key {
-                    NodeKey::BuildKey(key) => {
-                        let owner = key.0.owner().to_proto().into();
-
-                        // If we have a NodeKey that's an ActionKey we'd expect to have an `action`
-                        // in our data (unless we didn't actually run it because of e.g. early
-                        // cutoff, in which case omitting it is what we want).
-                        let action = data.action.as_ref()?;
-
-                        buck2_data::critical_path_entry2::ActionExecution {
-                            owner: Some(owner),
-                            name: Some(buck2_data::ActionName {
-                                category: action.category().as_str().to_owned(),
-                                identifier: action.identifier().unwrap_or("").to_owned(),
-                            }),
+            .filter_map(
+                |CriticalPathEntry {
+                     key,
+                     data,
+                     potential_improvement,
+                 }| {
+                    let entry: buck2_data::critical_path_entry2::Entry = match key {
+                        NodeKey::BuildKey(key) => {
+                            let owner = key.0.owner().to_proto().into();
+
+                            // If we have a NodeKey that's an ActionKey we'd expect to have an `action`
+                            // in our data (unless we didn't actually run it because of e.g. early
+                            // cutoff, in which case omitting it is what we want).
+                            let action = data.action.as_ref()?;
+
+                            buck2_data::critical_path_entry2::ActionExecution {
+                                owner: Some(owner),
+                                name: Some(buck2_data::ActionName {
+                                    category: categories.get(action.category().as_str()),
+                                    identifier: action.identifier().unwrap_or("").to_owned(),
+                                }),
+                            }
+                            .into()
                         }
-                        .into()
-                    }
-                    NodeKey::AnalysisKey(key) => buck2_data::critical_path_entry2::Analysis {
-                        target: Some(key.0.as_proto().into()),
-                    }
-                    .into(),
-                    NodeKey::Materialization(key) => {
-                        let owner = key.key().owner().to_proto().into();
-
-                        buck2_data::critical_path_entry2::Materialization {
-                            owner: Some(owner),
-                            path: key.get_path().path().to_string(),
+                        NodeKey::AnalysisKey(key) => buck2_data::critical_path_entry2::Analysis {
+                            target: Some(key.0.as_proto().into()),
                         }
-                        .into()
-                    }
-                    NodeKey::InterpreterResultsKey(key) => buck2_data::critical_path_entry2::Load {
-                        package: key.0.to_string(),
-                    }
-                    .into(),
-                    NodeKey::PackageListingKey(key) => buck2_data::critical_path_entry2::Listing {
-                        package: key.0.to_string(),
-                    }
-                    .into(),
-                    NodeKey::EnsureProjectedArtifactKey(..) => return None,
-                    NodeKey::EnsureTransitiveSetProjectionKey(..) => return None,
-                    NodeKey::DeferredCompute(..) => return None,
-                    NodeKey::DeferredResolve(..) => return None,
-                    NodeKey::ConfiguredTargetNodeKey(..) => return None,
-                };
-
-                Some((entry, data, potential_improvement))
-            })
+                        .into(),
+                        NodeKey::Materialization(key) => {
+                            let owner = key.key().owner().to_proto().into();
+
+                            buck2_data::critical_path_entry2::Materialization {
+                                owner: Some(owner),
+                                path: key.get_path().path().to_string(),
+                            }
+                            .into()
+                        }
+                        NodeKey::InterpreterResultsKey(key) => {
+                            buck2_data::critical_path_entry2::Load {
+                                package: key.0.to_string(),
+                            }
+                            .into()
+                        }
+                        NodeKey::PackageListingKey(key) => {
+                            buck2_data::critical_path_entry2::Listing {
+                                package: key.0.to_string(),
+                            }
+                            .into()
+                        }
+                        NodeKey::EnsureProjectedArtifactKey(..) => return None,
+                        NodeKey::EnsureTransitiveSetProjectionKey(key) => {
+                            if !include_transitive_set_projections {
+                                return None;
+                            }
+
+                            // There is no action behind a projection, so we name it after the
+                            // projection itself.
+                            let owner = key.0.key.deferred_key().owner().to_proto().into();
+
+                            buck2_data::critical_path_entry2::ActionExecution {
+                                owner: Some(owner),
+                                name: Some(buck2_data::ActionName {
+                                    category: "transitive_set_projection".to_owned(),
+                                    identifier: key.0.to_string(),
+                                }),
+                            }
+                            .into()
+                        }
+                        NodeKey::DeferredCompute(..) => return None,
+                        NodeKey::DeferredResolve(..) => return None,
+                        NodeKey::ConfiguredTargetNodeKey(..) => return None,
+                    };
+
+                    Some((entry, data, potential_improvement))
+                },
+            )
             .chain(std::iter::once(meta_entry))
             .map(|(entry, data, potential_improvement)| {
                 anyhow::Ok(buck2_data::CriticalPathEntry2 {
@@ -500,11 +620,59 @@ where
             metadata: ctx.metadata,
             command_name: Some(ctx.command_name),
             isolation_dir: Some(ctx.isolation_prefix.into_inner().into()),
-            num_nodes,
-            num_edges,
-            uses_total_duration: true,
+            num_nodes: *num_nodes,
+            num_edges: *num_edges,
+            uses_total_duration: *uses_total_duration,
             backend_name: Some(T::name().to_string()),
+            peak_concurrency: Some(*peak_concurrency),
+            invocation_label: ctx.invocation_label,
+            category_durations,
+            max_fan_out: max_fan_out
+                .as_ref()
+                .map(|(key, in_degree)| buck2_data::FanOut {
+                    node: key.to_string(),
+                    in_degree: *in_degree,
+                }),
+            action_timeline,
+            finish_critical_path: finish_critical_path
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+            potentials,
         });
+
+        // This is written once the critical path was computed and reported, so that it neither
+        // counts towards the time computing it took nor delays the event.
+        if let Some(path) = json_path {
+            let written = tokio::task::spawn_blocking(move || {
+                let file = std::fs::File::create(path)?;
+                build_info.to_json_writer(std::io::BufWriter::new(file))
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|written| written);
+            // This is a debugging aid, so a failure to write it is not an error.
+            if let Err(e) = written {
+                console_warning(format!(
+                    "Failed to write the critical path to `{}`: {:#}",
+                    path, e
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Report the total duration of the critical path so far, if it grew since we last did.
+    fn report_progress(&self, reported_total: &mut Duration) -> anyhow::Result<()> {
+        if let Some(total) = self.backend.current_critical_path_total() {
+            if total > *reported_total {
+                instant_event(buck2_data::CriticalPathProgress {
+                    total_duration: Some(total.try_into()?),
+                });
+                *reported_total = total;
+            }
+        }
         Ok(())
     }
 
@@ -513,10 +681,19 @@ where
     fn process_evaluation(&mut self, mut evaluation: Evaluation) {
         self.enrich_load(&mut evaluation);
 
+        if let Some(action) = &evaluation.action {
+            add_category_duration(
+                &mut self.category_durations,
+                action.category().as_str(),
+                evaluation.duration.critical_path_duration(),
+            );
+        }
+
         self.backend.process_node(
             evaluation.key,
             evaluation.action,
             evaluation.duration,
+            evaluation.action_interval,
             evaluation.dep_keys.into_iter(),
             evaluation.spans,
         );
@@ -601,6 +778,7 @@ where
             NodeKey::Materialization(materialization.artifact),
             None,
             materialization.duration,
+            None,
             std::iter::once(dep),
             materialization.span_id.into_iter().collect(),
         );
@@ -609,11 +787,203 @@ where
     }
 }
 
+/// A node on the critical path.
+pub struct CriticalPathEntry {
+    key: NodeKey,
+    data: NodeData,
+    /// How much shorter the critical path would be if this node took no time at all, if the
+    /// backend computes it.
+    potential_improvement: Option<Duration>,
+}
+
+impl CriticalPathEntry {
+    /// A human-readable description of the node, e.g. the key of an action.
+    pub fn name(&self) -> String {
+        self.key.to_string()
+    }
+
+    /// How much this node contributes to the duration of the critical path.
+    pub fn duration(&self) -> Duration {
+        self.data.duration.critical_path_duration()
+    }
+
+    pub fn potential_improvement(&self) -> Option<Duration> {
+        self.potential_improvement
+    }
+}
+
 pub struct BuildInfo {
-    // Node, its data, and its potential for improvement
-    critical_path: Vec<(NodeKey, NodeData, Option<Duration>)>,
+    critical_path: Vec<CriticalPathEntry>,
     num_nodes: u64,
     num_edges: u64,
+    /// Whether the backend weighs nodes by their total duration (as opposed to their user
+    /// duration).
+    uses_total_duration: bool,
+    /// The node with the most dependents, and how many it has.
+    max_fan_out: Option<(NodeKey, u64)>,
+    /// When each action started and ended, relative to the start of the build. Only computed by
+    /// `TimelineBackend`, empty otherwise.
+    action_timeline: Vec<(ActionKey, (Duration, Duration))>,
+    /// The chain of nodes which gated the end of the build, in the order they finished. Only
+    /// computed by the default backend (and the timeline one) if `BUCK2_FINISH_CRITICAL_PATH` is
+    /// set, empty otherwise.
+    finish_critical_path: Vec<NodeKey>,
+    /// The most actions that were running at the same time.
+    peak_concurrency: u64,
+}
+
+impl BuildInfo {
+    pub fn critical_path(&self) -> &[CriticalPathEntry] {
+        &self.critical_path
+    }
+
+    /// How many nodes the backend saw in the build graph.
+    pub fn num_nodes(&self) -> u64 {
+        self.num_nodes
+    }
+
+    /// How many edges the backend saw in the build graph.
+    pub fn num_edges(&self) -> u64 {
+        self.num_edges
+    }
+
+    pub fn uses_total_duration(&self) -> bool {
+        self.uses_total_duration
+    }
+
+    /// The node that the most other nodes depend on (as described by `CriticalPathEntry::name`),
+    /// and how many nodes depend on it. This helps find over-shared dependencies.
+    pub fn max_fan_out(&self) -> Option<(String, u64)> {
+        self.max_fan_out
+            .as_ref()
+            .map(|(key, in_degree)| (key.to_string(), *in_degree))
+    }
+
+    /// The most actions that were running at the same time, see `peak_concurrency`.
+    pub fn peak_concurrency(&self) -> u64 {
+        self.peak_concurrency
+    }
+
+    /// The start and end of each action, relative to the start of the build, sorted by start.
+    /// Only the timeline backend reports this.
+    pub fn action_timeline(&self) -> &[(ActionKey, (Duration, Duration))] {
+        &self.action_timeline
+    }
+
+    /// Render the critical path as "folded stacks", as consumed by `flamegraph.pl`. Each node on
+    /// the critical path is a frame whose stack is the chain of nodes leading up to it, and whose
+    /// sample count is its duration in microseconds.
+    pub fn to_folded_stacks(&self) -> String {
+        folded_stacks(
+            self.critical_path
+                .iter()
+                .map(|entry| (&entry.key, entry.duration())),
+        )
+    }
+
+    /// Write the critical path as a JSON array, with one object per node. Durations are integer
+    /// microseconds; the action fields are `null` for nodes that aren't actions.
+    pub fn to_json_writer<W: std::io::Write>(&self, w: W) -> anyhow::Result<()> {
+        let entries = self
+            .critical_path
+            .iter()
+            .map(|CriticalPathEntry { key, data, .. }| {
+                let category = data.action.as_ref().map(|a| a.category().as_str());
+                let identifier = data.action.as_ref().and_then(|a| a.identifier());
+                let name = category.map(|category| match identifier {
+                    Some(identifier) if !identifier.is_empty() => {
+                        format!("{} {}", category, identifier)
+                    }
+                    _ => category.to_owned(),
+                });
+                let duration_us: u64 = data
+                    .duration
+                    .critical_path_duration()
+                    .as_micros()
+                    .try_into()
+                    .context("Duration `as_micros()` exceeds u64")?;
+                anyhow::Ok(serde_json::json!({
+                    "name": name,
+                    "key": key.to_string(),
+                    "duration_us": duration_us,
+                    "category": category,
+                    "identifier": identifier,
+                }))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        serde_json::to_writer(w, &entries).context("Error writing critical path as JSON")?;
+        Ok(())
+    }
+
+    /// For each node on the critical path, how much shorter the critical path would be if that
+    /// node took no time at all. `None` if the backend doesn't compute potentials (only the
+    /// longest-path graph backend does, and the default one if `BUCK2_CRITICAL_PATH_POTENTIALS`
+    /// is set).
+    pub(crate) fn potentials(&self) -> Option<Vec<(NodeKey, Duration)>> {
+        if self.critical_path.is_empty() {
+            return None;
+        }
+        self.critical_path
+            .iter()
+            .map(|entry| Some((entry.key.dupe(), entry.potential_improvement?)))
+            .collect()
+    }
+}
+
+pub(crate) fn folded_stacks<K: fmt::Display>(
+    path: impl IntoIterator<Item = (K, Duration)>,
+) -> String {
+    let mut stack = String::new();
+    let mut out = String::new();
+
+    for (key, duration) in path {
+        if !stack.is_empty() {
+            stack.push(';');
+        }
+        // Frames are separated by `;` in this format, so they can't contain one.
+        stack.push_str(&key.to_string().replace(';', ":"));
+        writeln!(out, "{} {}", stack, duration.as_micros()).unwrap();
+    }
+
+    out
+}
+
+/// The maximum number of `(start, end)` intervals that overlap at any instant. Intervals are
+/// half-open, so one that ends exactly when another starts does not overlap with it.
+pub(crate) fn peak_concurrency(intervals: &[(Instant, Instant)]) -> u64 {
+    let mut edges = intervals
+        .iter()
+        .flat_map(|(start, end)| [(*start, 1i64), (*end, -1i64)])
+        .collect::<Vec<_>>();
+    // Ends sort before starts at the same instant.
+    edges.sort();
+
+    let mut current = 0;
+    let mut peak = 0;
+    for (_, delta) in edges {
+        current += delta;
+        peak = std::cmp::max(peak, current);
+    }
+    peak as u64
+}
+
+/// Add `duration` to the total of `category`. Nodes that took no time (e.g. redirections or
+/// transitive set projections) are ignored, so that they don't show up as categories.
+fn add_category_duration(
+    category_durations: &mut BTreeMap<String, Duration>,
+    category: &str,
+    duration: Duration,
+) {
+    if duration.is_zero() {
+        return;
+    }
+
+    match category_durations.get_mut(category) {
+        Some(total) => *total += duration,
+        None => {
+            category_durations.insert(category.to_owned(), duration);
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -625,6 +995,22 @@ struct NodeData {
 
 assert_eq_size!(NodeData, [usize; 8]);
 
+/// Owned copies of the action categories on the critical path. Builds only use a handful of
+/// distinct categories, so each one is converted once and then cloned for every entry.
+#[derive(Default)]
+struct CategoryCache<'a> {
+    categories: HashMap<&'a str, String>,
+}
+
+impl<'a> CategoryCache<'a> {
+    fn get(&mut self, category: &'a str) -> String {
+        self.categories
+            .entry(category)
+            .or_insert_with(|| category.to_owned())
+            .clone()
+    }
+}
+
 fn create_build_signals() -> (BuildSignalsInstaller, Box<dyn DeferredBuildSignals>) {
     let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
 
@@ -642,3 +1028,531 @@ fn create_build_signals() -> (BuildSignalsInstaller, Box<dyn DeferredBuildSignal
 pub fn init_late_bindings() {
     CREATE_BUILD_SIGNALS.init(create_build_signals)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use buck2_analysis::analysis::calculation::AnalysisKey;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_artifact::deferred::key::DeferredKey;
+    use buck2_build_api::actions::RegisteredAction;
+    use buck2_build_api::artifact_groups::calculation::EnsureTransitiveSetProjectionKey;
+    use buck2_build_api::artifact_groups::deferred::TransitiveSetKey;
+    use buck2_build_api::artifact_groups::TransitiveSetProjectionKey;
+    use buck2_build_signals::BuildSignalsContext;
+    use buck2_build_signals::CriticalPathBackendName;
+    use buck2_build_signals::NodeDuration;
+    use buck2_core::base_deferred_key::BaseDeferredKey;
+    use buck2_core::category::Category;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::paths::file_name::FileNameBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use buck2_events::create_source_sink_pair;
+    use buck2_events::dispatch::with_dispatcher_async;
+    use buck2_events::dispatch::EventDispatcher;
+    use buck2_events::source::ChannelEventSource;
+    use buck2_events::span::SpanId;
+    use buck2_events::Event;
+    use buck2_wrapper_common::invocation_id::TraceId;
+    use dupe::Dupe;
+    use smallvec::SmallVec;
+
+    use crate::add_category_duration;
+    use crate::backend::backend::BuildListenerBackend;
+    use crate::backend::default::DefaultBackend;
+    use crate::backend::default::DefaultBackendOptions;
+    use crate::backend::longest_path_graph::LongestPathGraphBackend;
+    use crate::peak_concurrency;
+    use crate::BuildInfo;
+    use crate::BuildSignal;
+    use crate::BuildSignalReceiver;
+    use crate::BuildSignalSender;
+    use crate::CategoryCache;
+    use crate::DeferredBuildSignalsImpl;
+    use crate::Evaluation;
+    use crate::NodeKey;
+    use crate::CRITICAL_PATH_PROGRESS_INTERVAL;
+
+    fn context() -> BuildSignalsContext {
+        BuildSignalsContext {
+            command_name: "build".to_owned(),
+            metadata: HashMap::new(),
+            isolation_prefix: FileNameBuf::unchecked_new("v2"),
+            invocation_label: None,
+            include_transitive_set_projections: false,
+        }
+    }
+
+    fn build_graph_info(source: &mut ChannelEventSource) -> buck2_data::BuildGraphExecutionInfo {
+        std::iter::from_fn(|| source.try_receive())
+            .find_map(|event| match event {
+                Event::Buck(event) => match event.data() {
+                    buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                        data: Some(buck2_data::instant_event::Data::BuildGraphInfo(info)),
+                    }) => Some(info.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .expect("BuildGraphExecutionInfo should have been emitted")
+    }
+
+    fn intervals(ranges: &[(u64, u64)]) -> Vec<(Instant, Instant)> {
+        let base = Instant::now();
+        ranges
+            .iter()
+            .map(|(start, end)| {
+                (
+                    base + Duration::from_secs(*start),
+                    base + Duration::from_secs(*end),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_peak_concurrency_empty() {
+        assert_eq!(0, peak_concurrency(&[]));
+    }
+
+    #[test]
+    fn test_peak_concurrency_non_overlapping() {
+        assert_eq!(1, peak_concurrency(&intervals(&[(0, 1), (1, 2), (3, 4)])));
+    }
+
+    #[test]
+    fn test_peak_concurrency_overlapping() {
+        assert_eq!(
+            3,
+            peak_concurrency(&intervals(&[(0, 10), (1, 3), (2, 5), (4, 6), (7, 8)]))
+        );
+    }
+
+    #[test]
+    fn test_add_category_duration() {
+        let mut category_durations = BTreeMap::new();
+        add_category_duration(&mut category_durations, "link", Duration::from_secs(3));
+        add_category_duration(
+            &mut category_durations,
+            "cxx_compile",
+            Duration::from_secs(1),
+        );
+        add_category_duration(
+            &mut category_durations,
+            "cxx_compile",
+            Duration::from_secs(2),
+        );
+        add_category_duration(&mut category_durations, "redirect", Duration::ZERO);
+
+        assert_eq!(
+            vec![
+                ("cxx_compile", Duration::from_secs(3)),
+                ("link", Duration::from_secs(3)),
+            ],
+            category_durations
+                .iter()
+                .map(|(category, duration)| (category.as_str(), *duration))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_critical_path_progress() -> anyhow::Result<()> {
+        let analysis = |name: &str| {
+            NodeKey::AnalysisKey(AnalysisKey(ConfiguredTargetLabel::testing_parse(
+                &format!("cell//pkg:{}", name),
+                ConfigurationData::testing_new(),
+            )))
+        };
+        let evaluation = |name: &str, deps: &[&str]| Evaluation {
+            key: analysis(name),
+            duration: NodeDuration {
+                user: Duration::from_secs(1),
+                total: Duration::from_secs(1),
+            },
+            dep_keys: deps.iter().map(|dep| analysis(dep)).collect(),
+            spans: Default::default(),
+            action: None,
+            action_interval: None,
+            load_result: None,
+        };
+
+        let (mut source, sink) = create_source_sink_pair();
+        let events = EventDispatcher::new(TraceId::new(), sink);
+
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let send = async move {
+            // Give the receiver a tick after each evaluation, and another one with nothing new.
+            assert!(sender.send(evaluation("a", &[]).into()).is_ok());
+            tokio::time::sleep(CRITICAL_PATH_PROGRESS_INTERVAL * 3 / 2).await;
+            assert!(sender.send(evaluation("b", &["a"]).into()).is_ok());
+            tokio::time::sleep(CRITICAL_PATH_PROGRESS_INTERVAL * 2).await;
+            assert!(sender.send(BuildSignal::BuildFinished).is_ok());
+        };
+        let run = with_dispatcher_async(
+            events,
+            BuildSignalReceiver::new(
+                receiver,
+                DefaultBackend::new(DefaultBackendOptions::default()),
+            )
+            .run_and_log(context()),
+        );
+        let (res, ()) = tokio::join!(run, send);
+        res?;
+
+        let totals = std::iter::from_fn(|| source.try_receive())
+            .filter_map(|event| match event {
+                Event::Buck(event) => match event.data() {
+                    buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                        data: Some(buck2_data::instant_event::Data::CriticalPathProgress(progress)),
+                    }) => progress.total_duration.clone(),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .map(Duration::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec![Duration::from_secs(1), Duration::from_secs(2)], totals);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_potentials() -> anyhow::Result<()> {
+        let analysis = |name: &str| {
+            NodeKey::AnalysisKey(AnalysisKey(ConfiguredTargetLabel::testing_parse(
+                &format!("cell//pkg:{}", name),
+                ConfigurationData::testing_new(),
+            )))
+        };
+        let evaluation = |name: &str, deps: &[&str]| Evaluation {
+            key: analysis(name),
+            duration: NodeDuration {
+                user: Duration::from_secs(1),
+                total: Duration::from_secs(1),
+            },
+            dep_keys: deps.iter().map(|dep| analysis(dep)).collect(),
+            spans: Default::default(),
+            action: None,
+            action_interval: None,
+            load_result: None,
+        };
+
+        let potentials = |options: DefaultBackendOptions| async move {
+            let (mut source, sink) = create_source_sink_pair();
+            let events = EventDispatcher::new(TraceId::new(), sink);
+
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            // `b` depends on `a`.
+            assert!(sender.send(evaluation("a", &[]).into()).is_ok());
+            assert!(sender.send(evaluation("b", &["a"]).into()).is_ok());
+            assert!(sender.send(BuildSignal::BuildFinished).is_ok());
+            with_dispatcher_async(
+                events,
+                BuildSignalReceiver::new(receiver, DefaultBackend::new(options))
+                    .run_and_log(context()),
+            )
+            .await?;
+
+            build_graph_info(&mut source)
+                .potentials
+                .into_iter()
+                .map(|p| anyhow::Ok((p.node, Duration::try_from(p.potential.unwrap())?)))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+
+        assert_eq!(
+            vec![
+                (analysis("a").to_string(), Duration::from_secs(1)),
+                (analysis("b").to_string(), Duration::from_secs(1)),
+            ],
+            potentials(DefaultBackendOptions {
+                potentials: true,
+                ..Default::default()
+            })
+            .await?
+        );
+        assert!(
+            potentials(DefaultBackendOptions::default())
+                .await?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invocation_label() -> anyhow::Result<()> {
+        let (mut source, sink) = create_source_sink_pair();
+        let events = EventDispatcher::new(TraceId::new(), sink);
+
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        assert!(sender.send(BuildSignal::BuildFinished).is_ok());
+        let ctx = BuildSignalsContext {
+            invocation_label: Some("experiment-1".to_owned()),
+            ..context()
+        };
+        with_dispatcher_async(
+            events,
+            BuildSignalReceiver::new(
+                receiver,
+                DefaultBackend::new(DefaultBackendOptions::default()),
+            )
+            .run_and_log(ctx),
+        )
+        .await?;
+
+        let info = build_graph_info(&mut source);
+        assert_eq!(Some("experiment-1"), info.invocation_label.as_deref());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_uses_total_duration() -> anyhow::Result<()> {
+        /// A backend that weighs nodes by their user duration.
+        struct UserDurationBackend;
+
+        impl BuildListenerBackend for UserDurationBackend {
+            fn process_node(
+                &mut self,
+                _key: NodeKey,
+                _value: Option<Arc<RegisteredAction>>,
+                _duration: NodeDuration,
+                _interval: Option<(Instant, Instant)>,
+                _dep_keys: impl IntoIterator<Item = NodeKey>,
+                _span_ids: SmallVec<[SpanId; 1]>,
+            ) {
+            }
+
+            fn process_top_level_target(
+                &mut self,
+                _analysis: NodeKey,
+                _artifacts: impl IntoIterator<Item = NodeKey>,
+            ) {
+            }
+
+            fn finish(self) -> anyhow::Result<BuildInfo> {
+                Ok(BuildInfo {
+                    critical_path: Vec::new(),
+                    num_nodes: 0,
+                    num_edges: 0,
+                    uses_total_duration: false,
+                    max_fan_out: None,
+                    action_timeline: Vec::new(),
+                    finish_critical_path: Vec::new(),
+                    peak_concurrency: 0,
+                })
+            }
+
+            fn name() -> CriticalPathBackendName {
+                CriticalPathBackendName::Default
+            }
+        }
+
+        /// Run an empty build with this backend, and return the event it emitted.
+        async fn build_graph_info_for(
+            backend: impl BuildListenerBackend,
+        ) -> anyhow::Result<buck2_data::BuildGraphExecutionInfo> {
+            let (mut source, sink) = create_source_sink_pair();
+            let events = EventDispatcher::new(TraceId::new(), sink);
+
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            assert!(sender.send(BuildSignal::BuildFinished).is_ok());
+            with_dispatcher_async(
+                events,
+                BuildSignalReceiver::new(receiver, backend).run_and_log(context()),
+            )
+            .await?;
+
+            Ok(build_graph_info(&mut source))
+        }
+
+        assert!(
+            build_graph_info_for(DefaultBackend::new(DefaultBackendOptions::default()))
+                .await?
+                .uses_total_duration
+        );
+        assert!(
+            build_graph_info_for(LongestPathGraphBackend::new())
+                .await?
+                .uses_total_duration
+        );
+        assert!(
+            !build_graph_info_for(UserDurationBackend)
+                .await?
+                .uses_total_duration
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_include_transitive_set_projections() -> anyhow::Result<()> {
+        let target = |name: &str| {
+            ConfiguredTargetLabel::testing_parse(
+                &format!("cell//pkg:{}", name),
+                ConfigurationData::testing_new(),
+            )
+        };
+        let analysis = |name: &str| NodeKey::AnalysisKey(AnalysisKey(target(name)));
+        let (a, b) = (analysis("a"), analysis("b"));
+        let projection = TransitiveSetProjectionKey {
+            key: TransitiveSetKey::unchecked_new(DeferredKey::Base(
+                BaseDeferredKey::TargetLabel(target("a")),
+                DeferredId::testing_new(0),
+            )),
+            projection: 1,
+        };
+        let tset = NodeKey::EnsureTransitiveSetProjectionKey(EnsureTransitiveSetProjectionKey(
+            projection.dupe(),
+        ));
+        let evaluation = |key: &NodeKey, duration: Duration, deps: &[&NodeKey]| Evaluation {
+            key: key.dupe(),
+            duration: NodeDuration {
+                user: duration,
+                total: duration,
+            },
+            dep_keys: deps.iter().map(|dep| (*dep).dupe()).collect(),
+            spans: Default::default(),
+            action: None,
+            action_interval: None,
+            load_result: None,
+        };
+
+        let critical_path = |include_transitive_set_projections: bool| {
+            let evaluations = [
+                evaluation(&a, Duration::from_secs(1), &[]),
+                evaluation(&tset, Duration::ZERO, &[&a]),
+                evaluation(&b, Duration::from_secs(1), &[&tset]),
+            ];
+            async move {
+                let (mut source, sink) = create_source_sink_pair();
+                let events = EventDispatcher::new(TraceId::new(), sink);
+
+                let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                for evaluation in evaluations {
+                    assert!(sender.send(evaluation.into()).is_ok());
+                }
+                assert!(sender.send(BuildSignal::BuildFinished).is_ok());
+                let ctx = BuildSignalsContext {
+                    include_transitive_set_projections,
+                    ..context()
+                };
+                with_dispatcher_async(
+                    events,
+                    BuildSignalReceiver::new(
+                        receiver,
+                        DefaultBackend::new(DefaultBackendOptions::default()),
+                    )
+                    .run_and_log(ctx),
+                )
+                .await?;
+
+                anyhow::Ok(build_graph_info(&mut source).critical_path2)
+            }
+        };
+
+        // Both analyses, plus the time spent computing the critical path.
+        assert_eq!(3, critical_path(false).await?.len());
+
+        // `b` depends on `a` through the projection.
+        let critical_path = critical_path(true).await?;
+        assert_eq!(4, critical_path.len());
+        match &critical_path[1].entry {
+            Some(buck2_data::critical_path_entry2::Entry::ActionExecution(execution)) => {
+                assert_eq!(
+                    Some(buck2_data::ActionName {
+                        category: "transitive_set_projection".to_owned(),
+                        identifier: projection.to_string(),
+                    }),
+                    execution.name
+                );
+            }
+            entry => panic!("unexpected entry: {:?}", entry),
+        }
+        assert_eq!(Some(Duration::ZERO.try_into()?), critical_path[1].duration);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scope_with_each_backend() -> anyhow::Result<()> {
+        let analysis = |name: &str| {
+            NodeKey::AnalysisKey(AnalysisKey(ConfiguredTargetLabel::testing_parse(
+                &format!("cell//pkg:{}", name),
+                ConfigurationData::testing_new(),
+            )))
+        };
+        // `b` depends on `a`.
+        let evaluation = |name: &str, deps: &[&str]| Evaluation {
+            key: analysis(name),
+            duration: NodeDuration {
+                user: Duration::from_secs(1),
+                total: Duration::from_secs(1),
+            },
+            dep_keys: deps.iter().map(|dep| analysis(dep)).collect(),
+            spans: Default::default(),
+            action: None,
+            action_interval: None,
+            load_result: None,
+        };
+
+        for backend in [
+            CriticalPathBackendName::Default,
+            CriticalPathBackendName::LongestPathGraph,
+        ] {
+            let (mut source, sink) = create_source_sink_pair();
+            let events = EventDispatcher::new(TraceId::new(), sink);
+
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            let sender = Arc::new(BuildSignalSender { sender });
+            let deferred = Box::new(DeferredBuildSignalsImpl {
+                sender: sender.dupe(),
+                receiver,
+            });
+
+            buck2_build_signals::scope(deferred, events, backend, context(), || async {
+                for signal in [evaluation("a", &[]), evaluation("b", &["a"])] {
+                    assert!(sender.sender.send(signal.into()).is_ok());
+                }
+                Ok(())
+            })
+            .await?;
+
+            let info = build_graph_info(&mut source);
+            assert_eq!(Some(backend.to_string()), info.backend_name);
+            // Both analyses, plus the time spent computing the critical path.
+            assert_eq!(3, info.critical_path2.len(), "{}", backend);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_category_cache() {
+        let distinct = ["cxx_compile", "cxx_link", "write", "copy", "run"]
+            .map(|c| Category::try_from(c).unwrap());
+        let path = (0..10_000)
+            .map(|i| distinct[i % distinct.len()].clone())
+            .collect::<Vec<_>>();
+
+        let mut categories = CategoryCache::default();
+        let cached = path
+            .iter()
+            .map(|c| categories.get(c.as_str()))
+            .collect::<Vec<_>>();
+        let uncached = path
+            .iter()
+            .map(|c| c.as_str().to_owned())
+            .collect::<Vec<_>>();
+
+        assert_eq!(uncached, cached);
+        assert_eq!(distinct.len(), categories.categories.len());
+    }
+}
@@ -20,7 +20,9 @@ use buck2_build_api::build::BuildEvent;
 use buck2_build_api::build::BuildTargetResult;
 use buck2_build_api::build::ConfiguredBuildEvent;
 use buck2_build_api::build::ConvertMaterializationContext;
+use buck2_build_api::build::MessageSink;
 use buck2_build_api::build::ProvidersToBuild;
+use buck2_build_api::build::WantConfiguredGraphSize;
 use buck2_build_api::bxl::build_result::BxlBuildResult;
 use buck2_build_api::interpreter::rule_defs::artifact::StarlarkArtifact;
 use buck2_cli_proto::build_request::Materializations;
@@ -222,15 +224,22 @@ pub(crate) fn build<'v>(
                                     dice,
                                     &materializations,
                                     target,
-                                    &ProvidersToBuild {
-                                        default: true,
-                                        default_other: true,
-                                        run: true,
-                                        tests: true,
-                                    }, // TODO support skipping/configuring?
+                                    &ProvidersToBuild::builder().all().build(), // TODO support skipping/configuring?
                                     BuildConfiguredLabelOptions {
                                         skippable: false,
-                                        want_configured_graph_size: false,
+                                        want_configured_graph_size: WantConfiguredGraphSize::for_all(false),
+                                        configured_graph_size_max_depth: None,
+                                        request_id: None,
+                                        rule_type_filter: None,
+                                        output_path_filter: None,
+                                        register_as_top_level: true,
+                                        max_concurrent_materializations: None,
+                                        incompatible_message_sink: MessageSink::Console,
+                                        path_separator: None,
+                                        resolve_only: false,
+                                        message_sink: None,
+                                        cancellation: None,
+                                        analysis_only: false,
                                     },
                                 ).await
                             }.then(|stream| stream.collect::<Vec<_>>()).boxed()
@@ -249,7 +258,13 @@ pub(crate) fn build<'v>(
         .values()
         .flatten()
         .flat_map(|r| &r.errors)
-        .chain(build_result.other_errors.values().flatten())
+        .chain(
+            build_result
+                .other_errors
+                .values()
+                .flatten()
+                .map(|e| &e.error),
+        )
         .next()
     {
         return Err(err.dupe().into());
@@ -154,8 +154,16 @@ impl ExitResult {
                 .category
                 .and_then(buck2_data::error::ErrorCategory::from_i32)
             {
-                Some(buck2_data::error::ErrorCategory::Infra) => has_infra = true,
-                Some(buck2_data::error::ErrorCategory::User) => has_user = true,
+                // Internal errors are bugs in Buck, which we report like infra errors.
+                Some(
+                    buck2_data::error::ErrorCategory::Infra
+                    | buck2_data::error::ErrorCategory::Internal,
+                ) => has_infra = true,
+                // Environment errors are not Buck's fault, so report them like user errors.
+                Some(
+                    buck2_data::error::ErrorCategory::User
+                    | buck2_data::error::ErrorCategory::Environment,
+                ) => has_user = true,
                 Some(buck2_data::error::ErrorCategory::UnusedDefaultCategory) | None => (),
             }
         }
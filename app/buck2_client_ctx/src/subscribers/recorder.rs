@@ -1033,6 +1033,7 @@ mod imp {
                 .filter_map(buck2_data::error::ErrorTag::from_i32)
                 .map(|t| t.as_str_name().to_owned())
                 .collect(),
+            custom_type: error.custom_type,
         }
     }
 
@@ -27,7 +27,7 @@ impl AsciiCharSet {
         AsciiCharSet { mask }
     }
 
-    pub(crate) fn contains(&self, b: u8) -> bool {
+    pub(crate) const fn contains(&self, b: u8) -> bool {
         self.mask[b as usize]
     }
 }
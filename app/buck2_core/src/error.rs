@@ -167,6 +167,8 @@ pub fn handle_soft_error(
 ) -> anyhow::Result<anyhow::Error> {
     validate_category(category)?;
 
+    buck2_error::increment_soft_error_count(category);
+
     if cfg!(test) {
         // When running unit tests of `buck2_core` crate, all errors are hard errors.
         return Err(err);
@@ -201,6 +203,7 @@ pub fn reset_soft_error_counters() {
     for counter in ALL_SOFT_ERROR_COUNTERS.lock().unwrap().iter() {
         counter.store(0, Ordering::Relaxed);
     }
+    buck2_error::reset_soft_error_counts();
 }
 
 pub fn initialize(handler: StructuredErrorHandler) -> anyhow::Result<()> {
@@ -0,0 +1,760 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::borrow::Borrow;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::ops::Deref;
+
+use allocative::Allocative;
+use buck2_util::arc_str::ThinArcStr;
+use dashmap::DashSet;
+use dupe::Dupe;
+use equivalent::Equivalent;
+use fnv::FnvHasher;
+use once_cell::sync::Lazy;
+use static_interner::Interner;
+
+use crate::ascii_char_set::AsciiCharSet;
+
+pub const EQ_SIGN_SUBST: &str = "_eqsb_";
+
+/// The maximum length of a target name, in bytes. Much longer names (typically generated by
+/// macros) tend to exceed path length limits (notably on Windows) once they are part of an output
+/// path, which fails much later with a far less clear error.
+pub const MAX_TARGET_NAME_LEN: usize = 1024;
+
+const VALID_CHARS: AsciiCharSet = AsciiCharSet::new(
+    r"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789_,.=-\/~@!+$",
+);
+
+/// Construct a `TargetName` from a string literal, checking it at compile time rather than at
+/// runtime. Literals which `TargetName::new` would reject, or warn about, fail to compile.
+///
+/// ```
+/// let name = buck2_core::target_name!("foo-bar");
+/// assert_eq!("foo-bar", name.as_ref().as_str());
+/// ```
+///
+/// ```compile_fail
+/// let name = buck2_core::target_name!("foo:bar");
+/// ```
+#[macro_export]
+macro_rules! target_name {
+    ($name:literal) => {{
+        const _: () = assert!(
+            $crate::target::name::TargetName::is_valid_literal($name),
+            concat!("invalid target name literal: `", $name, "`"),
+        );
+        $crate::target::name::TargetName::unchecked_new($name)
+    }};
+}
+
+/// 'TargetName' is the name given to a particular target.
+/// e.g. `foo` in the label `fbsource//package/path:foo`.
+#[derive(
+    Clone,
+    Debug,
+    Dupe,
+    derive_more::Display,
+    Hash,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Allocative
+)]
+pub struct TargetName(ThinArcStr);
+
+/// Common names (e.g. `lib` or `test`) are used by a lot of targets, so share their storage.
+static INTERNER: Interner<ThinArcStr, FnvHasher> = Interner::new();
+
+#[derive(Hash)]
+struct TargetNameInternKey<'a>(&'a str);
+
+impl<'a> Equivalent<ThinArcStr> for TargetNameInternKey<'a> {
+    fn equivalent(&self, key: &ThinArcStr) -> bool {
+        self.0 == key.as_str()
+    }
+}
+
+impl<'a> From<TargetNameInternKey<'a>> for ThinArcStr {
+    fn from(key: TargetNameInternKey<'a>) -> Self {
+        ThinArcStr::from(key.0)
+    }
+}
+
+#[derive(buck2_error::Error, Debug)]
+enum TargetNameError {
+    #[error(
+        "Invalid target name `{}`. Target names are non-empty strings and can only contain alpha numeric characters, and symbols \
+        `,`, `.`, `=`, `-`, `/`, `~`, `@`, `!`, `+`, `$`, and `_`. No other characters are allowed.",
+        _0
+    )]
+    InvalidName(String),
+    #[error(
+        "Invalid target name `{}`. Invalid character `{}` at position {}. Target names can only contain alpha numeric characters, and symbols \
+        `,`, `.`, `=`, `-`, `/`, `~`, `@`, `!`, `+`, `$`, and `_`. No other characters are allowed.",
+        _0, _1.ch, _1.offset
+    )]
+    InvalidCharacter(String, InvalidCharAt),
+    #[error(
+        "found inner providers label when target names are expected. remove `[...]` portion of the target name from `{}`",
+        _0
+    )]
+    FoundProvidersLabel(String),
+    #[error("Target name `{0}` has special character `{1}`, which is discouraged")]
+    LabelHasSpecialCharacter(String, char),
+    #[error("Target name must not be equal to `...`")]
+    DotDotDot,
+    #[error("Target name is {len} bytes long, but the maximum is {max}: `{name}`")]
+    TooLong {
+        name: String,
+        len: usize,
+        max: usize,
+    },
+    #[error("Target name `{0}` should not contain pattern: `{1}`")]
+    InvalidPattern(String, String),
+}
+
+/// `(category, name)` pairs we already reported a soft error for. The same name tends to be
+/// validated over and over (e.g. in loops), and reporting it more than once per process is just
+/// noise.
+static REPORTED_SOFT_ERRORS: Lazy<DashSet<(&'static str, String)>> = Lazy::new(DashSet::new);
+
+/// Call `report` (which emits a soft error) unless it was already called for this `category` and
+/// `name`. If the soft error was upgraded to a hard error, it is forgotten again, so that later
+/// validations of that name keep failing (though a concurrent validation may have succeeded in the
+/// meantime).
+fn report_once(
+    category: &'static str,
+    name: &str,
+    report: impl FnOnce() -> anyhow::Result<anyhow::Error>,
+) -> anyhow::Result<()> {
+    let key = (category, name.to_owned());
+    // Claim the key before reporting, so that concurrent validations don't both report.
+    if !REPORTED_SOFT_ERRORS.insert(key.clone()) {
+        return Ok(());
+    }
+    if let Err(e) = report() {
+        REPORTED_SOFT_ERRORS.remove(&key);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// How strictly `TargetName::new_with_validation` treats characters which are allowed but
+/// discouraged (`,` and `$`).
+#[derive(Debug, Clone, Copy, Dupe, Default, Eq, PartialEq)]
+pub enum TargetNameValidation {
+    /// Accept them, reporting a soft error.
+    #[default]
+    Lenient,
+    /// Reject them.
+    Strict,
+}
+
+/// The first character in a target name which is not allowed, and its byte offset.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct InvalidCharAt {
+    pub offset: usize,
+    pub ch: char,
+}
+
+impl TargetName {
+    #[inline]
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        Self::new_with_validation(name, TargetNameValidation::Lenient)
+    }
+
+    pub fn new_with_validation(
+        name: &str,
+        validation: TargetNameValidation,
+    ) -> anyhow::Result<Self> {
+        Self::verify_with_validation(name, validation)?;
+        Ok(Self::unchecked_new(name))
+    }
+
+    #[inline]
+    pub fn unchecked_new(name: &str) -> Self {
+        Self(
+            INTERNER
+                .intern(TargetNameInternKey(name))
+                .deref_static()
+                .dupe(),
+        )
+    }
+
+    /// Whether `name` is a valid target name which `verify` accepts without reporting a soft
+    /// error. Used by `target_name!` to check literals at compile time.
+    #[doc(hidden)]
+    pub const fn is_valid_literal(name: &str) -> bool {
+        const fn contains_at(name: &[u8], pattern: &[u8], start: usize) -> bool {
+            let mut i = 0;
+            while i != pattern.len() {
+                if name[start + i] != pattern[i] {
+                    return false;
+                }
+                i += 1;
+            }
+            true
+        }
+
+        let name = name.as_bytes();
+        if name.is_empty() || name.len() > MAX_TARGET_NAME_LEN {
+            return false;
+        }
+        let mut i = 0;
+        while i != name.len() {
+            let b = name[i];
+            // `,` and `$` are allowed but discouraged.
+            if !VALID_CHARS.contains(b) || b == b',' || b == b'$' {
+                return false;
+            }
+            if i + EQ_SIGN_SUBST.len() <= name.len()
+                && contains_at(name, EQ_SIGN_SUBST.as_bytes(), i)
+            {
+                return false;
+            }
+            i += 1;
+        }
+        !(name.len() == 3 && contains_at(name, b"...", 0))
+    }
+
+    fn bad_name_error(name: &str, invalid_char: Option<InvalidCharAt>) -> anyhow::Error {
+        if let Some((_, p)) = name.split_once('[') {
+            if p.contains(']') {
+                return TargetNameError::FoundProvidersLabel(name.to_owned()).into();
+            }
+        }
+        match invalid_char {
+            Some(invalid_char) => {
+                TargetNameError::InvalidCharacter(name.to_owned(), invalid_char).into()
+            }
+            None => TargetNameError::InvalidName(name.to_owned()).into(),
+        }
+    }
+
+    /// Check that all characters in the name are allowed, reporting the first one which is not.
+    ///
+    /// This does not check for emptiness or disallowed patterns, which `verify` handles.
+    pub fn verify_detailed(name: &str) -> Result<(), InvalidCharAt> {
+        match name.bytes().position(|b| !VALID_CHARS.contains(b)) {
+            None => Ok(()),
+            Some(offset) => {
+                // All bytes before `offset` are ASCII, so `offset` is a char boundary.
+                let ch = name[offset..].chars().next().unwrap();
+                Err(InvalidCharAt { offset, ch })
+            }
+        }
+    }
+
+    fn verify(name: &str) -> anyhow::Result<()> {
+        Self::verify_with_validation(name, TargetNameValidation::Lenient)
+    }
+
+    fn verify_with_validation(name: &str, validation: TargetNameValidation) -> anyhow::Result<()> {
+        if name.is_empty() {
+            return Err(Self::bad_name_error(name, None));
+        }
+        if name.len() > MAX_TARGET_NAME_LEN {
+            return Err(TargetNameError::TooLong {
+                name: name.to_owned(),
+                len: name.len(),
+                max: MAX_TARGET_NAME_LEN,
+            }
+            .into());
+        }
+        if let Err(invalid_char) = Self::verify_detailed(name) {
+            return Err(Self::bad_name_error(name, Some(invalid_char)));
+        }
+
+        if name.contains(EQ_SIGN_SUBST) {
+            return Err(
+                TargetNameError::InvalidPattern(name.to_owned(), EQ_SIGN_SUBST.to_owned()).into(),
+            );
+        }
+
+        if name == "..." {
+            return Err(TargetNameError::DotDotDot.into());
+        }
+        if validation == TargetNameValidation::Strict {
+            if let Some(c) = name.chars().find(|c| matches!(c, ',' | '$')) {
+                return Err(TargetNameError::LabelHasSpecialCharacter(name.to_owned(), c).into());
+            }
+        }
+        if name.contains(',') {
+            report_once("label_has_comma", name, || {
+                soft_error!(
+                    "label_has_comma",
+                    TargetNameError::LabelHasSpecialCharacter(name.to_owned(), ',').into(),
+                    quiet: true
+                )
+            })?;
+        }
+        if name.contains('$') {
+            report_once("label_has_dollar_sign", name, || {
+                soft_error!(
+                    "label_has_dollar_sign",
+                    TargetNameError::LabelHasSpecialCharacter(name.to_owned(), '$').into(),
+                    quiet: true
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    // Generic `as_ref` confuses typechecker because of overloads.
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn as_ref(&self) -> &TargetNameRef {
+        TargetNameRef::unchecked_new(&self.0)
+    }
+}
+
+impl AsRef<str> for TargetName {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for TargetName {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<String> for TargetName {
+    #[inline]
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<str> for TargetName {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl Deref for TargetName {
+    type Target = TargetNameRef;
+
+    fn deref(&self) -> &TargetNameRef {
+        self.as_ref()
+    }
+}
+
+#[derive(Debug, derive_more::Display, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(transparent)]
+pub struct TargetNameRef(str);
+
+impl TargetNameRef {
+    pub fn new(name: &str) -> anyhow::Result<&TargetNameRef> {
+        TargetName::verify(name)?;
+        Ok(TargetNameRef::unchecked_new(name))
+    }
+
+    #[inline]
+    pub fn unchecked_new(name: &str) -> &TargetNameRef {
+        unsafe {
+            // SAFETY: `repr(transparent)`.
+            &*(name as *const str as *const TargetNameRef)
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    #[inline]
+    pub fn to_owned(&self) -> TargetName {
+        TargetName::unchecked_new(&self.0)
+    }
+
+    /// A version of this name which is safe to use as a single path component on all platforms,
+    /// e.g. for per-target scratch directories.
+    ///
+    /// ASCII alphanumerics and `_`, `-`, `.`, `,`, `=` and `+` are kept as is. Any other character
+    /// (e.g. `/`, `@`, `!`, `$` or `~`), as well as a leading `.` (so that the slug is never `.`,
+    /// `..` or a hidden file), is replaced with `%` followed by its two uppercase hex digits.
+    /// Target names never contain `%`, so distinct names always produce distinct slugs, and the
+    /// name can be recovered by decoding the `%XX` escapes.
+    ///
+    /// Note that on case-insensitive filesystems, names which differ only by case still collide
+    /// (see `normalized`).
+    pub fn to_fs_slug(&self) -> String {
+        const SAFE_CHARS: &str =
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789_-.,=+";
+        const SAFE: AsciiCharSet = AsciiCharSet::new(SAFE_CHARS);
+
+        let mut slug = String::with_capacity(self.0.len());
+        for (i, b) in self.0.bytes().enumerate() {
+            if SAFE.contains(b) && !(i == 0 && b == b'.') {
+                slug.push(b as char);
+            } else {
+                write!(slug, "%{:02X}", b).unwrap();
+            }
+        }
+        slug
+    }
+
+    /// This name with ASCII letters lowercased (target names are always ASCII). Names with the same
+    /// normalized form, e.g. `Foo` and `foo`, are distinct targets, but collide when written to a
+    /// case-insensitive filesystem (the default on macOS and Windows).
+    pub fn normalized(&self) -> Cow<str> {
+        if self.0.bytes().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(self.0.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(&self.0)
+        }
+    }
+
+    /// Whether this name and `other` have the same `normalized` form.
+    pub fn eq_ignore_case(&self, other: &TargetNameRef) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+
+    /// Whether this name matches `pattern`, where `*` matches any (possibly empty) run of
+    /// characters and `?` matches exactly one character. Every other character only matches
+    /// itself: there is no escaping, and e.g. `.` or `+` are not special as they are in regexes.
+    pub fn matches_glob(&self, pattern: &str) -> bool {
+        let name = self.0.chars().collect::<Vec<_>>();
+        let pattern = pattern.chars().collect::<Vec<_>>();
+
+        let (mut n, mut p) = (0, 0);
+        // The position of the last `*` in the pattern, and the position in the name it was tried
+        // against, so that we can backtrack and let it match one more character.
+        let mut backtrack = None;
+        while n < name.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p, n));
+                    p += 1;
+                }
+                Some(&c) if c == '?' || c == name[n] => {
+                    n += 1;
+                    p += 1;
+                }
+                _ => match backtrack {
+                    Some((star, star_n)) => {
+                        backtrack = Some((star, star_n + 1));
+                        p = star + 1;
+                        n = star_n + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|c| *c == '*')
+    }
+}
+
+impl Borrow<TargetNameRef> for TargetName {
+    #[inline]
+    fn borrow(&self) -> &TargetNameRef {
+        self.as_ref()
+    }
+}
+
+impl AsRef<str> for TargetNameRef {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Validate each of `names`, returning the index and error of every invalid one.
+///
+/// Unlike calling `TargetName::new` in a loop, this does not stop at the first failure.
+pub fn validate_names(names: &[&str]) -> Vec<(usize, anyhow::Error)> {
+    names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| TargetName::verify(name).err().map(|e| (i, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::hash::Hash;
+    use std::hash::Hasher;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use buck2_util::arc_str::ThinArcStr;
+
+    use crate::target::name::report_once;
+    use crate::target::name::validate_names;
+    use crate::target::name::InvalidCharAt;
+    use crate::target::name::TargetName;
+    use crate::target::name::TargetNameError;
+    use crate::target::name::TargetNameRef;
+    use crate::target::name::TargetNameValidation;
+    use crate::target::name::MAX_TARGET_NAME_LEN;
+
+    #[test]
+    fn target_name_validation() {
+        assert_eq!(
+            TargetName::new("foo").unwrap(),
+            TargetName(ThinArcStr::from("foo"))
+        );
+        assert_eq!(
+            // Copied allowed symbols from above.
+            // `.`, `-`, `/`, `~`, `@`, `!`, `+` and `_`
+            // `,`, `$`, and `=` are currently soft errors and should eventually be removed.
+            TargetName::new("foo.-/~@!+_1").unwrap(),
+            TargetName(ThinArcStr::from("foo.-/~@!+_1"))
+        );
+        assert!(TargetName::new("foo bar").is_err());
+        assert!(TargetName::new("foo?bar").is_err());
+        assert!(TargetName::new("foo_eqsb_bar").is_err());
+
+        if let Err(e) = TargetName::new("target[label]") {
+            let msg = format!("{:#}", e);
+            assert!(msg.contains("found inner providers label when target names are expected. remove `[...]` portion of the target name from `target[label]`"), "{}", msg);
+        } else {
+            panic!("should have gotten an error")
+        }
+    }
+
+    #[test]
+    fn test_verify_detailed() {
+        assert_eq!(Ok(()), TargetName::verify_detailed("foo.-/~@!+_1"));
+        assert_eq!(
+            Err(InvalidCharAt { offset: 3, ch: ' ' }),
+            TargetName::verify_detailed("foo bar")
+        );
+        assert_eq!(
+            Err(InvalidCharAt {
+                offset: 2, ch: 'ä'
+            }),
+            TargetName::verify_detailed("abäc?")
+        );
+    }
+
+    #[test]
+    fn test_validate_names() {
+        let errors = validate_names(&["foo", "foo bar", "", "baz.1", "x[y]", "..."]);
+        assert_eq!(
+            vec![1, 2, 4, 5],
+            errors.iter().map(|(i, _)| *i).collect::<Vec<_>>()
+        );
+        assert!(format!("{:#}", errors[2].1).contains("found inner providers label"));
+
+        assert!(validate_names(&["a", "b"]).is_empty());
+        assert!(validate_names(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_report_once() {
+        let calls = AtomicUsize::new(0);
+        let report = || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(anyhow::anyhow!("bad name"))
+        };
+        report_once("test_report_once", "foo,bar", report).unwrap();
+        report_once("test_report_once", "foo,bar", report).unwrap();
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        // Other names and categories are still reported.
+        report_once("test_report_once", "foo,baz", report).unwrap();
+        report_once("test_report_once_other", "foo,bar", report).unwrap();
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        // Hard errors are returned every time.
+        let calls = AtomicUsize::new(0);
+        let fail = || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow::anyhow!("bad name"))
+        };
+        assert!(report_once("test_report_once", "foo$bar", fail).is_err());
+        assert!(report_once("test_report_once", "foo$bar", fail).is_err());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_target_name_macro() {
+        assert_eq!(
+            TargetName::new("foo.-/~@!+_1").unwrap(),
+            target_name!("foo.-/~@!+_1")
+        );
+
+        for valid in ["foo", "a", "..", "x=y", "_eqsb"] {
+            assert!(TargetName::is_valid_literal(valid), "{}", valid);
+        }
+        for invalid in [
+            "",
+            "...",
+            "foo bar",
+            "foo_eqsb_bar",
+            "_eqsb_",
+            "x[y]",
+            "a,b",
+            "a$b",
+        ] {
+            assert!(!TargetName::is_valid_literal(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_invalid_character_position() {
+        let err = TargetName::new("foo?bar").unwrap_err().to_string();
+        assert!(
+            err.contains("Invalid character `?` at position 3"),
+            "{}",
+            err
+        );
+        assert!(err.contains("`foo?bar`"), "{}", err);
+
+        let err = TargetName::new("").unwrap_err().to_string();
+        assert!(!err.contains("position"), "{}", err);
+
+        // Names that look like they carry a providers label keep their dedicated error.
+        let err = TargetName::new("foo[bar]").unwrap_err().to_string();
+        assert!(err.contains("found inner providers label"), "{}", err);
+    }
+
+    #[test]
+    fn test_max_length() {
+        let longest = "a".repeat(MAX_TARGET_NAME_LEN);
+        assert!(TargetName::new(&longest).is_ok());
+        assert!(TargetName::is_valid_literal(&longest));
+
+        let too_long = "a".repeat(MAX_TARGET_NAME_LEN + 1);
+        let err = TargetName::new(&too_long).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<TargetNameError>(),
+                Some(TargetNameError::TooLong { len, max: MAX_TARGET_NAME_LEN, .. })
+                    if *len == MAX_TARGET_NAME_LEN + 1
+            ),
+            "{:#}",
+            err
+        );
+        assert!(!TargetName::is_valid_literal(&too_long));
+        assert!(TargetNameRef::new(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        for validation in [TargetNameValidation::Lenient, TargetNameValidation::Strict] {
+            // With `Lenient`, this is a soft error, but unit tests of this crate upgrade all soft
+            // errors to hard errors, so both modes fail here. `tests/soft_error.rs` checks that
+            // `Lenient` accepts it.
+            let err = TargetName::new_with_validation("a,b", validation)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("special character `,`"), "{}", err);
+            assert!(TargetName::new_with_validation("ab", validation).is_ok());
+            assert!(TargetName::new_with_validation("a b", validation).is_err());
+        }
+        assert!(TargetName::new_with_validation("a$b", TargetNameValidation::Strict).is_err());
+    }
+
+    #[test]
+    fn test_to_fs_slug() {
+        fn slug(name: &str) -> String {
+            TargetNameRef::unchecked_new(name).to_fs_slug()
+        }
+
+        assert_eq!("foo", slug("foo"));
+        assert_eq!("foo_bar-1.2,x=y+z", slug("foo_bar-1.2,x=y+z"));
+        assert_eq!("foo%2Fbar", slug("foo/bar"));
+        assert_eq!("foo%40bar", slug("foo@bar"));
+        assert_eq!("%2E.", slug(".."));
+        assert_eq!("%2Efoo.bar", slug(".foo.bar"));
+        assert_eq!("a%21b%24c%7Ed%5C", slug("a!b$c~d\\"));
+        // Escapes can't collide with names which happen to look like them.
+        assert_ne!(slug("a/b"), slug("a2Fb"));
+    }
+
+    #[test]
+    fn test_matches_glob() {
+        fn matches(name: &str, pattern: &str) -> bool {
+            TargetNameRef::unchecked_new(name).matches_glob(pattern)
+        }
+
+        assert!(matches("foo", "foo"));
+        assert!(matches("foobar", "foo*"));
+        assert!(matches("foo", "foo*"));
+        assert!(matches("foobar", "*bar"));
+        assert!(matches("foo", "f?o"));
+        assert!(matches("foo", "*"));
+        assert!(matches("foo-bar-baz", "*-*-baz"));
+        assert!(matches("abab", "*ab"));
+        assert!(matches("lib.so+1", "lib.so+?"));
+        assert!(matches("a/b@c~d", "a/*@c~?"));
+
+        assert!(!matches("foo", "bar*"));
+        assert!(!matches("foobar", "*baz"));
+        assert!(!matches("fo", "f?o"));
+        assert!(!matches("fooo", "f?o"));
+        assert!(!matches("foo", ""));
+        // Characters which are special in regexes only match themselves.
+        assert!(!matches("libxso", "lib.so"));
+        assert!(!matches("aa", "a+"));
+    }
+
+    #[test]
+    fn test_normalized() {
+        let upper = TargetName::new("Foo").unwrap();
+        let lower = TargetName::new("foo").unwrap();
+
+        assert_ne!(upper, lower);
+        assert_eq!(upper.normalized(), lower.normalized());
+        assert_eq!("foo", upper.normalized());
+        assert!(upper.eq_ignore_case(&lower));
+        assert!(lower.eq_ignore_case(&upper));
+        assert!(!upper.eq_ignore_case(TargetNameRef::unchecked_new("food")));
+
+        // Names which are already lowercase are not copied.
+        assert!(matches!(lower.normalized(), Cow::Borrowed("foo")));
+        // Only letters are affected.
+        assert_eq!(
+            "lib-1.2_x@y",
+            TargetNameRef::unchecked_new("LIB-1.2_X@y").normalized()
+        );
+    }
+
+    #[test]
+    fn test_interned() {
+        let a = TargetName::new("lib").unwrap();
+        let b = TargetName::new("lib").unwrap();
+        assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+        assert_eq!(
+            a.as_str().as_ptr(),
+            TargetName::unchecked_new("lib").as_str().as_ptr()
+        );
+        assert_ne!(
+            a.as_str().as_ptr(),
+            TargetName::new("main").unwrap().as_str().as_ptr()
+        );
+    }
+
+    #[test]
+    fn test_value_and_ref_hashes_equal() {
+        fn hash<T: Hash + ?Sized>(t: &T) -> u64 {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            t.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        assert_eq!(
+            hash(TargetNameRef::unchecked_new("foo")),
+            hash(&TargetName::unchecked_new("foo"))
+        );
+    }
+}
@@ -16,6 +16,8 @@ use buck2_core::error::reset_soft_error_counters;
 use buck2_core::error::StructuredErrorOptions;
 use buck2_core::is_open_source;
 use buck2_core::soft_error;
+use buck2_core::target::name::TargetName;
+use buck2_core::target::name::TargetNameValidation;
 
 static RESULT: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
@@ -100,3 +102,55 @@ fn test_reset_counters() {
         "Should be logged 10 more times"
     );
 }
+
+#[test]
+fn test_soft_error_counts() {
+    let _guard = test_init();
+
+    for _ in 0..2 {
+        let _ignore = soft_error!("test_soft_error_counts", anyhow::anyhow!("Message"));
+    }
+    assert_eq!(
+        Some(&2),
+        buck2_error::soft_error_counts().get("test_soft_error_counts")
+    );
+
+    reset_soft_error_counters();
+    assert_eq!(
+        None,
+        buck2_error::soft_error_counts().get("test_soft_error_counts")
+    );
+}
+
+#[test]
+fn test_target_name_soft_error_reported_once() {
+    if is_open_source() {
+        return; // Errors are always hard in open source
+    }
+    let _guard = test_init();
+
+    for _ in 0..3 {
+        TargetName::new("reported_once,name").unwrap();
+    }
+
+    let result = RESULT.lock().unwrap();
+    assert_eq!(1, result.len(), "{:?}", result);
+    assert!(result[0].contains("label_has_comma"), "{:?}", result);
+}
+
+#[test]
+fn test_target_name_validation() {
+    if is_open_source() {
+        return; // Errors are always hard in open source
+    }
+    let _guard = test_init();
+
+    // A discouraged character is only a soft error with `Lenient`, but an error with `Strict`.
+    assert!(
+        TargetName::new_with_validation("validation,name", TargetNameValidation::Lenient).is_ok()
+    );
+    let err = TargetName::new_with_validation("validation,name", TargetNameValidation::Strict)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("special character `,`"), "{}", err);
+}
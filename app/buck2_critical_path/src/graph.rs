@@ -137,6 +137,58 @@ impl Graph {
         Ok(topo_order)
     }
 
+    /// Find a cycle in this graph, if it has any (which `topo_sort` reports, but can't describe).
+    /// The vertices of the cycle are returned in the order of its edges: each vertex has an edge
+    /// to the next one, and the last one has an edge to the first one.
+    pub fn find_cycle(&self) -> Option<Vec<VertexId>> {
+        enum Work {
+            Push(VertexId),
+            Pop,
+        }
+
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Unvisited,
+            OnPath,
+            Visited,
+        }
+
+        let mut state = self.allocate_vertex_data(State::Unvisited);
+        // The vertices from the root of the current traversal to the vertex being visited.
+        let mut path = Vec::new();
+        let mut queue = Vec::new();
+
+        for i in self.iter_vertices() {
+            queue.push(Work::Push(i));
+
+            while let Some(work) = queue.pop() {
+                match work {
+                    Work::Push(j) => match state[j] {
+                        State::Visited => {}
+                        State::OnPath => {
+                            // The vertex on top of the path has an edge back to `j`.
+                            let start = path.iter().position(|v| *v == j)?;
+                            return Some(path.split_off(start));
+                        }
+                        State::Unvisited => {
+                            state[j] = State::OnPath;
+                            path.push(j);
+                            queue.push(Work::Pop);
+                            queue.extend(self.iter_edges(j).map(Work::Push));
+                        }
+                    },
+                    Work::Pop => {
+                        if let Some(j) = path.pop() {
+                            state[j] = State::Visited;
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
     /// Given a DAG and a *reverse topological* ordering thereof, return the predecessor for each
     /// node after aggregating by runtime.
     pub fn find_longest_paths(
@@ -378,6 +430,30 @@ mod test {
         assert!(graph.topo_sort().is_err());
     }
 
+    #[test]
+    fn test_find_cycle() {
+        let (graph, keys, data) = test_graph();
+        assert_eq!(None, graph.find_cycle());
+
+        // Close the cycle K0 -> K2 -> K3 -> K0, next to the K0 -> K1 edge.
+        let v0 = keys.get(&K0).unwrap();
+        let v3 = keys.get(&K3).unwrap();
+        let mut new_edges = graph.allocate_vertex_data(OptionalVertexId::none());
+        new_edges[v3] = v0.into();
+        let graph = graph.add_edges(&new_edges, None).unwrap();
+
+        let mut cycle = graph
+            .find_cycle()
+            .unwrap()
+            .into_iter()
+            .map(|v| data[v])
+            .collect::<Vec<_>>();
+        // Where the cycle starts depends on the traversal order.
+        let start = cycle.iter().position(|k| *k == K0).unwrap();
+        cycle.rotate_left(start);
+        assert_eq!(vec![K0, K2, K3], cycle);
+    }
+
     #[test]
     fn test_longest_paths() {
         let (graph, keys, _data) = test_graph();
@@ -186,6 +186,22 @@ fn main() -> io::Result<()> {
             "CriticalPathEntry2.potential_improvement_duration",
             "#[serde(rename = \"potential_improvement_duration_us\", with = \"crate::serialize_duration_as_micros\")]",
         )
+        .field_attribute(
+            "CriticalPathProgress.total_duration",
+            "#[serde(rename = \"total_duration_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
+        .field_attribute(
+            "CriticalPathPotential.potential",
+            "#[serde(rename = \"potential_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
+        .field_attribute(
+            "ActionTiming.start",
+            "#[serde(rename = \"start_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
+        .field_attribute(
+            "ActionTiming.end",
+            "#[serde(rename = \"end_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
         .type_attribute(
             "buck.data.CriticalPathEntry2.entry",
             "#[derive(::derive_more::From, ::gazebo::variants::VariantName)]",
@@ -97,6 +97,17 @@ tonic::include_proto!("buck.data");
 
 pub mod error {
     tonic::include_proto!("buck.data.error");
+
+    impl ErrorTag {
+        /// Whether this tag implies that the error is transient, i.e. that the operation which
+        /// failed may succeed if retried.
+        pub fn is_transient(&self) -> bool {
+            match self {
+                ErrorTag::WatchmanTimeout | ErrorTag::Retryable => true,
+                ErrorTag::UnusedDefaultTag | ErrorTag::StarlarkFail => false,
+            }
+        }
+    }
 }
 
 /// Trait for things that can be converted into protobuf messages, for ease of emitting events. There are many core Buck
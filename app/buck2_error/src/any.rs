@@ -64,7 +64,9 @@ pub(crate) fn recover_crate_error(
     // the bottom-most value when actually generating the root
     let mut source_location = source_location;
     let mut typ = None;
+    let mut custom_typ = None;
     let mut action_error = None;
+    let mut remediation = None;
     let base = 'base: loop {
         // Handle the `cur` error
         if let Some(base) = cur.downcast_ref::<CrateAsStdError>() {
@@ -79,9 +81,15 @@ pub(crate) fn recover_crate_error(
             if metadata.typ.is_some() {
                 typ = metadata.typ;
             }
+            if metadata.custom_typ.is_some() {
+                custom_typ = metadata.custom_typ;
+            }
             if metadata.action_error.is_some() {
                 action_error = metadata.action_error;
             }
+            if metadata.remediation.is_some() {
+                remediation = metadata.remediation;
+            }
         }
 
         // Compute the next element in the source chain
@@ -99,8 +107,10 @@ pub(crate) fn recover_crate_error(
         let e = crate::Error(Arc::new(ErrorKind::Root(Box::new(ErrorRoot::new(
             description,
             typ,
+            custom_typ,
             source_location,
             action_error,
+            remediation,
         )))));
         break 'base maybe_add_context_from_metadata(e, cur);
     };
@@ -145,7 +155,7 @@ impl StdError for CrateAsStdError {
 
 /// This can be `provide`d by an error to inject buck2-specific information about it.
 ///
-/// For `typ`, `action_error`, and the source information, only the value that appears last in the
+/// For `typ`, `custom_typ`, `action_error`, `remediation`, and the source information, only the value that appears last in the
 /// source chain will be used. The derive macro typically handles this to prevent any surprises,
 /// however if this value is being provided manually then care may need to be taken.
 #[derive(Clone)]
@@ -157,8 +167,12 @@ pub struct ProvidableMetadata {
     /// and the same thing as gets passed to `buck2_error::source_location::from_file`.
     pub source_location_extra: Option<&'static str>,
     pub typ: Option<crate::ErrorType>,
+    /// A domain-specific error type, for errors which none of the `ErrorType`s describe.
+    pub custom_typ: Option<&'static str>,
     /// The protobuf ActionError, if the root was an action error
     pub action_error: Option<buck2_data::ActionError>,
+    /// A link explaining how to fix the error.
+    pub remediation: Option<&'static str>,
 }
 
 #[cfg(test)]
@@ -223,7 +237,9 @@ mod tests {
         fn provide<'a>(&'a self, request: &mut Request<'a>) {
             request.provide_value(ProvidableMetadata {
                 typ: Some(crate::ErrorType::Watchman),
+                custom_typ: None,
                 action_error: None,
+                remediation: None,
                 source_file: file!(),
                 source_location_extra: Some("FullMetadataError"),
                 tags: vec![
@@ -243,7 +259,7 @@ mod tests {
             crate::Error::new(FullMetadataError),
         ] {
             assert_eq!(e.get_category(), Some(crate::Category::User));
-            assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman));
+            assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
             assert_eq!(
                 e.source_location(),
                 Some("buck2_error/src/any.rs::FullMetadataError")
@@ -287,7 +303,7 @@ mod tests {
     fn test_context_in_wrapper() {
         let e: crate::Error = FullMetadataContextWrapperError(FullMetadataError).into();
         assert_eq!(e.get_category(), Some(crate::Category::Infra));
-        assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman));
+        assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
         assert_eq!(
             e.source_location(),
             Some("buck2_error/src/any.rs::FullMetadataError")
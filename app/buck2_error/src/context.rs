@@ -60,6 +60,16 @@ pub trait Context<T>: Sealed {
         self.context(crate::Category::Infra)
     }
 
+    #[track_caller]
+    fn environment(self) -> anyhow::Result<T> {
+        self.context(crate::Category::Environment)
+    }
+
+    #[track_caller]
+    fn internal(self) -> anyhow::Result<T> {
+        self.context(crate::Category::Internal)
+    }
+
     #[track_caller]
     fn tag(self, tag: crate::ErrorTag) -> anyhow::Result<T> {
         self.context(ContextValue::Tags(smallvec![tag]))
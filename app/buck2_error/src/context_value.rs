@@ -62,10 +62,25 @@ impl<T: fmt::Display> From<T> for ContextValue {
     }
 }
 
-#[derive(allocative::Allocative, PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(
+    allocative::Allocative,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Copy,
+    Clone,
+    Debug
+)]
 pub enum Category {
     User,
     Infra,
+    /// The error is caused by the machine Buck is running on being misconfigured, e.g. a missing
+    /// system tool, a wrong `PATH` or an incompatible OS.
+    Environment,
+    /// The error is a bug in Buck itself, e.g. a violated invariant. It is neither the user's
+    /// fault nor caused by infra.
+    Internal,
 }
 
 impl crate::Error {
@@ -75,29 +90,80 @@ impl crate::Error {
             ContextValue::Category(cat) => Some(*cat),
             _ => None,
         }) {
-            // It's an infra error if it was ever marked as an infra error
+            // It's an internal error if it was ever marked as an internal error, otherwise an
+            // infra error if it was ever marked as an infra error, and otherwise an environment
+            // error if it was ever marked as one
             match cat {
-                Category::Infra => return Some(cat),
-                Category::User => out = Some(cat),
+                Category::Internal => return Some(cat),
+                Category::Infra => out = Some(cat),
+                Category::Environment if out == Some(Category::Infra) => {}
+                Category::Environment => out = Some(cat),
+                Category::User => {
+                    if out.is_none() {
+                        out = Some(cat)
+                    }
+                }
             }
         }
         out
     }
 
-    /// Get all the tags that have been added to this error
-    pub fn get_tags(&self) -> Vec<crate::ErrorTag> {
-        let mut tags: Vec<_> = self
-            .iter_context()
+    /// Whether this error has been categorized as a user error (and no higher-priority category).
+    pub fn is_user(&self) -> bool {
+        self.get_category() == Some(Category::User)
+    }
+
+    /// Whether this error has been categorized as an infra error (and not also as an internal
+    /// error).
+    pub fn is_infra(&self) -> bool {
+        self.get_category() == Some(Category::Infra)
+    }
+
+    /// Whether this error has been categorized as an environment error (and no higher-priority
+    /// category).
+    pub fn is_environment(&self) -> bool {
+        self.get_category() == Some(Category::Environment)
+    }
+
+    /// Whether this error has been categorized as an internal error, i.e. a bug in Buck.
+    pub fn is_internal(&self) -> bool {
+        self.get_category() == Some(Category::Internal)
+    }
+
+    /// Whether retrying the operation which produced this error may succeed: it's an infra error,
+    /// or it was tagged with a transient tag (see `ErrorTag::is_transient`), whatever its
+    /// category.
+    pub fn is_retryable(&self) -> bool {
+        self.is_infra() || self.get_tags().iter().any(|tag| tag.is_transient())
+    }
+
+    fn iter_tags(&self) -> impl Iterator<Item = crate::ErrorTag> + '_ {
+        self.iter_context()
             .filter_map(|kind| match kind {
                 ContextValue::Tags(tags) => Some(tags.iter().copied()),
                 _ => None,
             })
             .flatten()
-            .collect();
+    }
+
+    /// Get all the tags that have been added to this error
+    pub fn get_tags(&self) -> Vec<crate::ErrorTag> {
+        let mut tags: Vec<_> = self.iter_tags().collect();
         tags.sort_unstable_by_key(|tag| tag.as_str_name());
         tags.dedup();
         tags
     }
+
+    /// Whether `tag` has been added to this error. Unlike `get_tags`, this does not allocate.
+    pub fn has_tag(&self, tag: crate::ErrorTag) -> bool {
+        self.iter_tags().any(|t| t == tag)
+    }
+
+    /// Whether any of `tags` has been added to this error. Unlike `get_tags`, this does not
+    /// allocate.
+    pub fn has_any_tag(&self, tags: &[crate::ErrorTag]) -> bool {
+        self.iter_tags().any(|t| tags.contains(&t))
+    }
 }
 
 impl From<Category> for ContextValue {
@@ -131,4 +197,52 @@ mod tests {
             .context(crate::Category::User);
         assert_eq!(e.get_category(), Some(crate::Category::Infra));
     }
+
+    #[test]
+    fn test_category_internal_preferred() {
+        let e: crate::Error = TestError.into();
+        let e = e
+            .context(crate::Category::User)
+            .context(crate::Category::Internal)
+            .context(crate::Category::Infra);
+        assert_eq!(e.get_category(), Some(crate::Category::Internal));
+        assert!(e.is_internal());
+        assert!(!e.is_infra());
+        assert!(!e.is_user());
+    }
+
+    #[test]
+    fn test_category_environment_preferred_over_user() {
+        let e: crate::Error = TestError.into();
+        let e = e
+            .context(crate::Category::Environment)
+            .context(crate::Category::User);
+        assert_eq!(e.get_category(), Some(crate::Category::Environment));
+        assert!(e.is_environment());
+
+        let e = e.context(crate::Category::Infra);
+        assert_eq!(e.get_category(), Some(crate::Category::Infra));
+        assert!(!e.is_environment());
+    }
+
+    #[test]
+    fn test_transient_tag_retryable() {
+        let e: crate::Error = TestError.into();
+        let e = e.context(crate::Category::User);
+        assert!(!e.is_retryable());
+        assert!(
+            !e.clone()
+                .tag([crate::ErrorTag::StarlarkFail])
+                .is_retryable()
+        );
+        assert!(
+            e.clone()
+                .tag([crate::ErrorTag::WatchmanTimeout])
+                .is_retryable()
+        );
+        assert!(e.tag([crate::ErrorTag::Retryable]).is_retryable());
+
+        let e: crate::Error = TestError.into();
+        assert!(e.context(crate::Category::Infra).is_retryable());
+    }
 }
@@ -38,10 +38,86 @@ fn test_derive_error2() {
     assert_eq!(e.get_category(), Some(crate::Category::Infra));
     assert_eq!(
         e.get_error_type(),
-        Some(crate::ErrorType::ActionCommandFailure)
+        Some(crate::ErrorType::ActionCommandFailure.into())
     );
 }
 
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("foo")]
+#[buck2(environment)]
+pub struct EnvironmentError;
+
+#[test]
+fn test_derive_environment_error() {
+    let e: crate::Error = EnvironmentError.into();
+    assert_eq!(e.get_category(), Some(crate::Category::Environment));
+    assert!(e.is_environment());
+    assert!(!e.is_user());
+
+    let e: anyhow::Error = EnvironmentError.into();
+    let e: crate::Error = e.into();
+    assert_eq!(e.get_category(), Some(crate::Category::Environment));
+}
+
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("foo")]
+#[buck2(typ = ActionCommandFailure, internal)]
+#[allow(unused)]
+struct InternalError((), ());
+
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("foo")]
+#[buck2(category = Internal)]
+struct InternalCategoryError;
+
+#[test]
+fn test_derive_internal_error() {
+    let e: crate::Error = InternalError((), ()).into();
+    assert_eq!(e.get_category(), Some(crate::Category::Internal));
+    assert!(e.is_internal());
+    assert!(!e.is_infra());
+    assert_eq!(
+        e.get_error_type(),
+        Some(crate::ErrorType::ActionCommandFailure.into())
+    );
+
+    let e: anyhow::Error = InternalCategoryError.into();
+    let e: crate::Error = e.into();
+    assert_eq!(e.get_category(), Some(crate::Category::Internal));
+}
+
+#[derive(buck2_error_derive::Error, Debug)]
+enum RemediationError {
+    #[error("foo")]
+    #[buck2(
+        user,
+        remediation = "https://buck2.build/docs/users/faq/common_issues/"
+    )]
+    WithRemediation,
+    #[error("bar")]
+    #[buck2(user)]
+    WithoutRemediation,
+}
+
+#[test]
+fn test_derive_remediation() {
+    let e: crate::Error = RemediationError::WithRemediation.into();
+    assert_eq!(
+        e.remediation(),
+        Some("https://buck2.build/docs/users/faq/common_issues/")
+    );
+    // Context doesn't hide the remediation of the root.
+    let e: anyhow::Error = e.into();
+    let e: crate::Error = e.context("context").into();
+    assert_eq!(
+        e.remediation(),
+        Some("https://buck2.build/docs/users/faq/common_issues/")
+    );
+
+    let e: crate::Error = RemediationError::WithoutRemediation.into();
+    assert_eq!(e.remediation(), None);
+}
+
 #[derive(buck2_error_derive::Error, Debug)]
 pub enum Error3 {
     #[error("foo")]
@@ -59,7 +135,10 @@ pub enum Error3 {
 fn test_derive_error3() {
     let e: crate::Error = Error3::VariantA.into();
     assert_eq!(e.get_category(), Some(crate::Category::User));
-    assert_eq!(e.get_error_type(), Some(crate::ErrorType::DaemonIsBusy));
+    assert_eq!(
+        e.get_error_type(),
+        Some(crate::ErrorType::DaemonIsBusy.into())
+    );
 
     let e: crate::Error = Error3::VariantB.into();
     assert_eq!(e.get_category(), Some(crate::Category::Infra));
@@ -70,6 +149,21 @@ fn test_derive_error3() {
     assert_eq!(e.get_error_type(), None);
 }
 
+#[test]
+fn test_is_user_is_infra() {
+    let e: crate::Error = Error1.into();
+    assert!(e.is_user());
+    assert!(!e.is_infra());
+
+    let e: crate::Error = Error2((), ()).into();
+    assert!(!e.is_user());
+    assert!(e.is_infra());
+
+    let e: crate::Error = Error3::VariantC.into();
+    assert!(!e.is_user());
+    assert!(!e.is_infra());
+}
+
 #[derive(buck2_error_derive::Error, Debug)]
 #[error("Generic error")]
 pub struct GenericError<G>(G);
@@ -178,17 +272,108 @@ enum ComputedOptionsError {
 fn test_computed_options() {
     let e: crate::Error = ComputedOptionsError::A.into();
     assert_eq!(e.get_category(), Some(crate::Category::User));
-    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman));
+    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
 
     let e: crate::Error = ComputedOptionsError::B(false).into();
     assert_eq!(e.get_category(), Some(crate::Category::Infra));
-    assert_eq!(e.get_error_type(), Some(crate::ErrorType::DaemonIsBusy));
+    assert_eq!(
+        e.get_error_type(),
+        Some(crate::ErrorType::DaemonIsBusy.into())
+    );
 
     let e: crate::Error = ComputedOptionsError::B(true).into();
     assert_eq!(e.get_category(), Some(crate::Category::Infra));
     assert_eq!(e.get_error_type(), None);
 }
 
+fn always_infra(_val: bool) -> crate::Category {
+    crate::Category::Infra
+}
+
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("Unused")]
+enum UnwrappedCategoryError {
+    #[buck2(category = always_infra(*_0))]
+    A(bool),
+    B,
+}
+
+#[test]
+fn test_computed_category_without_option() {
+    let e: crate::Error = UnwrappedCategoryError::A(true).into();
+    assert_eq!(e.get_category(), Some(crate::Category::Infra));
+
+    let e: crate::Error = UnwrappedCategoryError::B.into();
+    assert_eq!(e.get_category(), None);
+}
+
+fn compute_typ_from_str(s: &str) -> Option<crate::ErrorType> {
+    if s == "watchman" {
+        Some(crate::ErrorType::Watchman)
+    } else {
+        None
+    }
+}
+
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("Unused")]
+enum BorrowedFieldError {
+    #[buck2(typ = compute_typ_from_str(&_0))]
+    Tuple(String),
+    #[buck2(typ = compute_typ_from_str(name))]
+    Named { name: String },
+}
+
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("Unused")]
+#[buck2(typ = compute_typ_from_str(&self.0))]
+struct BorrowedSelfError(String);
+
+#[test]
+fn test_computed_options_borrow_fields() {
+    let e: crate::Error = BorrowedFieldError::Tuple("watchman".to_owned()).into();
+    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
+
+    let e: crate::Error = BorrowedFieldError::Tuple("other".to_owned()).into();
+    assert_eq!(e.get_error_type(), None);
+
+    let e: crate::Error = BorrowedFieldError::Named {
+        name: "watchman".to_owned(),
+    }
+    .into();
+    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
+
+    let e: crate::Error = BorrowedSelfError("watchman".to_owned()).into();
+    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
+}
+
+#[derive(buck2_error_derive::Error, Debug)]
+#[error("Unused")]
+enum CustomTypeError {
+    #[buck2(typ = custom("my_domain"))]
+    Custom,
+    #[buck2(typ = Watchman)]
+    Builtin,
+}
+
+#[test]
+fn test_custom_type() {
+    let e: crate::Error = CustomTypeError::Custom.into();
+    assert_eq!(
+        e.get_error_type(),
+        Some(crate::ErrorTypeOrCustom::Custom("my_domain"))
+    );
+    // The type survives a round trip through `anyhow`.
+    let e: crate::Error = anyhow::Error::from(e).context("context").into();
+    assert_eq!(
+        e.get_error_type(),
+        Some(crate::ErrorTypeOrCustom::Custom("my_domain"))
+    );
+
+    let e: crate::Error = CustomTypeError::Builtin.into();
+    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
+}
+
 #[test]
 fn test_root_is_applied_conditionally() {
     #[derive(buck2_error_derive::Error, Debug)]
@@ -212,10 +397,13 @@ fn test_root_is_applied_conditionally() {
     }
 
     let e: crate::Error = MaybeWatchmanError::None.into();
-    assert_eq!(e.get_error_type(), Some(crate::ErrorType::DaemonIsBusy));
+    assert_eq!(
+        e.get_error_type(),
+        Some(crate::ErrorType::DaemonIsBusy.into())
+    );
 
     let e: crate::Error = MaybeWatchmanError::Some(WatchmanError).into();
-    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman));
+    assert_eq!(e.get_error_type(), Some(crate::ErrorType::Watchman.into()));
 }
 
 #[test]
@@ -244,4 +432,16 @@ fn test_error_tags() {
     );
     let b: crate::Error = TaggedError::B.into();
     assert_eq!(&b.get_tags(), &[crate::ErrorTag::WatchmanTimeout]);
+
+    assert!(a.has_tag(crate::ErrorTag::WatchmanTimeout));
+    assert!(a.has_tag(crate::ErrorTag::StarlarkFail));
+    assert!(b.has_tag(crate::ErrorTag::WatchmanTimeout));
+    assert!(!b.has_tag(crate::ErrorTag::StarlarkFail));
+
+    assert!(b.has_any_tag(&[
+        crate::ErrorTag::StarlarkFail,
+        crate::ErrorTag::WatchmanTimeout
+    ]));
+    assert!(!b.has_any_tag(&[crate::ErrorTag::StarlarkFail]));
+    assert!(!b.has_any_tag(&[]));
 }
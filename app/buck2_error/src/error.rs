@@ -11,10 +11,13 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::sync::Arc;
 
+use ref_cast::RefCast;
+
+use crate::any::CrateAsStdError;
 use crate::context_value::ContextValue;
 use crate::format::into_anyhow_for_format;
 use crate::root::ErrorRoot;
-use crate::ErrorType;
+use crate::ErrorTypeOrCustom;
 use crate::UniqueRootId;
 
 pub type DynLateFormat = dyn Fn(&mut fmt::Formatter<'_>) -> fmt::Result + Send + Sync + 'static;
@@ -57,7 +60,8 @@ impl Error {
         crate::any::recover_crate_error(&e, source_location)
     }
 
-    fn iter_kinds<'a>(&'a self) -> impl Iterator<Item = &'a ErrorKind> {
+    /// This error, and each error it wraps, down to the root.
+    fn iter_layers(&self) -> impl Iterator<Item = &Error> {
         let mut cur = Some(self);
         std::iter::from_fn(move || {
             let out = cur?;
@@ -65,10 +69,27 @@ impl Error {
                 ErrorKind::WithContext(_, next) | ErrorKind::Emitted(_, next) => cur = Some(next),
                 ErrorKind::Root(_) => cur = None,
             };
-            Some(out.0.as_ref())
+            Some(out)
         })
     }
 
+    fn iter_kinds<'a>(&'a self) -> impl Iterator<Item = &'a ErrorKind> {
+        self.iter_layers().map(|e| e.0.as_ref())
+    }
+
+    /// The error and each of its causes, from the outermost context down to the root, like
+    /// `anyhow::Error::chain`. Each element displays (with `{}`) as just its own message. Context
+    /// which is not displayed, such as categories and tags, is skipped.
+    pub fn chain(&self) -> impl Iterator<Item = &(dyn StdError + 'static)> {
+        self.iter_layers()
+            .filter(|e| match &*e.0 {
+                ErrorKind::Root(_) => true,
+                ErrorKind::WithContext(ctx, _) => ctx.as_display().is_some(),
+                ErrorKind::Emitted(..) => false,
+            })
+            .map(|e| CrateAsStdError::ref_cast(e) as &(dyn StdError + 'static))
+    }
+
     fn root(&self) -> &ErrorRoot {
         let Some(ErrorKind::Root(r)) = self.iter_kinds().last() else {
             unreachable!()
@@ -80,6 +101,12 @@ impl Error {
         self.root().action_error()
     }
 
+    /// A link explaining how to fix this error, if its type declared one with
+    /// `#[buck2(remediation = "...")]`.
+    pub fn remediation(&self) -> Option<&str> {
+        self.root().remediation()
+    }
+
     pub(crate) fn iter_context<'a>(&'a self) -> impl Iterator<Item = &'a ContextValue> {
         self.iter_kinds().filter_map(|kind| match kind {
             ErrorKind::WithContext(ctx, _) => Some(ctx),
@@ -105,8 +132,14 @@ impl Error {
         if was_late_formatted { Some(val) } else { None }
     }
 
-    pub fn get_error_type(&self) -> Option<ErrorType> {
-        self.root().error_type()
+    /// The type of the error, which is a custom one for errors outside of Buck itself that none
+    /// of the `ErrorType`s describe, e.g. set with `#[buck2(typ = custom("my_domain"))]`.
+    pub fn get_error_type(&self) -> Option<ErrorTypeOrCustom> {
+        let root = self.root();
+        match root.error_type() {
+            Some(typ) => Some(ErrorTypeOrCustom::Known(typ)),
+            None => root.custom_error_type().map(ErrorTypeOrCustom::Custom),
+        }
     }
 
     /// Only intended to be used for debugging, helps to understand the structure of the error
@@ -136,12 +169,64 @@ impl Error {
     pub fn source_location(&self) -> Option<&str> {
         self.root().source_location()
     }
+
+    /// A hash identifying "the same error" across occurrences, e.g. to aggregate failures of
+    /// several builds. It covers the category, type, tags and source location of the error, but
+    /// deliberately not its message, which often contains paths or other details that vary between
+    /// occurrences.
+    ///
+    /// The fingerprint is stable across runs, machines and Rust versions, but may change between
+    /// versions of Buck.
+    pub fn fingerprint(&self) -> u64 {
+        // Uses blake3 rather than `DefaultHasher`, whose algorithm is unspecified. Everything is
+        // hashed by name rather than by discriminant so that reordering enums does not change
+        // fingerprints, and each field is length-prefixed so that fields can't run into each
+        // other.
+        fn field(hasher: &mut blake3::Hasher, value: Option<&str>) {
+            match value {
+                None => {
+                    hasher.update(&[0]);
+                }
+                Some(value) => {
+                    hasher.update(&[1]);
+                    hasher.update(&(value.len() as u64).to_le_bytes());
+                    hasher.update(value.as_bytes());
+                }
+            }
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        field(
+            &mut hasher,
+            self.get_category()
+                .map(|category| format!("{:?}", category))
+                .as_deref(),
+        );
+        let error_type = self.get_error_type();
+        field(
+            &mut hasher,
+            error_type
+                .and_then(ErrorTypeOrCustom::known)
+                .map(|error_type| error_type.as_str_name()),
+        );
+        field(&mut hasher, error_type.and_then(ErrorTypeOrCustom::custom));
+        let tags = self.get_tags();
+        hasher.update(&(tags.len() as u64).to_le_bytes());
+        for tag in tags {
+            field(&mut hasher, Some(tag.as_str_name()));
+        }
+        field(&mut hasher, self.source_location());
+        let hash = hasher.finalize();
+        u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
+    use crate as buck2_error;
+
     #[derive(Debug, thiserror::Error)]
     #[error("Test")]
     struct TestError;
@@ -157,6 +242,77 @@ mod tests {
         assert!(e.is_emitted().is_some());
     }
 
+    #[derive(Debug, thiserror::Error)]
+    #[error("Outer")]
+    struct OuterError(#[source] TestError);
+
+    #[test]
+    fn test_chain() {
+        let messages = |e: &crate::Error| e.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+
+        let e: crate::Error = OuterError(TestError).into();
+        assert_eq!(vec!["Outer", "Test"], messages(&e));
+
+        let e = e
+            .tag([crate::ErrorTag::Retryable])
+            .context("Failed to materialize artifacts")
+            .mark_emitted(Arc::new(|_| Ok(())));
+        assert_eq!(
+            vec!["Failed to materialize artifacts", "Outer", "Test"],
+            messages(&e)
+        );
+    }
+
+    #[derive(buck2_error_derive::Error, Debug)]
+    #[error("Failed to read `{0}`")]
+    #[buck2(user)]
+    struct ReadError(&'static str);
+
+    #[derive(buck2_error_derive::Error, Debug)]
+    #[error("Failed to read `{0}`")]
+    #[buck2(user)]
+    struct OtherReadError(&'static str);
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = |e: crate::Error| e.fingerprint();
+
+        let e1: crate::Error = ReadError("foo/bar.txt").into();
+        let e2: crate::Error = ReadError("baz.txt").into();
+        assert_eq!(e1.fingerprint(), e2.fingerprint());
+        // Context messages don't matter either.
+        assert_eq!(
+            e1.fingerprint(),
+            fingerprint(e2.clone().context("Failed to build `//:baz`"))
+        );
+
+        // But the source location, category and tags do.
+        assert_ne!(
+            e1.fingerprint(),
+            fingerprint(OtherReadError("foo/bar.txt").into())
+        );
+        assert_ne!(
+            e1.fingerprint(),
+            fingerprint(e1.clone().context(crate::Category::Infra))
+        );
+        assert_ne!(
+            e1.fingerprint(),
+            fingerprint(e1.clone().tag([crate::ErrorTag::Retryable]))
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_is_pinned() {
+        // The fingerprint must not change with the Rust version (or anything other than the
+        // error's metadata), since fingerprints are compared across runs.
+        let e: crate::Error = ReadError("foo/bar.txt").into();
+        assert_eq!(
+            Some("buck2_error/src/error.rs::ReadError"),
+            e.source_location()
+        );
+        assert_eq!(17135340256575583137, e.fingerprint());
+    }
+
     #[test]
     fn test_root_id() {
         let e1: crate::Error = TestError.into();
@@ -19,15 +19,18 @@ mod derive_tests;
 mod error;
 mod format;
 mod root;
+mod soft_error_counts;
 mod source_location;
 
 use std::error::Request;
 
 pub use context::Context;
-/// A piece of metadata to indicate whether this error is an infra or user error.
+/// A piece of metadata to indicate whether this error is an infra, user, environment or internal
+/// error.
 ///
 /// You can attach this to an error by passing it to the [`Error::context`] method. Alternatively,
-/// you can call `.user()` or `.infra()` on a [`buck2_error::Result`][`Result`].
+/// you can call `.user()`, `.infra()`, `.environment()` or `.internal()` on a
+/// [`buck2_error::Result`][`Result`].
 ///
 /// The category is fundamentally closed - the expectation is that it will not grow new variants in
 /// the future.
@@ -36,6 +39,11 @@ pub use context_value::Category;
 pub use error::DynLateFormat;
 pub use error::Error;
 pub use root::UniqueRootId;
+#[doc(hidden)]
+pub use soft_error_counts::increment_soft_error_count;
+#[doc(hidden)]
+pub use soft_error_counts::reset_soft_error_counts;
+pub use soft_error_counts::soft_error_counts;
 
 pub type Result<T> = std::result::Result<T, crate::Error>;
 
@@ -52,6 +60,39 @@ pub use buck2_data::error::ErrorTag;
 /// Unlike the [`category`](crate::Category) metadata, this type is "open" in the sense that it is
 /// expected to grow in the future. You should not match on it exhaustively.
 pub use buck2_data::error::ErrorType;
+
+/// The type of an error as returned by [`Error::get_error_type`]: either one of the
+/// [`ErrorType`]s, or a domain-specific type set with `typ = custom("...")`, for errors which none
+/// of the `ErrorType`s describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorTypeOrCustom {
+    Known(ErrorType),
+    Custom(&'static str),
+}
+
+impl ErrorTypeOrCustom {
+    /// The `ErrorType`, unless this is a custom type.
+    pub fn known(self) -> Option<ErrorType> {
+        match self {
+            ErrorTypeOrCustom::Known(typ) => Some(typ),
+            ErrorTypeOrCustom::Custom(_) => None,
+        }
+    }
+
+    /// The custom type, unless this is one of the `ErrorType`s.
+    pub fn custom(self) -> Option<&'static str> {
+        match self {
+            ErrorTypeOrCustom::Known(_) => None,
+            ErrorTypeOrCustom::Custom(typ) => Some(typ),
+        }
+    }
+}
+
+impl From<ErrorType> for ErrorTypeOrCustom {
+    fn from(typ: ErrorType) -> Self {
+        ErrorTypeOrCustom::Known(typ)
+    }
+}
 /// Generates an error impl for the type.
 ///
 /// This macro is a drop-in replacement for [`thiserror::Error`]. In the near future, all uses of
@@ -73,6 +114,37 @@ pub use buck2_data::error::ErrorType;
 /// let e = buck2_error::Error::from(MyError);
 /// assert_eq!(&format!("{}", e), "My error type");
 /// ```
+///
+/// ## Computed metadata
+///
+/// The `category`, `typ` and `tag` options accept either a variant name, like `typ = Watchman`, or
+/// an expression returning an `Option` of the corresponding type, like `typ = compute(self)`. The
+/// expression can refer to `self` as well as to the fields of the type or variant, which are bound
+/// by reference: tuple fields as `_0`, `_1`, etc. and named fields by their name. Since the
+/// bindings are references, fields can be passed to functions taking a reference without copying
+/// them. A `category` expression may also return a `Category` directly, for classifiers which
+/// always produce one.
+///
+/// ```rust
+/// # #![feature(error_generic_member_access)]
+/// fn compute_typ(path: &str) -> Option<buck2_error::ErrorType> {
+///     path.contains("watchman").then_some(buck2_error::ErrorType::Watchman)
+/// }
+///
+/// #[derive(Debug, buck2_error::Error)]
+/// #[error("Failed to read `{0}`")]
+/// #[buck2(typ = compute_typ(_0))]
+/// struct ReadError(String);
+///
+/// let e = buck2_error::Error::from(ReadError("watchman.sock".to_owned()));
+/// assert_eq!(e.get_error_type(), Some(buck2_error::ErrorType::Watchman.into()));
+/// ```
+///
+/// ## Custom types
+///
+/// Errors of domains which none of the `ErrorType`s describe (e.g. those of third-party rules) can
+/// use `typ = custom("my_domain")` instead. `Error::get_error_type` then returns
+/// `ErrorTypeOrCustom::Custom("my_domain")`.
 #[doc(inline)]
 pub use buck2_error_derive::Error;
 
@@ -91,18 +163,27 @@ use crate::any::ProvidableMetadata;
 ///
 /// The `source_file` should just be `std::file!()`; the `source_location_extra` should be the type
 /// - and possibly variant - name, formatted as either `Type` or `Type::Variant`.
+///
+/// The `custom_typ` is a domain-specific error type, for errors which none of the `ErrorType`s
+/// describe, see [`ErrorTypeOrCustom`].
+///
+/// The `remediation` is a link explaining how to fix the error, see [`Error::remediation`].
 pub fn provide_metadata<'a, 'b>(
     request: &'b mut Request<'a>,
     category: Option<crate::Category>,
     typ: Option<crate::ErrorType>,
+    custom_typ: Option<&'static str>,
     tags: &[Option<crate::ErrorTag>],
     source_file: &'static str,
     source_location_extra: Option<&'static str>,
     action_error: Option<buck2_data::ActionError>,
+    remediation: Option<&'static str>,
 ) {
     let metadata = ProvidableMetadata {
         typ,
+        custom_typ,
         action_error,
+        remediation,
         category,
         tags: tags.iter().copied().flatten().collect(),
         source_file,
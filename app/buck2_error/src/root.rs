@@ -36,24 +36,30 @@ pub(crate) struct ErrorRoot {
     id: UniqueRootId,
     description: String,
     error_type: Option<ErrorType>,
+    custom_error_type: Option<&'static str>,
     source_location: Option<String>,
     action_error: Option<buck2_data::ActionError>,
+    remediation: Option<&'static str>,
 }
 
 impl ErrorRoot {
     pub(crate) fn new(
         description: String,
         error_type: Option<ErrorType>,
+        custom_error_type: Option<&'static str>,
         source_location: Option<String>,
         action_error: Option<buck2_data::ActionError>,
+        remediation: Option<&'static str>,
     ) -> Self {
         let id = UniqueRootId(NEXT_ROOT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
         Self {
             id,
             description,
             error_type,
+            custom_error_type,
             source_location,
             action_error,
+            remediation,
         }
     }
 
@@ -75,6 +81,10 @@ impl ErrorRoot {
         self.error_type
     }
 
+    pub(crate) fn custom_error_type(&self) -> Option<&'static str> {
+        self.custom_error_type
+    }
+
     pub(crate) fn source_location(&self) -> Option<&str> {
         self.source_location.as_deref()
     }
@@ -82,6 +92,10 @@ impl ErrorRoot {
     pub fn action_error(&self) -> Option<&buck2_data::ActionError> {
         self.action_error.as_ref()
     }
+
+    pub(crate) fn remediation(&self) -> Option<&'static str> {
+        self.remediation
+    }
 }
 
 impl fmt::Debug for ErrorRoot {
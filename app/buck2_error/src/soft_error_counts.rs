@@ -0,0 +1,44 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+static SOFT_ERROR_COUNTS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// How many times soft errors (see `buck2_core::soft_error!`) fired, by category, since the
+/// counts were last reset (which the daemon does at the start of every command).
+///
+/// Unlike the reporting of soft errors, this is not capped: every soft error counts.
+pub fn soft_error_counts() -> HashMap<String, u64> {
+    SOFT_ERROR_COUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(category, count)| (category.clone(), *count))
+        .collect()
+}
+
+// Hidden because an implementation detail of `soft_error!`.
+#[doc(hidden)]
+pub fn increment_soft_error_count(category: &str) {
+    let mut counts = SOFT_ERROR_COUNTS.lock().unwrap();
+    match counts.get_mut(category) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(category.to_owned(), 1);
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn reset_soft_error_counts() {
+    SOFT_ERROR_COUNTS.lock().unwrap().clear();
+}
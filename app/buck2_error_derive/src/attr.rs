@@ -69,7 +69,9 @@ impl Parse for OptionStyle {
 enum MacroOption {
     Category(OptionStyle),
     Typ(OptionStyle),
+    CustomTyp(LitStr),
     Tag(OptionStyle),
+    Remediation(LitStr),
 }
 
 impl Parse for MacroOption {
@@ -81,15 +83,36 @@ impl Parse for MacroOption {
         } else if name == "infra" {
             let ident = syn::Ident::new("Infra", name.span());
             Ok(MacroOption::Category(OptionStyle::Explicit(ident)))
+        } else if name == "environment" {
+            let ident = syn::Ident::new("Environment", name.span());
+            Ok(MacroOption::Category(OptionStyle::Explicit(ident)))
+        } else if name == "internal" {
+            let ident = syn::Ident::new("Internal", name.span());
+            Ok(MacroOption::Category(OptionStyle::Explicit(ident)))
         } else if name == "category" {
             let _eq: Token![=] = input.parse()?;
             Ok(MacroOption::Category(input.parse()?))
         } else if name == "typ" {
             let _eq: Token![=] = input.parse()?;
+            // `typ = custom("...")`, for types that aren't an `ErrorType`.
+            let fork = input.fork();
+            if fork
+                .parse::<syn::Ident>()
+                .is_ok_and(|ident| ident == "custom")
+                && fork.peek(token::Paren)
+            {
+                let _custom: syn::Ident = input.parse()?;
+                let content;
+                parenthesized!(content in input);
+                return Ok(MacroOption::CustomTyp(content.parse()?));
+            }
             Ok(MacroOption::Typ(input.parse()?))
         } else if name == "tag" {
             let _eq: Token![=] = input.parse()?;
             Ok(MacroOption::Tag(input.parse()?))
+        } else if name == "remediation" {
+            let _eq: Token![=] = input.parse()?;
+            Ok(MacroOption::Remediation(input.parse()?))
         } else {
             Err(syn::Error::new_spanned(name, "expected option"))
         }
@@ -102,7 +125,11 @@ pub struct Attrs<'a> {
     pub transparent: Option<Transparent<'a>>,
     pub category: Option<OptionStyle>,
     pub typ: Option<OptionStyle>,
+    /// Set by `typ = custom("...")`, instead of `typ`.
+    pub custom_typ: Option<LitStr>,
     pub tags: Vec<OptionStyle>,
+    /// A link explaining how to fix the error.
+    pub remediation: Option<LitStr>,
 }
 
 #[derive(Clone)]
@@ -139,7 +166,9 @@ pub fn get(input: &[Attribute]) -> Result<Attrs> {
         transparent: None,
         category: None,
         typ: None,
+        custom_typ: None,
         tags: Vec::new(),
+        remediation: None,
     };
 
     for attr in input {
@@ -164,14 +193,29 @@ pub fn get(input: &[Attribute]) -> Result<Attrs> {
                         attrs.category = Some(style);
                     }
                     MacroOption::Typ(style) => {
-                        if attrs.typ.is_some() {
+                        if attrs.typ.is_some() || attrs.custom_typ.is_some() {
                             return Err(syn::Error::new(style.span(), "duplicate error type"));
                         }
                         attrs.typ = Some(style);
                     }
+                    MacroOption::CustomTyp(custom_typ) => {
+                        if attrs.typ.is_some() || attrs.custom_typ.is_some() {
+                            return Err(syn::Error::new(custom_typ.span(), "duplicate error type"));
+                        }
+                        attrs.custom_typ = Some(custom_typ);
+                    }
                     MacroOption::Tag(style) => {
                         attrs.tags.push(style);
                     }
+                    MacroOption::Remediation(remediation) => {
+                        if attrs.remediation.is_some() {
+                            return Err(syn::Error::new(
+                                remediation.span(),
+                                "duplicate remediation",
+                            ));
+                        }
+                        attrs.remediation = Some(remediation);
+                    }
                 }
             }
         }
@@ -83,7 +83,7 @@ fn impl_struct(input: Struct) -> TokenStream {
     let pat = fields_pat(&input.fields);
     let provide_method = quote! {
         fn provide<'__macro>(&'__macro self, __request: &mut std::error::Request<'__macro>) {
-            #[allow(unused_variables, deprecated)]
+            #[allow(unused_variables, deprecated, clippy::used_underscore_binding)]
             let Self #pat = self;
             #provide_body
         }
@@ -161,7 +161,13 @@ fn impl_enum(mut input: Enum) -> TokenStream {
         if input.attrs.typ.is_some() {
             variant.attrs.typ = input.attrs.typ.clone();
         }
+        if input.attrs.custom_typ.is_some() {
+            variant.attrs.custom_typ = input.attrs.custom_typ.clone();
+        }
         variant.attrs.tags.extend(input.attrs.tags.iter().cloned());
+        if input.attrs.remediation.is_some() {
+            variant.attrs.remediation = input.attrs.remediation.clone();
+        }
     }
 
     let source_method = if input.has_source() {
@@ -213,7 +219,7 @@ fn impl_enum(mut input: Enum) -> TokenStream {
         let ident = &variant.ident;
         let pat = fields_pat(&variant.fields);
         quote! {
-            #[allow(unused_variables, deprecated)]
+            #[allow(unused_variables, deprecated, clippy::used_underscore_binding)]
             #ty::#ident #pat => {
                 #content
             },
@@ -316,7 +322,10 @@ fn gen_provide_contents(
         Some(OptionStyle::Explicit(cat)) => quote::quote! {
             core::option::Option::Some(buck2_error::Category::#cat)
         },
-        Some(OptionStyle::ByExpr(e)) => e.to_token_stream(),
+        // Classifiers which always return a category don't need to wrap it in `Some`.
+        Some(OptionStyle::ByExpr(e)) => quote::quote! {
+            core::convert::Into::<core::option::Option<buck2_error::Category>>::into(#e)
+        },
         None => quote::quote! {
             core::option::Option::None
         },
@@ -330,6 +339,14 @@ fn gen_provide_contents(
             core::option::Option::None
         },
     };
+    let custom_typ = match &attrs.custom_typ {
+        Some(custom_typ) => quote::quote! {
+            core::option::Option::Some(#custom_typ)
+        },
+        None => quote::quote! {
+            core::option::Option::None
+        },
+    };
     let tags = attrs.tags.iter().map(|tag| match tag {
         OptionStyle::Explicit(tag) => quote::quote! {
             core::option::Option::Some(buck2_error::ErrorTag::#tag)
@@ -337,15 +354,26 @@ fn gen_provide_contents(
         OptionStyle::ByExpr(e) => e.to_token_stream(),
     });
 
+    let remediation = match &attrs.remediation {
+        Some(remediation) => quote::quote! {
+            core::option::Option::Some(#remediation)
+        },
+        None => quote::quote! {
+            core::option::Option::None
+        },
+    };
+
     let metadata = quote! {
         buck2_error::provide_metadata(
             __request,
             #category,
             #typ,
+            #custom_typ,
             &[#(#tags,)*],
             core::file!(),
             core::option::Option::Some(#source_location_extra),
             core::option::Option::None,
+            #remediation,
         );
     };
 
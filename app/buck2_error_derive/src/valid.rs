@@ -134,10 +134,20 @@ fn check_non_field_attrs(attrs: &Attrs, parsed_earlier: Option<&Attrs>) -> Resul
         {
             return Err(Error::new(category.span(), "already specified on enum"));
         }
-        if let Some(typ) = &attrs.typ
-            && parsed_earlier.typ.is_some()
+        let typ_span = match (&attrs.typ, &attrs.custom_typ) {
+            (Some(typ), _) => Some(typ.span()),
+            (None, Some(custom_typ)) => Some(custom_typ.span()),
+            (None, None) => None,
+        };
+        if let Some(span) = typ_span
+            && (parsed_earlier.typ.is_some() || parsed_earlier.custom_typ.is_some())
+        {
+            return Err(Error::new(span, "already specified on enum"));
+        }
+        if let Some(remediation) = &attrs.remediation
+            && parsed_earlier.remediation.is_some()
         {
-            return Err(Error::new(typ.span(), "already specified on enum"));
+            return Err(Error::new(remediation.span(), "already specified on enum"));
         }
     }
     Ok(())
@@ -167,9 +177,15 @@ fn check_field_attrs(fields: &[Field]) -> Result<()> {
         } else {
             None
         };
-        if let Some(style) = style {
+        let span = match (style, &field.attrs.custom_typ, &field.attrs.remediation) {
+            (Some(style), _, _) => Some(style.span()),
+            (None, Some(custom_typ), _) => Some(custom_typ.span()),
+            (None, None, Some(remediation)) => Some(remediation.span()),
+            (None, None, None) => None,
+        };
+        if let Some(span) = span {
             return Err(Error::new(
-                style.span(),
+                span,
                 "not expected here; the #[buck2(...)] attribute belongs on top of a struct or an enum variant",
             ));
         }
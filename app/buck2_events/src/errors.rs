@@ -12,8 +12,13 @@ pub fn create_error_report(err: &buck2_error::Error) -> buck2_data::ErrorReport
     let category = err.get_category().map(|c| match c {
         buck2_error::Category::User => buck2_data::error::ErrorCategory::User,
         buck2_error::Category::Infra => buck2_data::error::ErrorCategory::Infra,
+        buck2_error::Category::Environment => buck2_data::error::ErrorCategory::Environment,
+        buck2_error::Category::Internal => buck2_data::error::ErrorCategory::Internal,
     });
-    let typ = err.get_error_type().map(|c| c as i32);
+    let error_type = err.get_error_type();
+    let typ = error_type
+        .and_then(buck2_error::ErrorTypeOrCustom::known)
+        .map(|c| c as i32);
 
     let (message, telemetry_message) = if let Some(f) = err.is_emitted() {
         (format!("{:?}", f), Some(format!("{:?}", err)))
@@ -22,6 +27,9 @@ pub fn create_error_report(err: &buck2_error::Error) -> buck2_data::ErrorReport
     };
 
     let source_location = err.source_location().map(ToOwned::to_owned);
+    let custom_type = error_type
+        .and_then(buck2_error::ErrorTypeOrCustom::custom)
+        .map(ToOwned::to_owned);
 
     buck2_data::ErrorReport {
         category: category.map(|c| c as i32),
@@ -30,5 +38,25 @@ pub fn create_error_report(err: &buck2_error::Error) -> buck2_data::ErrorReport
         telemetry_message,
         source_location,
         tags: Vec::new(),
+        custom_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(buck2_error::Error, Debug)]
+    #[error("Timed out")]
+    #[buck2(typ = custom("RemoteTimeout"))]
+    struct TimeoutError;
+
+    #[test]
+    fn test_custom_type() {
+        let report = create_error_report(&TimeoutError.into());
+        assert_eq!(Some("RemoteTimeout"), report.custom_type.as_deref());
+
+        let report = create_error_report(&anyhow::anyhow!("plain").into());
+        assert_eq!(None, report.custom_type);
     }
 }
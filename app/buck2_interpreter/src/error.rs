@@ -73,10 +73,12 @@ impl std::error::Error for BuckStarlarkError {
             request,
             category,
             None, /* typ */
+            None, /* custom typ */
             tags,
             std::file!(),
             Some(variant_name),
             None, /* action error */
+            None, /* remediation */
         );
     }
 }
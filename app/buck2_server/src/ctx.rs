@@ -166,6 +166,8 @@ pub struct ServerCommandContext<'a> {
     pub oncall: Option<String>,
     /// The client ID, if one was provided via --client-metadata.
     pub client_id_from_client_metadata: Option<String>,
+    /// The invocation label, if one was provided via --client-metadata.
+    invocation_label: Option<String>,
 
     host_platform_override: HostPlatformOverride,
     host_arch_override: HostArchOverride,
@@ -293,6 +295,12 @@ impl<'a> ServerCommandContext<'a> {
             .find(|m| m.key == "id")
             .map(|m| m.value.clone());
 
+        let invocation_label = client_context
+            .client_metadata
+            .iter()
+            .find(|m| m.key == "invocation_label")
+            .map(|m| m.value.clone());
+
         let heartbeat_guard_handle =
             HeartbeatGuard::new(base_context.events.dupe(), snapshot_collector);
 
@@ -317,6 +325,7 @@ impl<'a> ServerCommandContext<'a> {
             host_xcode_version_override: client_context.host_xcode_version.clone(),
             oncall,
             client_id_from_client_metadata,
+            invocation_label,
             _re_connection_handle: re_connection_handle,
             starlark_profiler_instrumentation_override,
             buck_out_dir: paths.buck_out_dir(),
@@ -764,6 +773,10 @@ impl<'a> ServerCommandContextTrait for ServerCommandContext<'a> {
         &self.isolation_prefix
     }
 
+    fn invocation_label(&self) -> Option<&str> {
+        self.invocation_label.as_deref()
+    }
+
     fn project_root(&self) -> &ProjectRoot {
         &self.base_context.project_root
     }
@@ -194,10 +194,12 @@ impl<'a> BuildReportCollector<'a> {
             .configured
             .iter()
             .group_by(|x| x.0.target().unconfigured().dupe());
-        let errors_by_unconfigured = build_result
-            .other_errors
-            .iter()
-            .filter_map(|(l, e)| Some((l.as_ref()?.target().dupe(), e)));
+        let errors_by_unconfigured = build_result.other_errors.iter().filter_map(|(l, e)| {
+            Some((
+                l.as_ref()?.target().dupe(),
+                e.iter().map(|e| e.error.dupe()).collect::<Vec<_>>(),
+            ))
+        });
         for i in Itertools::merge_join_by(
             IntoIterator::into_iter(results_by_unconfigured),
             errors_by_unconfigured,
@@ -205,14 +207,14 @@ impl<'a> BuildReportCollector<'a> {
         ) {
             let (label, results, errors) = match i {
                 EitherOrBoth::Both((label, results), (_, errors)) => {
-                    (label, Either::Left(results), &**errors)
+                    (label, Either::Left(results), errors)
                 }
-                EitherOrBoth::Left((label, results)) => (label, Either::Left(results), &[][..]),
+                EitherOrBoth::Left((label, results)) => (label, Either::Left(results), Vec::new()),
                 EitherOrBoth::Right((label, errors)) => {
-                    (label, Either::Right(std::iter::empty()), &**errors)
+                    (label, Either::Right(std::iter::empty()), errors)
                 }
             };
-            let entry = this.collect_results_for_unconfigured(results, errors);
+            let entry = this.collect_results_for_unconfigured(results, &errors);
             entries.insert(EntryLabel::Target(label), entry);
         }
 
@@ -27,8 +27,10 @@ use buck2_build_api::build::ConfiguredBuildEvent;
 use buck2_build_api::build::ConvertMaterializationContext;
 use buck2_build_api::build::HasCreateUnhashedSymlinkLock;
 use buck2_build_api::build::MaterializationContext;
+use buck2_build_api::build::MessageSink;
 use buck2_build_api::build::ProviderArtifacts;
 use buck2_build_api::build::ProvidersToBuild;
+use buck2_build_api::build::WantConfiguredGraphSize;
 use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_cli_proto::build_request::build_providers::Action as BuildProviderAction;
 use buck2_cli_proto::build_request::BuildProviders;
@@ -453,6 +455,7 @@ fn build_targets_in_universe<'a>(
     want_configured_graph_size: bool,
 ) -> impl Stream<Item = ConfiguredBuildEvent> + Unpin + 'a {
     let providers_to_build = build_providers_to_providers_to_build(&build_providers);
+    let analysis_only = is_analysis_only(&build_providers);
     let provider_labels = universe.get_provider_labels(&spec);
     provider_labels
         .into_iter()
@@ -466,7 +469,21 @@ fn build_targets_in_universe<'a>(
                     &providers_to_build,
                     build::BuildConfiguredLabelOptions {
                         skippable: false,
-                        want_configured_graph_size,
+                        want_configured_graph_size: WantConfiguredGraphSize::for_all(
+                            want_configured_graph_size,
+                        ),
+                        configured_graph_size_max_depth: None,
+                        request_id: None,
+                        rule_type_filter: None,
+                        output_path_filter: None,
+                        register_as_top_level: true,
+                        max_concurrent_materializations: None,
+                        incompatible_message_sink: MessageSink::Console,
+                        path_separator: None,
+                        resolve_only: false,
+                        message_sink: None,
+                        cancellation: None,
+                        analysis_only,
                     },
                 )
                 .await
@@ -513,25 +530,33 @@ struct TargetBuildSpec {
     // the target platform).
     skippable: bool,
     want_configured_graph_size: bool,
+    analysis_only: bool,
 }
 
 fn build_providers_to_providers_to_build(build_providers: &BuildProviders) -> ProvidersToBuild {
-    let mut providers_to_build = ProvidersToBuild::default();
+    let mut providers_to_build = ProvidersToBuild::builder();
 
     if build_providers.default_info != BuildProviderAction::Skip as i32 {
-        providers_to_build.default = true;
-        providers_to_build.default_other = true;
+        providers_to_build = providers_to_build.default().default_other();
     }
 
     if build_providers.test_info != BuildProviderAction::Skip as i32 {
-        providers_to_build.tests = true;
+        providers_to_build = providers_to_build.tests();
     }
 
     if build_providers.run_info != BuildProviderAction::Skip as i32 {
-        providers_to_build.run = true;
+        providers_to_build = providers_to_build.run();
     }
 
-    providers_to_build
+    providers_to_build.build()
+}
+
+/// Whether the user skipped every provider category, e.g. `--skip-default-info --skip-run-info`,
+/// so that targets are only analyzed.
+fn is_analysis_only(build_providers: &BuildProviders) -> bool {
+    build_providers.default_info == BuildProviderAction::Skip as i32
+        && build_providers.test_info == BuildProviderAction::Skip as i32
+        && build_providers.run_info == BuildProviderAction::Skip as i32
 }
 
 async fn build_targets_for_spec<'a>(
@@ -601,6 +626,7 @@ async fn build_targets_for_spec<'a>(
         (None, _) => futures::stream::empty().right_stream(),
     };
 
+    let analysis_only = is_analysis_only(&build_providers);
     let todo_targets: Vec<TargetBuildSpec> = targets
         .into_iter()
         .map(|((_target_name, extra), target)| TargetBuildSpec {
@@ -609,6 +635,7 @@ async fn build_targets_for_spec<'a>(
             global_target_platform: global_target_platform.dupe(),
             skippable,
             want_configured_graph_size,
+            analysis_only,
         })
         .collect();
 
@@ -662,7 +689,21 @@ async fn build_target<'a>(
         providers_to_build,
         build::BuildConfiguredLabelOptions {
             skippable: spec.skippable,
-            want_configured_graph_size: spec.want_configured_graph_size,
+            want_configured_graph_size: WantConfiguredGraphSize::for_all(
+                spec.want_configured_graph_size,
+            ),
+            configured_graph_size_max_depth: None,
+            request_id: None,
+            rule_type_filter: None,
+            output_path_filter: None,
+            register_as_top_level: true,
+            max_concurrent_materializations: None,
+            incompatible_message_sink: MessageSink::Console,
+            path_separator: None,
+            resolve_only: false,
+            message_sink: None,
+            cancellation: None,
+            analysis_only: spec.analysis_only,
         },
     )
     .await
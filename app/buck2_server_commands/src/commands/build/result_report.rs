@@ -64,7 +64,13 @@ impl<'a> ResultReporter<'a> {
 
         let mut non_action_errors = vec![];
         let mut action_errors = vec![];
-        non_action_errors.extend(build_result.other_errors.values().flatten().cloned());
+        non_action_errors.extend(
+            build_result
+                .other_errors
+                .values()
+                .flatten()
+                .map(|e| e.error.dupe()),
+        );
 
         for (k, v) in &build_result.configured {
             // We omit skipped targets here.
@@ -109,6 +115,7 @@ impl<'a> ResultReporter<'a> {
                 let ProviderArtifacts {
                     values,
                     provider_type,
+                    ..
                 } = output;
 
                 if !self.options.return_default_other_outputs
@@ -1168,7 +1168,7 @@ mod tests {
         let fut3_error: buck2_error::Error = fut3_result.unwrap_err().into();
         assert_eq!(
             fut3_error.get_error_type(),
-            Some(buck2_error::ErrorType::DaemonIsBusy)
+            Some(buck2_error::ErrorType::DaemonIsBusy.into())
         );
 
         Ok(())
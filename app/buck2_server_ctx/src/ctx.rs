@@ -15,6 +15,7 @@ use async_trait::async_trait;
 use buck2_build_signals::BuildSignalsContext;
 use buck2_build_signals::DeferredBuildSignals;
 use buck2_build_signals::HasCriticalPathBackend;
+use buck2_core::buck2_env;
 use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
@@ -47,6 +48,10 @@ pub trait ServerCommandContextTrait: Send + Sync {
 
     fn isolation_prefix(&self) -> &FileName;
 
+    /// The label to attach to this invocation's critical path, if one was provided via
+    /// `--client-metadata invocation_label=...`.
+    fn invocation_label(&self) -> Option<&str>;
+
     fn project_root(&self) -> &ProjectRoot;
 
     fn materializer(&self) -> Arc<dyn Materializer>;
@@ -136,6 +141,9 @@ impl ServerCommandDiceContext for dyn ServerCommandContextTrait + '_ {
             build_signals,
         } = self.dice_accessor(PrivateStruct(())).await?;
 
+        let include_transitive_set_projections =
+            buck2_env!("BUCK2_CRITICAL_PATH_INCLUDE_TRANSITIVE_SET_PROJECTIONS", bool)?;
+
         let events = self.events().dupe();
         events
             .span_async(DiceCriticalSectionStart {}, async move {
@@ -176,6 +184,10 @@ impl ServerCommandDiceContext for dyn ServerCommandContextTrait + '_ {
                                                     isolation_prefix: self
                                                         .isolation_prefix()
                                                         .to_owned(),
+                                                    invocation_label: self
+                                                        .invocation_label()
+                                                        .map(ToOwned::to_owned),
+                                                    include_transitive_set_projections,
                                                 },
                                                 || exec(self, dice),
                                             )